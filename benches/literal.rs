@@ -0,0 +1,32 @@
+//! Compares host name lookups for IP literals and actual host names.
+//!
+//! Literals are answered without setting up a reactor, so they should be
+//! several orders of magnitude faster than even a lookup of `localhost`
+//! which is answered from the hosts file.
+//!
+//! Run with `cargo bench --bench literal`.
+
+extern crate netdb;
+
+use std::time::{Duration, Instant};
+use netdb::hosts::get_host_by_name;
+
+fn bench(name: &str, rounds: u32) -> Duration {
+    let start = Instant::now();
+    for _ in 0..rounds {
+        let _ = get_host_by_name(name);
+    }
+    start.elapsed() / rounds
+}
+
+fn report(label: &str, per_lookup: Duration) {
+    println!("{:>10}: {:>10} ns/lookup", label,
+             per_lookup.as_secs() * 1_000_000_000
+                 + per_lookup.subsec_nanos() as u64);
+}
+
+fn main() {
+    report("ipv4", bench("192.0.2.1", 100_000));
+    report("ipv6", bench("2001:db8::1", 100_000));
+    report("localhost", bench("localhost", 100));
+}
@@ -1,34 +1,41 @@
 extern crate netdb;
 
 use std::env;
-use netdb::hosts::get_host_by_name;
+use netdb::hosts::get_hosts;
 
 fn main() {
     let mut args = env::args();
     let cmd = args.next().unwrap();
-    let name = match args.next() {
-        None => {
-            println!("Usage: {} <hostname>", cmd);
+    let names = args.collect::<Vec<_>>().join(" ");
+    if names.is_empty() {
+        println!("Usage: {} <hostname> ...", cmd);
+        return;
+    }
+
+    let results = match get_hosts(&names) {
+        Ok(results) => results,
+        Err(err) => {
+            println!("Error: {:?}", err);
             return;
         }
-        Some(name) => name
     };
-
-    match get_host_by_name(&name) {
-        Ok(Some(ent)) => {
-            println!("{}", ent.name());
-            if !ent.aliases().is_empty() {
-                println!("  Aliases:");
-                for name in ent.aliases() {
-                    println!("     {}", name);
+    for (name, res) in results {
+        match res {
+            Ok(Some(ent)) => {
+                println!("{}", ent.name());
+                if !ent.aliases().is_empty() {
+                    println!("  Aliases:");
+                    for name in ent.aliases() {
+                        println!("     {}", name);
+                    }
+                }
+                println!("  Addresses:");
+                for addr in ent.addrs() {
+                    println!("     {}", addr);
                 }
             }
-            println!("  Addresses:");
-            for addr in ent.addrs() {
-                println!("     {}", addr);
-            }
+            Ok(None) => println!("{}: Not found.", name),
+            Err(err) => println!("{}: Error: {:?}", name, err),
         }
-        Ok(None) => println!("Not found."),
-        Err(err) => println!("Error: {:?}", err),
     }
 }
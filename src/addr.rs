@@ -0,0 +1,424 @@
+//! Queries combining several databases.
+//!
+//! The functions in this module combine information from several of the
+//! databases of this crate to produce the values applications actually
+//! need, such as socket addresses to connect to.
+
+use std::{io, mem};
+use std::collections::VecDeque;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+use futures::{Async, Future, Poll};
+use tokio_core::net::{TcpStream, TcpStreamNew};
+use tokio_core::reactor;
+use ::dns::already_resolved;
+use ::hosts::{self, HostByName};
+use ::services::ServicesDb;
+
+
+//============ High-level API ================================================
+
+/// Returns the socket addresses for a service on a host.
+///
+/// The host is looked up via the hosts database exactly as by
+/// `hosts::get_host_by_name()`. The service is either a port number or a
+/// name that is looked up in the services database for the transport
+/// protocol `proto`. The protocol is given either by name, such as `"tcp"`
+/// or `"udp"`, or by its IANA protocol number, such as `"6"` or `"17"`,
+/// like the `ai_protocol` hint of `getaddrinfo()`. Since there is no
+/// protocols database yet, only the numbers of TCP, UDP, DCCP, and SCTP
+/// are known. Other numbers result in an error of kind
+/// `io::ErrorKind::NotFound`.
+///
+/// The function waits for all necessary IO to resolve. Upon success, it
+/// returns a socket address for each address of the host. If the host
+/// doesn’t exist, the list is empty. If the service doesn’t exist, an
+/// error of kind `io::ErrorKind::NotFound` is returned.
+pub fn get_service_addrs(host: &str, service: &str, proto: &str)
+                         -> Result<Vec<SocketAddr>, io::Error> {
+    let mut core = reactor::Core::new()?;
+    let handle = core.handle();
+    core.run(poll_service_addrs(host, service, proto, &handle))
+}
+
+/// Returns the socket addresses for a service on a host.
+///
+/// This is the asynchronous version of `get_service_addrs()`. The function
+/// returns a future that performs all necessary IO via the Tokio reactor
+/// given by `reactor`.
+pub fn poll_service_addrs(host: &str, service: &str, proto: &str,
+                          reactor: &reactor::Handle) -> ServiceAddrs {
+    ServiceAddrs::new(host, service, proto, reactor)
+}
+
+
+//------------ ServiceAddrs --------------------------------------------------
+
+/// The future returned by `poll_service_addrs()`.
+///
+/// Resolves into a list of socket addresses for the service.
+pub struct ServiceAddrs(Result<(u16, HostByName), Option<io::Error>>);
+
+impl ServiceAddrs {
+    pub fn new(host: &str, service: &str, proto: &str,
+               reactor: &reactor::Handle) -> Self {
+        ServiceAddrs(match service_port(service, proto) {
+            Ok(port) => Ok((port, hosts::poll_host_by_name(host, reactor))),
+            Err(err) => Err(Some(err))
+        })
+    }
+}
+
+impl Future for ServiceAddrs {
+    type Item = Vec<SocketAddr>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.0 {
+            Ok((port, ref mut lookup)) => {
+                let ent = match try_ready!(lookup.poll()) {
+                    Some(ent) => ent,
+                    None => return Ok(Async::Ready(Vec::new()))
+                };
+                Ok(Async::Ready(ent.addrs().iter().map(|addr| {
+                    SocketAddr::new(*addr, port)
+                }).collect()))
+            }
+            Err(ref mut err) => {
+                match mem::replace(err, None) {
+                    Some(err) => Err(err),
+                    None => Err(already_resolved()),
+                }
+            }
+        }
+    }
+}
+
+/// Returns the port for a service given by number or name.
+fn service_port(service: &str, proto: &str) -> Result<u16, io::Error> {
+    if let Ok(port) = u16::from_str(service) {
+        return Ok(port)
+    }
+    service_port_in(&ServicesDb::system()?, service, proto)
+}
+
+/// Returns the port for a service name in the services table `db`.
+///
+/// The protocol is given by name or number.
+fn service_port_in(db: &ServicesDb, service: &str, proto: &str)
+                   -> Result<u16, io::Error> {
+    if let Ok(port) = u16::from_str(service) {
+        return Ok(port)
+    }
+    let proto_name = proto_name(proto)?;
+    match db.lookup_name(service, Some(proto_name)) {
+        Some(ent) => Ok(ent.port()),
+        None => {
+            Err(io::Error::new(io::ErrorKind::NotFound,
+                               format!("unknown service {}/{}",
+                                       service, proto)))
+        }
+    }
+}
+
+/// Returns the name of a transport protocol given by name or number.
+///
+/// Names are returned as is. Numbers are IANA protocol numbers. Only those
+/// of the transport protocols that appear in services files are known.
+fn proto_name(proto: &str) -> Result<&str, io::Error> {
+    let number = match u8::from_str(proto) {
+        Ok(number) => number,
+        Err(_) => return Ok(proto)
+    };
+    match number {
+        6 => Ok("tcp"),
+        17 => Ok("udp"),
+        33 => Ok("dccp"),
+        132 => Ok("sctp"),
+        _ => {
+            Err(io::Error::new(io::ErrorKind::NotFound,
+                               format!("unknown protocol {}", proto)))
+        }
+    }
+}
+
+
+//============ Connecting ====================================================
+
+/// The delay in milliseconds before starting the next connection attempt.
+///
+/// This is the default Connection Attempt Delay of RFC 8305.
+const CONNECTION_ATTEMPT_DELAY: u64 = 250;
+
+/// Connects to a port on a host using Happy Eyeballs.
+///
+/// The host is looked up via the hosts database exactly as by
+/// `hosts::poll_host_by_name()`. Connections to the addresses of the host
+/// are then attempted following RFC 8305: the addresses are reordered so
+/// that the address families alternate, starting with the family of the
+/// first address. A new attempt is started every 250 milliseconds or as
+/// soon as the previous attempt failed. The first connection established
+/// is used and all other attempts are abandoned.
+///
+/// The returned future resolves into the connected stream. If all
+/// attempts fail, it resolves into the error of the last attempt. If the
+/// host has no addresses, the error is of kind `io::ErrorKind::NotFound`.
+pub fn connect(host: &str, port: u16, reactor: &reactor::Handle) -> Connect {
+    Connect::new(host, port, reactor)
+}
+
+
+//------------ Connect -------------------------------------------------------
+
+/// The future returned by `connect()`.
+///
+/// Resolves into a TCP stream connected to one of the host’s addresses.
+pub struct Connect(ConnectState);
+
+enum ConnectState {
+    Resolving(HostByName, u16, reactor::Handle),
+    Connecting(Attempts),
+}
+
+impl Connect {
+    pub fn new(host: &str, port: u16, reactor: &reactor::Handle) -> Self {
+        Connect(ConnectState::Resolving(
+            hosts::poll_host_by_name(host, reactor), port, reactor.clone()
+        ))
+    }
+
+    /// Creates a future connecting to one of the given addresses.
+    ///
+    /// The connection attempts are made as described with `connect()`.
+    pub fn from_addrs(addrs: &[IpAddr], port: u16,
+                      reactor: &reactor::Handle) -> Self {
+        Connect(ConnectState::Connecting(Attempts::new(addrs, port, reactor)))
+    }
+}
+
+impl Future for Connect {
+    type Item = TcpStream;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let attempts = match self.0 {
+            ConnectState::Resolving(ref mut lookup, port, ref reactor) => {
+                let addrs = match try_ready!(lookup.poll()) {
+                    Some(ent) => ent.addrs().to_vec(),
+                    None => Vec::new(),
+                };
+                Attempts::new(&addrs, port, reactor)
+            }
+            ConnectState::Connecting(ref mut attempts) => {
+                return attempts.poll()
+            }
+        };
+        self.0 = ConnectState::Connecting(attempts);
+        self.poll()
+    }
+}
+
+
+//------------ Attempts ------------------------------------------------------
+
+/// The connection attempts to a list of addresses.
+struct Attempts {
+    /// The addresses not yet attempted in order.
+    addrs: VecDeque<SocketAddr>,
+
+    /// The attempts in progress.
+    pending: Vec<TcpStreamNew>,
+
+    /// The timer for starting the next attempt.
+    delay: Option<reactor::Timeout>,
+
+    /// Whether the first attempt has been started.
+    started: bool,
+
+    /// The error of the last failed attempt.
+    err: Option<io::Error>,
+
+    reactor: reactor::Handle,
+}
+
+impl Attempts {
+    fn new(addrs: &[IpAddr], port: u16, reactor: &reactor::Handle) -> Self {
+        Attempts {
+            addrs: interleave(addrs, port),
+            pending: Vec::new(),
+            delay: None,
+            started: false,
+            err: None,
+            reactor: reactor.clone(),
+        }
+    }
+
+    /// Starts the attempt for the next address.
+    ///
+    /// Returns whether there was an address left to attempt.
+    fn start_next(&mut self) -> io::Result<bool> {
+        let addr = match self.addrs.pop_front() {
+            Some(addr) => addr,
+            None => {
+                self.delay = None;
+                return Ok(false)
+            }
+        };
+        self.pending.push(TcpStream::connect(&addr, &self.reactor));
+        self.delay = Some(reactor::Timeout::new(
+            Duration::from_millis(CONNECTION_ATTEMPT_DELAY), &self.reactor
+        )?);
+        Ok(true)
+    }
+}
+
+impl Future for Attempts {
+    type Item = TcpStream;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if !self.started {
+            self.started = true;
+            self.start_next()?;
+        }
+        loop {
+            let mut failed = false;
+            let mut i = 0;
+            while i < self.pending.len() {
+                match self.pending[i].poll() {
+                    Ok(Async::Ready(stream)) => {
+                        self.pending.clear();
+                        self.delay = None;
+                        return Ok(Async::Ready(stream))
+                    }
+                    Ok(Async::NotReady) => i += 1,
+                    Err(err) => {
+                        self.pending.swap_remove(i);
+                        self.err = Some(err);
+                        failed = true;
+                    }
+                }
+            }
+            let expired = match self.delay {
+                Some(ref mut delay) => delay.poll()?.is_ready(),
+                None => false,
+            };
+            if (failed || expired) && self.start_next()? {
+                continue
+            }
+            if !self.pending.is_empty() {
+                return Ok(Async::NotReady)
+            }
+            return Err(self.err.take().unwrap_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "no addresses")
+            }))
+        }
+    }
+}
+
+/// Orders addresses so that the address families alternate.
+///
+/// The first family is that of the first address. Within each family, the
+/// order of the addresses is kept.
+fn interleave(addrs: &[IpAddr], port: u16) -> VecDeque<SocketAddr> {
+    let first_v6 = addrs.first().map_or(false, IpAddr::is_ipv6);
+    let (first, second): (Vec<IpAddr>, Vec<IpAddr>)
+        = addrs.iter().cloned().partition(|addr| addr.is_ipv6() == first_v6);
+    let mut first = first.into_iter();
+    let mut second = second.into_iter();
+    let mut res = VecDeque::with_capacity(addrs.len());
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => break,
+            (left, right) => {
+                res.extend(left.map(|addr| SocketAddr::new(addr, port)));
+                res.extend(right.map(|addr| SocketAddr::new(addr, port)));
+            }
+        }
+    }
+    res
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use std::net::TcpListener;
+
+    fn addrs(addrs: &[&str]) -> Vec<IpAddr> {
+        addrs.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn numeric_protocols() {
+        let mut db = ServicesDb::new();
+        db.parse(&mut Cursor::new("domain\t53/tcp\n\
+                                   domain\t5353/udp\n")).unwrap();
+        assert_eq!(service_port_in(&db, "domain", "6").unwrap(), 53);
+        assert_eq!(service_port_in(&db, "domain", "17").unwrap(), 5353);
+        assert_eq!(service_port_in(&db, "domain", "udp").unwrap(), 5353);
+        assert_eq!(service_port_in(&db, "80", "17").unwrap(), 80);
+        assert_eq!(service_port_in(&db, "domain", "132").unwrap_err().kind(),
+                   io::ErrorKind::NotFound);
+        assert_eq!(service_port_in(&db, "domain", "99").unwrap_err().kind(),
+                   io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn resolved_twice() {
+        let core = reactor::Core::new().unwrap();
+        let mut addrs = ServiceAddrs::new("localhost", "no-such-service",
+                                          "tcp", &core.handle());
+        assert!(addrs.poll().is_err());
+        assert_eq!(addrs.poll().unwrap_err().kind(), io::ErrorKind::Other);
+    }
+
+    #[test]
+    fn interleaved() {
+        let res: Vec<_> = interleave(
+            &addrs(&["2001:db8::1", "2001:db8::2", "2001:db8::3",
+                     "192.0.2.1", "192.0.2.2"]),
+            80
+        ).into_iter().map(|addr| addr.ip()).collect();
+        assert_eq!(res, addrs(&["2001:db8::1", "192.0.2.1", "2001:db8::2",
+                                "192.0.2.2", "2001:db8::3"]));
+    }
+
+    #[test]
+    fn connect_to_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+
+        let stream = core.run(connect("127.0.0.1", port, &handle)).unwrap();
+        assert_eq!(stream.peer_addr().unwrap().port(), port);
+
+        let err = core.run(Connect::from_addrs(&[], port, &handle))
+                      .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    /// Checks that a failing address is skipped for the next one.
+    ///
+    /// This relies on all of 127/8 being routed to the loopback interface
+    /// which only Linux does by default.
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn connect_falls_back() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+
+        let stream = core.run(Connect::from_addrs(
+            &addrs(&["127.0.0.2", "127.0.0.1"]), port, &handle
+        )).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(),
+                   SocketAddr::new("127.0.0.1".parse().unwrap(), port));
+    }
+}
@@ -0,0 +1,50 @@
+//! Running blocking operations off the reactor.
+//!
+//! Reading configuration files blocks the thread doing so. In order to not
+//! stall a reactor during startup, the loaders of the system configuration
+//! can run the reading on a thread of their own and deliver the result
+//! through a future.
+
+use std::io;
+use std::thread;
+use futures::{Async, Future, Poll};
+use futures::sync::oneshot;
+
+
+//------------ Blocking ------------------------------------------------------
+
+/// A future resolving into the result of an operation run on a thread.
+///
+/// This is the future returned by the `poll_system()` functions of the
+/// various configuration types.
+pub struct Blocking<T> {
+    rx: oneshot::Receiver<io::Result<T>>,
+}
+
+impl<T: Send + 'static> Blocking<T> {
+    /// Starts running `op` on a new thread.
+    pub fn spawn<F>(op: F) -> Self
+                 where F: FnOnce() -> io::Result<T> + Send + 'static {
+        let (tx, rx) = oneshot::channel();
+        thread::spawn(move || {
+            let _ = tx.send(op());
+        });
+        Blocking { rx: rx }
+    }
+}
+
+impl<T> Future for Blocking<T> {
+    type Item = T;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.rx.poll() {
+            Ok(Async::Ready(res)) => res.map(Async::Ready),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(_) => {
+                Err(io::Error::new(io::ErrorKind::Other,
+                                   "loading thread panicked"))
+            }
+        }
+    }
+}
@@ -0,0 +1,913 @@
+//! Direct DNS queries.
+//!
+//! While DNS is one of the sources for the databases in this crate, some
+//! information only exists in DNS. This module provides queries for such
+//! information. Like with the databases, there are synchronous functions
+//! prefixed with `get_` and asynchronous functions prefixed with `poll_`.
+
+use std::{io, mem};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use domain::bits::{DNameBuf, MessageBuf, ParsedDName};
+use domain::iana::{Class, Rcode};
+use domain::rdata::{A, Aaaa, Cname, Mx, Soa, Srv, Txt};
+use domain::resolv::{Query, Resolver};
+use domain::resolv::error::Error;
+use futures::{Async, Future, Poll};
+use tokio_core::reactor;
+use ::resolv::ResolvConf;
+
+pub use domain::iana::Rtype;
+
+
+//============ High-level API ================================================
+
+/// Returns the SOA record of the zone a name belongs to.
+///
+/// If `name` is the apex of a zone, this is the SOA record of that zone.
+/// Otherwise, it is the SOA record of the closest enclosing zone as
+/// reported by the name server in its answer, even if `name` itself does
+/// not exist. This makes the function useful for finding out why a name
+/// doesn’t resolve.
+///
+/// The function waits for all necessary IO to resolve. Upon success, it
+/// returns the SOA record if the answer contained one or `Ok(None)`
+/// otherwise.
+pub fn get_soa(name: &str) -> Result<Option<SoaEnt>, io::Error> {
+    let mut core = reactor::Core::new()?;
+    let handle = core.handle();
+    core.run(poll_soa(name, &handle))
+}
+
+/// Returns the SOA record of the zone a name belongs to.
+///
+/// This is the asynchronous version of `get_soa()`. The function returns
+/// a future that performs all necessary IO via the Tokio reactor given by
+/// `reactor`.
+pub fn poll_soa(name: &str, reactor: &reactor::Handle) -> SoaByName {
+    SoaByName::new(name, reactor)
+}
+
+/// Returns the records of a given type for a name.
+///
+/// This is a lower-level query for callers that need more than what the
+/// databases provide. The name is queried as given, no search list is
+/// applied. Records of type A, AAAA, MX, TXT, and SRV are returned with
+/// their data parsed, records of other types only with their type.
+///
+/// The function waits for all necessary IO to resolve. Upon success, it
+/// returns the records found in the answer, which may be empty if the name
+/// or records of this type don’t exist. If the query fails, for instance
+/// because no name server answered, an error is returned instead.
+pub fn get_records(name: &str, rtype: Rtype)
+                   -> Result<Vec<Record>, io::Error> {
+    let mut core = reactor::Core::new()?;
+    let handle = core.handle();
+    core.run(poll_records(name, rtype, &handle))
+}
+
+/// Returns the records of a given type for a name.
+///
+/// This is the asynchronous version of `get_records()`. The function
+/// returns a future that performs all necessary IO via the Tokio reactor
+/// given by `reactor`.
+pub fn poll_records(name: &str, rtype: Rtype, reactor: &reactor::Handle)
+                    -> RecordsByName {
+    RecordsByName::new(name, rtype, reactor)
+}
+
+/// Returns the CAA records relevant for a name.
+///
+/// This follows the tree climbing rule of RFC 8659: if the name itself has
+/// no CAA records, its parent is tried and so on up to but excluding the
+/// root. The records of the first name that has any are returned. An empty
+/// list means that no CA is restricted from issuing certificates for the
+/// name.
+///
+/// The function waits for all necessary IO to resolve. Only names that
+/// don’t exist or have no CAA records are skipped. If any query fails, for
+/// instance because no name server answered, an error is returned rather
+/// than an empty list.
+pub fn get_caa(name: &str) -> Result<Vec<CaaEnt>, io::Error> {
+    let mut core = reactor::Core::new()?;
+    let handle = core.handle();
+    core.run(poll_caa(name, &handle))
+}
+
+/// Returns the CAA records relevant for a name.
+///
+/// This is the asynchronous version of `get_caa()`. The function returns
+/// a future that performs all necessary IO via the Tokio reactor given by
+/// `reactor`.
+pub fn poll_caa(name: &str, reactor: &reactor::Handle) -> CaaByName {
+    CaaByName::new(name, reactor)
+}
+
+/// Returns the chain of canonical names for a name.
+///
+/// The chain starts with `name` itself, followed by the target of each
+/// CNAME record in turn, and ends with the canonical name the aliases
+/// finally lead to. This reveals the aliasing hidden by the host entries
+/// which only report the final canonical name. The name is queried as
+/// given, no search list is applied. All names are absolute and end in a
+/// dot.
+///
+/// The function waits for all necessary IO to resolve. An empty chain
+/// means that `name` is not an alias. If the query fails, an error is
+/// returned rather than a chain that may be incomplete.
+pub fn get_cname_chain(name: &str) -> Result<Vec<String>, io::Error> {
+    let mut core = reactor::Core::new()?;
+    let handle = core.handle();
+    core.run(poll_cname_chain(name, &handle))
+}
+
+/// Returns the chain of canonical names for a name.
+///
+/// This is the asynchronous version of `get_cname_chain()`. The function
+/// returns a future that performs all necessary IO via the Tokio reactor
+/// given by `reactor`.
+pub fn poll_cname_chain(name: &str, reactor: &reactor::Handle)
+                        -> CnameChain {
+    CnameChain::new(name, reactor)
+}
+
+
+//------------ resolver ------------------------------------------------------
+
+/// Creates a resolver using the system configuration.
+fn resolver(reactor: &reactor::Handle) -> Resolver {
+    Resolver::from_conf(reactor, ResolvConf::system().to_domain_conf())
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Converts the outcome of a query into the response to use.
+///
+/// A name that doesn’t exist results in `Ok(None)`. All other failures,
+/// including responses with an error rcode, are returned as errors.
+fn response(answer: Result<MessageBuf, Error>)
+            -> Result<Option<MessageBuf>, io::Error> {
+    let msg = match answer {
+        Ok(msg) => msg,
+        Err(Error::NoName) => return Ok(None),
+        Err(err) => return Err(io_error(err)),
+    };
+    match msg.header().rcode() {
+        Rcode::NoError | Rcode::NXDomain => Ok(Some(msg)),
+        rcode => {
+            Err(io::Error::new(io::ErrorKind::Other,
+                               format!("query failed: {}", rcode)))
+        }
+    }
+}
+
+/// Returns `name` as an absolute domain name.
+///
+/// The names given to the lookups of this module are always taken as
+/// absolute, whether they end in a dot or not. They are not expanded by
+/// the search list of the resolver configuration.
+fn absolute_name(name: &str) -> String {
+    if name.ends_with('.') {
+        name.into()
+    }
+    else {
+        format!("{}.", name)
+    }
+}
+
+/// Converts an error of the resolver into an IO error.
+///
+/// IO errors are returned as is. A question the resolver couldn’t send
+/// results in an error of kind `InvalidInput` and a query no server
+/// answered in one of kind `TimedOut`. All other errors become errors of
+/// kind `Other`.
+pub(crate) fn io_error(err: Error) -> io::Error {
+    match err {
+        Error::Io(err) => err,
+        Error::Timeout => {
+            io::Error::new(io::ErrorKind::TimedOut, "no server answered")
+        }
+        Error::Question(err) => {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("{}", err))
+        }
+        err => io::Error::new(io::ErrorKind::Other, format!("{}", err)),
+    }
+}
+
+/// Returns the error for polling a lookup that has already resolved.
+pub(crate) fn already_resolved() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "polling a resolved lookup")
+}
+
+
+//------------ SoaEnt --------------------------------------------------------
+
+/// The result of a SOA lookup.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SoaEnt {
+    zone: String,
+    mname: String,
+    rname: String,
+    serial: u32,
+    refresh: u32,
+    retry: u32,
+    expire: u32,
+    minimum: u32,
+}
+
+impl SoaEnt {
+    fn from_record(zone: &ParsedDName, soa: &Soa<ParsedDName>) -> Self {
+        SoaEnt {
+            zone: format!("{}", zone),
+            mname: format!("{}", soa.mname()),
+            rname: format!("{}", soa.rname()),
+            serial: soa.serial(),
+            refresh: soa.refresh(),
+            retry: soa.retry(),
+            expire: soa.expire(),
+            minimum: soa.minimum(),
+        }
+    }
+
+    /// The name of the zone, i.e., the owner of the SOA record.
+    pub fn zone(&self) -> &str {
+        &self.zone
+    }
+
+    /// The name of the primary name server of the zone.
+    pub fn mname(&self) -> &str {
+        &self.mname
+    }
+
+    /// The mailbox of the person responsible for the zone.
+    ///
+    /// This is in domain name format, i.e., the `@` is replaced by a dot.
+    pub fn rname(&self) -> &str {
+        &self.rname
+    }
+
+    /// The serial number of the zone.
+    pub fn serial(&self) -> u32 {
+        self.serial
+    }
+
+    /// The refresh interval of the zone in seconds.
+    pub fn refresh(&self) -> u32 {
+        self.refresh
+    }
+
+    /// The retry interval of the zone in seconds.
+    pub fn retry(&self) -> u32 {
+        self.retry
+    }
+
+    /// The expire time of the zone in seconds.
+    pub fn expire(&self) -> u32 {
+        self.expire
+    }
+
+    /// The minimum TTL of the zone in seconds.
+    ///
+    /// This is used as the TTL for negative answers.
+    pub fn minimum(&self) -> u32 {
+        self.minimum
+    }
+}
+
+
+//------------ SoaByName -----------------------------------------------------
+
+/// The future returned by `poll_soa()`.
+///
+/// Resolves into a `SoaEnt` value if the answer contained a SOA record or
+/// `None` otherwise.
+pub struct SoaByName(Result<Query, Option<io::Error>>);
+
+impl SoaByName {
+    pub fn new(name: &str, reactor: &reactor::Handle) -> Self {
+        let name = match DNameBuf::from_str(&absolute_name(name)) {
+            Ok(name) => name,
+            Err(err) => {
+                return SoaByName(Err(Some(
+                    io::Error::new(io::ErrorKind::InvalidInput, err)
+                )))
+            }
+        };
+        SoaByName(Ok(resolver(reactor).query((name, Rtype::Soa, Class::In))))
+    }
+}
+
+impl Future for SoaByName {
+    type Item = Option<SoaEnt>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.0 {
+            Ok(ref mut query) => {
+                let answer = match query.poll() {
+                    Ok(Async::Ready(msg)) => Ok(msg),
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(err) => Err(err),
+                };
+                let msg = response(answer)?;
+                Ok(Async::Ready(msg.and_then(|msg| soa_from(&msg))))
+            }
+            Err(ref mut inner) => {
+                match mem::replace(inner, None) {
+                    Some(err) => Err(err),
+                    None => Err(already_resolved()),
+                }
+            }
+        }
+    }
+}
+
+
+//------------ Record --------------------------------------------------------
+
+/// A resource record returned by `get_records()`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Record {
+    name: String,
+    ttl: u32,
+    data: RecordData,
+}
+
+impl Record {
+    /// The owner name of the record.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The time-to-live of the record in seconds.
+    pub fn ttl(&self) -> u32 {
+        self.ttl
+    }
+
+    /// The data of the record.
+    pub fn data(&self) -> &RecordData {
+        &self.data
+    }
+}
+
+
+//------------ RecordData ----------------------------------------------------
+
+/// The data of a resource record.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RecordData {
+    /// An IPv4 address.
+    A(Ipv4Addr),
+
+    /// An IPv6 address.
+    Aaaa(Ipv6Addr),
+
+    /// A mail exchange with its preference and host name.
+    Mx(u16, String),
+
+    /// Text consisting of a sequence of character strings.
+    ///
+    /// Character strings that aren’t valid UTF-8 are converted lossily.
+    Txt(Vec<String>),
+
+    /// A service location with priority, weight, port, and target host.
+    Srv(u16, u16, u16, String),
+
+    /// A record of some other type whose data isn’t parsed.
+    Other(Rtype),
+}
+
+
+//------------ RecordsByName -------------------------------------------------
+
+/// The future returned by `poll_records()`.
+///
+/// Resolves into the records found in the answer.
+pub struct RecordsByName {
+    rtype: Rtype,
+    query: Result<Query, Option<io::Error>>,
+}
+
+impl RecordsByName {
+    pub fn new(name: &str, rtype: Rtype, reactor: &reactor::Handle) -> Self {
+        let name = match DNameBuf::from_str(&absolute_name(name)) {
+            Ok(name) => name,
+            Err(err) => {
+                return RecordsByName {
+                    rtype: rtype,
+                    query: Err(Some(
+                        io::Error::new(io::ErrorKind::InvalidInput, err)
+                    ))
+                }
+            }
+        };
+        RecordsByName {
+            rtype: rtype,
+            query: Ok(resolver(reactor).query((name, rtype, Class::In))),
+        }
+    }
+}
+
+impl Future for RecordsByName {
+    type Item = Vec<Record>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.query {
+            Ok(ref mut query) => {
+                let answer = match query.poll() {
+                    Ok(Async::Ready(msg)) => Ok(msg),
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(err) => Err(err),
+                };
+                Ok(Async::Ready(match response(answer)? {
+                    Some(msg) => records_from(&msg, self.rtype),
+                    None => Vec::new(),
+                }))
+            }
+            Err(ref mut inner) => {
+                match mem::replace(inner, None) {
+                    Some(err) => Err(err),
+                    None => Err(already_resolved()),
+                }
+            }
+        }
+    }
+}
+
+/// Extracts the records of type `rtype` from the answer of a response.
+///
+/// Records that fail to parse are skipped.
+fn records_from(msg: &MessageBuf, rtype: Rtype) -> Vec<Record> {
+    let mut res = Vec::new();
+    let section = match msg.answer() {
+        Ok(section) => section,
+        Err(_) => return res
+    };
+    macro_rules! push {
+        ( $rdata:ty, $record:ident => $data:expr ) => {
+            for $record in section.limit_to::<$rdata>() {
+                if let Ok($record) = $record {
+                    res.push(Record {
+                        name: format!("{}", $record.name()),
+                        ttl: $record.ttl(),
+                        data: $data,
+                    })
+                }
+            }
+        }
+    }
+    match rtype {
+        Rtype::A => push!(A, r => RecordData::A(r.data().addr())),
+        Rtype::Aaaa => push!(Aaaa, r => RecordData::Aaaa(r.data().addr())),
+        Rtype::Mx => {
+            push!(Mx<ParsedDName>, r => {
+                RecordData::Mx(r.data().preference(),
+                               format!("{}", r.data().exchange()))
+            })
+        }
+        Rtype::Txt => {
+            push!(Txt<&[u8]>, r => {
+                RecordData::Txt(r.data().iter().map(|text| {
+                    String::from_utf8_lossy(text).into_owned()
+                }).collect())
+            })
+        }
+        Rtype::Srv => {
+            push!(Srv<ParsedDName>, r => {
+                RecordData::Srv(r.data().priority(), r.data().weight(),
+                                r.data().port(),
+                                format!("{}", r.data().target()))
+            })
+        }
+        _ => {
+            for record in section.iter() {
+                if let Ok(record) = record {
+                    if record.rtype() == rtype {
+                        res.push(Record {
+                            name: format!("{}", record.name()),
+                            ttl: record.ttl(),
+                            data: RecordData::Other(rtype),
+                        })
+                    }
+                }
+            }
+        }
+    }
+    res
+}
+
+
+//------------ CaaEnt --------------------------------------------------------
+
+/// A CAA record.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CaaEnt {
+    flags: u8,
+    tag: String,
+    value: String,
+}
+
+impl CaaEnt {
+    /// Parses the record data of a CAA record.
+    ///
+    /// Returns `None` if the data is malformed.
+    fn from_rdata(data: &[u8]) -> Option<Self> {
+        if data.len() < 2 {
+            return None
+        }
+        let len = data[1] as usize;
+        if len == 0 || data.len() < 2 + len {
+            return None
+        }
+        Some(CaaEnt {
+            flags: data[0],
+            tag: String::from_utf8_lossy(&data[2..2 + len]).into_owned(),
+            value: String::from_utf8_lossy(&data[2 + len..]).into_owned(),
+        })
+    }
+
+    /// The flags of the record.
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Returns whether the issuer critical flag is set.
+    ///
+    /// A CA must not issue if it doesn’t understand the tag of a record
+    /// with this flag.
+    pub fn is_critical(&self) -> bool {
+        self.flags & 0x80 != 0
+    }
+
+    /// The property tag, such as `"issue"` or `"iodef"`.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// The property value.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+
+//------------ CaaByName -----------------------------------------------------
+
+/// The future returned by `poll_caa()`.
+///
+/// Resolves into the CAA records of the closest name that has any.
+pub struct CaaByName(Result<CaaLookup, Option<io::Error>>);
+
+struct CaaLookup {
+    resolver: Resolver,
+    name: DNameBuf,
+    query: Query,
+}
+
+impl CaaByName {
+    pub fn new(name: &str, reactor: &reactor::Handle) -> Self {
+        Self::with_resolver(name, resolver(reactor))
+    }
+
+    /// Creates the lookup using the given resolver.
+    fn with_resolver(name: &str, resolver: Resolver) -> Self {
+        let name = match DNameBuf::from_str(&absolute_name(name)) {
+            Ok(name) => name,
+            Err(err) => {
+                return CaaByName(Err(Some(
+                    io::Error::new(io::ErrorKind::InvalidInput, err)
+                )))
+            }
+        };
+        let query = resolver.query((&name, Rtype::Caa, Class::In));
+        CaaByName(Ok(CaaLookup {
+            resolver: resolver,
+            name: name,
+            query: query,
+        }))
+    }
+}
+
+impl Future for CaaByName {
+    type Item = Vec<CaaEnt>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let lookup = match self.0 {
+            Ok(ref mut lookup) => lookup,
+            Err(ref mut inner) => {
+                return match mem::replace(inner, None) {
+                    Some(err) => Err(err),
+                    None => Err(already_resolved()),
+                }
+            }
+        };
+        loop {
+            let answer = match lookup.query.poll() {
+                Ok(Async::Ready(msg)) => Ok(msg),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(err) => Err(err),
+            };
+            let caa = match response(answer)? {
+                Some(msg) => caa_from(&msg),
+                None => Vec::new(),
+            };
+            if !caa.is_empty() {
+                return Ok(Async::Ready(caa))
+            }
+            let parent = match lookup.name.parent() {
+                Some(parent) if parent.parent().is_some() => {
+                    parent.to_owned()
+                }
+                _ => return Ok(Async::Ready(Vec::new()))
+            };
+            lookup.name = parent;
+            lookup.query = lookup.resolver.query((&lookup.name, Rtype::Caa,
+                                                  Class::In));
+        }
+    }
+}
+
+/// Extracts the CAA records from the answer of a response.
+///
+/// Records that fail to parse are skipped.
+fn caa_from(msg: &MessageBuf) -> Vec<CaaEnt> {
+    let mut res = Vec::new();
+    if let Ok(section) = msg.answer() {
+        for record in section.iter() {
+            if let Ok(record) = record {
+                if record.rtype() == Rtype::Caa {
+                    if let Some(caa) = CaaEnt::from_rdata(
+                                                record.data().data()) {
+                        res.push(caa)
+                    }
+                }
+            }
+        }
+    }
+    res
+}
+
+/// Extracts the SOA record from a response.
+///
+/// The record is taken from the answer section if the name was a zone
+/// apex or from the authority section of a negative answer otherwise.
+fn soa_from(msg: &MessageBuf) -> Option<SoaEnt> {
+    for section in vec![msg.answer(), msg.authority()] {
+        if let Ok(section) = section {
+            for record in section.limit_to::<Soa<ParsedDName>>() {
+                if let Ok(record) = record {
+                    return Some(SoaEnt::from_record(record.name(),
+                                                    record.data()))
+                }
+            }
+        }
+    }
+    None
+}
+
+
+//------------ CnameChain ----------------------------------------------------
+
+/// The future returned by `poll_cname_chain()`.
+///
+/// Resolves into the chain of names from the queried name to its canonical
+/// name.
+pub struct CnameChain {
+    qname: String,
+    query: Result<Query, Option<io::Error>>,
+}
+
+impl CnameChain {
+    pub fn new(name: &str, reactor: &reactor::Handle) -> Self {
+        let name = match DNameBuf::from_str(&absolute_name(name)) {
+            Ok(name) => name,
+            Err(err) => {
+                return CnameChain {
+                    qname: String::new(),
+                    query: Err(Some(
+                        io::Error::new(io::ErrorKind::InvalidInput, err)
+                    ))
+                }
+            }
+        };
+        CnameChain {
+            qname: format!("{}", name),
+            query: Ok(resolver(reactor).query((name, Rtype::A, Class::In))),
+        }
+    }
+}
+
+impl Future for CnameChain {
+    type Item = Vec<String>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.query {
+            Ok(ref mut query) => {
+                let answer = match query.poll() {
+                    Ok(Async::Ready(msg)) => Ok(msg),
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(err) => Err(err),
+                };
+                Ok(Async::Ready(match response(answer)? {
+                    Some(msg) => cname_chain_from(&msg, &self.qname),
+                    None => Vec::new(),
+                }))
+            }
+            Err(ref mut inner) => {
+                match mem::replace(inner, None) {
+                    Some(err) => Err(err),
+                    None => Err(already_resolved()),
+                }
+            }
+        }
+    }
+}
+
+/// Follows the CNAME records in the answer of a response from `qname`.
+///
+/// Each record is used at most once, so a loop of aliases ends the chain
+/// rather than going on forever. Records that fail to parse are skipped.
+fn cname_chain_from(msg: &MessageBuf, qname: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    if let Ok(section) = msg.answer() {
+        for record in section.limit_to::<Cname<ParsedDName>>() {
+            if let Ok(record) = record {
+                links.push((format!("{}", record.name()),
+                            format!("{}", record.data().cname())));
+            }
+        }
+    }
+    let mut res = Vec::new();
+    let mut name = qname.to_owned();
+    loop {
+        let pos = match links.iter().position(|link| {
+            link.0.eq_ignore_ascii_case(&name)
+        }) {
+            Some(pos) => pos,
+            None => break
+        };
+        let (owner, target) = links.swap_remove(pos);
+        if res.is_empty() {
+            res.push(owner)
+        }
+        res.push(target.clone());
+        name = target;
+    }
+    res
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::net::UdpSocket;
+    use super::*;
+
+    /// An NXDOMAIN response for `nx.example.com. SOA`.
+    const NXDOMAIN_SOA: &'static [u8] = &[
+        0x00, 0x00, 0x81, 0x83, 0x00, 0x01, 0x00, 0x00,
+        0x00, 0x01, 0x00, 0x00,
+        2, b'n', b'x',
+        7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0,
+        0x00, 0x06, 0x00, 0x01,
+        0xc0, 0x0f, 0x00, 0x06, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10,
+        0x00, 0x20,
+        2, b'n', b's', 0xc0, 0x0f,
+        4, b'r', b'o', b'o', b't', 0xc0, 0x0f,
+        0x78, 0x39, 0xbf, 0x85,
+        0x00, 0x00, 0x1c, 0x20,
+        0x00, 0x00, 0x0e, 0x10,
+        0x00, 0x12, 0x75, 0x00,
+        0x00, 0x00, 0x0e, 0x10
+    ];
+
+    /// A response for `example.com. TXT` with one record.
+    const TXT: &'static [u8] = &[
+        0x00, 0x00, 0x81, 0x80, 0x00, 0x01, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x00,
+        7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0,
+        0x00, 0x10, 0x00, 0x01,
+        0xc0, 0x0c, 0x00, 0x10, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10,
+        0x00, 0x0c,
+        11, b'v', b'=', b's', b'p', b'f', b'1', b' ', b'-', b'a', b'l', b'l'
+    ];
+
+    #[test]
+    fn txt_records() {
+        let msg = MessageBuf::from_vec(TXT.into()).unwrap();
+        let records = records_from(&msg, Rtype::Txt);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].name(), "example.com.");
+        assert_eq!(records[0].ttl(), 3600);
+        assert_eq!(records[0].data(),
+                   &RecordData::Txt(vec!["v=spf1 -all".into()]));
+    }
+
+    /// A response for `example.com. CAA` with one record.
+    const CAA: &'static [u8] = &[
+        0x00, 0x00, 0x81, 0x80, 0x00, 0x01, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x00,
+        7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0,
+        0x01, 0x01, 0x00, 0x01,
+        0xc0, 0x0c, 0x01, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10,
+        0x00, 0x11,
+        0x80, 5, b'i', b's', b's', b'u', b'e',
+        b'c', b'a', b'.', b'e', b'x', b'a', b'm', b'p', b'l', b'e'
+    ];
+
+    #[test]
+    fn caa_records() {
+        let msg = MessageBuf::from_vec(CAA.into()).unwrap();
+        let caa = caa_from(&msg);
+        assert_eq!(caa.len(), 1);
+        assert!(caa[0].is_critical());
+        assert_eq!(caa[0].tag(), "issue");
+        assert_eq!(caa[0].value(), "ca.example");
+        assert!(CaaEnt::from_rdata(&[0, 5, b'i']).is_none());
+    }
+
+    #[test]
+    fn caa_unanswered() {
+        // A name server that receives queries but never answers them.
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let conf = ResolvConf::parse(&mut io::Cursor::new(format!(
+            "nameserver {}\noptions timeout:1 attempts:1\n",
+            server.local_addr().unwrap()
+        ))).unwrap();
+        let mut core = reactor::Core::new().unwrap();
+        let resolver = Resolver::from_conf(&core.handle(),
+                                           conf.to_domain_conf());
+        let res = core.run(CaaByName::with_resolver("www.example.com.",
+                                                    resolver));
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn resolved_twice() {
+        let core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        let mut soa = SoaByName::new("a..example.com.", &handle);
+        assert_eq!(soa.poll().unwrap_err().kind(),
+                   io::ErrorKind::InvalidInput);
+        assert_eq!(soa.poll().unwrap_err().kind(), io::ErrorKind::Other);
+        let mut records = RecordsByName::new("a..example.com.", Rtype::Txt,
+                                             &handle);
+        assert_eq!(records.poll().unwrap_err().kind(),
+                   io::ErrorKind::InvalidInput);
+        assert_eq!(records.poll().unwrap_err().kind(),
+                   io::ErrorKind::Other);
+        let mut caa = CaaByName::new("a..example.com.", &handle);
+        assert_eq!(caa.poll().unwrap_err().kind(),
+                   io::ErrorKind::InvalidInput);
+        assert_eq!(caa.poll().unwrap_err().kind(), io::ErrorKind::Other);
+        let mut chain = CnameChain::new("a..example.com.", &handle);
+        assert_eq!(chain.poll().unwrap_err().kind(),
+                   io::ErrorKind::InvalidInput);
+        assert_eq!(chain.poll().unwrap_err().kind(), io::ErrorKind::Other);
+    }
+
+    /// A response for `www.example.com. A` with a chain of two CNAMEs.
+    const CNAME_CHAIN: &'static [u8] = &[
+        0x00, 0x00, 0x81, 0x80, 0x00, 0x01, 0x00, 0x03,
+        0x00, 0x00, 0x00, 0x00,
+        3, b'w', b'w', b'w',
+        7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0,
+        0x00, 0x01, 0x00, 0x01,
+        0xc0, 0x0c, 0x00, 0x05, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10,
+        0x00, 0x06, 3, b'c', b'd', b'n', 0xc0, 0x10,
+        0xc0, 0x2d, 0x00, 0x05, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10,
+        0x00, 0x02, 0xc0, 0x10,
+        0xc0, 0x10, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10,
+        0x00, 0x04, 192, 0, 2, 1
+    ];
+
+    #[test]
+    fn cname_chain() {
+        let msg = MessageBuf::from_vec(CNAME_CHAIN.into()).unwrap();
+        assert_eq!(cname_chain_from(&msg, "www.example.com."),
+                   ["www.example.com.", "cdn.example.com.", "example.com."]);
+        assert_eq!(cname_chain_from(&msg, "WWW.Example.com."),
+                   ["www.example.com.", "cdn.example.com.", "example.com."]);
+        let msg = MessageBuf::from_vec(TXT.into()).unwrap();
+        assert!(cname_chain_from(&msg, "example.com.").is_empty());
+    }
+
+    #[test]
+    fn soa_from_negative_answer() {
+        let msg = MessageBuf::from_vec(NXDOMAIN_SOA.into()).unwrap();
+        let soa = soa_from(&msg).unwrap();
+        assert_eq!(soa.zone(), "example.com.");
+        assert_eq!(soa.mname(), "ns.example.com.");
+        assert_eq!(soa.rname(), "root.example.com.");
+        assert_eq!(soa.serial(), 2017050501);
+        assert_eq!(soa.refresh(), 7200);
+        assert_eq!(soa.retry(), 3600);
+        assert_eq!(soa.expire(), 1209600);
+        assert_eq!(soa.minimum(), 3600);
+    }
+}
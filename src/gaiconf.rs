@@ -0,0 +1,393 @@
+//! Parsing of gai.conf.
+//!
+//! The `/etc/gai.conf` file used by glibc tunes the policy tables of RFC
+//! 3484 that `getaddrinfo()` uses for ordering the addresses of a host.
+//! There are three tables: the label and precedence tables for IPv6
+//! prefixes, with IPv4 addresses looked up as IPv4-mapped IPv6 addresses,
+//! and the scope table for IPv4 addresses.
+//!
+//! As with glibc, a table given in the file replaces the default table
+//! entirely. Tables not given keep their defaults.
+
+use std::{error, fmt, fs, io};
+use std::cmp::Reverse;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::str::FromStr;
+use ::blocking::Blocking;
+
+
+//------------ GaiConf -------------------------------------------------------
+
+/// The address selection configuration.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GaiConf {
+    labels: Vec<(Prefix, u32)>,
+    precedences: Vec<(Prefix, u32)>,
+    scopes: Vec<(Prefix, u32)>,
+}
+
+impl GaiConf {
+    /// Creates a configuration with the default tables of RFC 3484.
+    pub fn new() -> Self {
+        let table = |items: &[(&str, u32)]| -> Vec<(Prefix, u32)> {
+            items.iter().map(|&(prefix, value)| {
+                (Prefix::from_str(prefix).unwrap(), value)
+            }).collect()
+        };
+        GaiConf {
+            labels: table(&[
+                ("::1/128", 0), ("::/0", 1), ("2002::/16", 2), ("::/96", 3),
+                ("::ffff:0:0/96", 4), ("fec0::/10", 5), ("fc00::/7", 6),
+                ("2001::/32", 7),
+            ]),
+            precedences: table(&[
+                ("::1/128", 50), ("::/0", 40), ("2002::/16", 30),
+                ("::/96", 20), ("::ffff:0:0/96", 10),
+            ]),
+            scopes: table(&[
+                ("::ffff:169.254.0.0/112", 2), ("::ffff:127.0.0.0/104", 2),
+                ("::ffff:0:0/96", 14),
+            ]),
+        }
+    }
+
+    /// Returns the label of an address.
+    ///
+    /// Returns `None` if no prefix of the label table matches.
+    pub fn label(&self, addr: &IpAddr) -> Option<u32> {
+        lookup(&self.labels, &mapped(addr))
+    }
+
+    /// Returns the precedence of an address.
+    ///
+    /// Returns `None` if no prefix of the precedence table matches.
+    pub fn precedence(&self, addr: &IpAddr) -> Option<u32> {
+        lookup(&self.precedences, &mapped(addr))
+    }
+
+    /// Returns the scope of an address.
+    ///
+    /// The scope of IPv4 addresses is taken from the scope table, with
+    /// global scope for addresses not matching any of its entries. For IPv6
+    /// addresses, the scope follows from the address itself.
+    pub fn scope(&self, addr: &IpAddr) -> u32 {
+        match *addr {
+            IpAddr::V4(_) => {
+                lookup(&self.scopes, &mapped(addr)).unwrap_or(SCOPE_GLOBAL)
+            }
+            IpAddr::V6(addr) => {
+                let segment = addr.segments()[0];
+                if segment & 0xff00 == 0xff00 {
+                    u32::from(segment & 0x000f)
+                }
+                else if addr.is_loopback() || segment & 0xffc0 == 0xfe80 {
+                    SCOPE_LINK_LOCAL
+                }
+                else if segment & 0xffc0 == 0xfec0 {
+                    SCOPE_SITE_LOCAL
+                }
+                else {
+                    SCOPE_GLOBAL
+                }
+            }
+        }
+    }
+
+    /// Orders addresses according to the configuration.
+    ///
+    /// Of the destination address selection rules of RFC 3484, only those
+    /// that don’t need the source address to use are applied: addresses
+    /// with higher precedence are placed first (rule 6) and, among those
+    /// with equal precedence, addresses with smaller scope (rule 8). The
+    /// sort is stable, so addresses considered equal keep their order.
+    pub fn sort(&self, addrs: &mut [IpAddr]) {
+        addrs.sort_by_key(|addr| {
+            (Reverse(self.precedence(addr).unwrap_or(0)), self.scope(addr))
+        })
+    }
+}
+
+/// # System Configuration
+///
+impl GaiConf {
+    /// Returns the address selection configuration of this system.
+    ///
+    /// If the configuration file cannot be read, returns the defaults.
+    ///
+    /// XXX This currently only works for Unix-y systems.
+    pub fn system() -> Self {
+        Self::parse_file("/etc/gai.conf").unwrap_or_default()
+    }
+
+    /// Returns a future resolving into the configuration of this system.
+    ///
+    /// This is identical to `system()` except that the file is read on a
+    /// thread of its own so that a reactor isn’t blocked during startup.
+    pub fn poll_system() -> Blocking<Self> {
+        Blocking::spawn(|| Ok(Self::system()))
+    }
+}
+
+impl Default for GaiConf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// # Parsing Conf File
+///
+impl GaiConf {
+    /// Parse a conf file.
+    pub fn parse_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::parse(&mut fs::File::open(path)?)
+    }
+
+    /// Parse a conf from a reader.
+    ///
+    /// Lines that fail to parse are ignored. The `reload` directive is
+    /// accepted but has no effect.
+    pub fn parse<R: io::Read>(reader: &mut R) -> Result<Self, Error> {
+        use std::io::BufRead;
+
+        let mut labels = Vec::new();
+        let mut precedences = Vec::new();
+        let mut scopes = Vec::new();
+        for line in io::BufReader::new(reader).lines() {
+            let line = line?;
+            let _ = parse_line(&line, &mut labels, &mut precedences,
+                               &mut scopes);
+        }
+        let mut res = GaiConf::new();
+        if !labels.is_empty() {
+            res.labels = labels
+        }
+        if !precedences.is_empty() {
+            res.precedences = precedences
+        }
+        if !scopes.is_empty() {
+            res.scopes = scopes
+        }
+        Ok(res)
+    }
+}
+
+/// Parses a line and adds its entry to the table it is for.
+fn parse_line(line: &str, labels: &mut Vec<(Prefix, u32)>,
+              precedences: &mut Vec<(Prefix, u32)>,
+              scopes: &mut Vec<(Prefix, u32)>) -> Result<(), Error> {
+    let line: &str = match line.find('#') {
+        Some(pos) => line.split_at(pos).0,
+        None => line
+    };
+    let mut words = line.split_whitespace();
+    let (keyword, prefix, value) = match words.next() {
+        None => return Ok(()),
+        Some("reload") => return Ok(()),
+        Some(keyword) => {
+            let prefix = words.next().ok_or(Error::ParseError)?;
+            let value = words.next().ok_or(Error::ParseError)?;
+            if words.next().is_some() {
+                return Err(Error::ParseError)
+            }
+            let value = u32::from_str(value).map_err(|_| Error::ParseError)?;
+            (keyword, prefix, value)
+        }
+    };
+    match keyword {
+        "label" => labels.push((Prefix::from_str(prefix)?, value)),
+        "precedence" => precedences.push((Prefix::from_str(prefix)?, value)),
+        "scopev4" => scopes.push((Prefix::from_v4_str(prefix)?, value)),
+        _ => return Err(Error::ParseError)
+    }
+    Ok(())
+}
+
+
+//------------ Prefix --------------------------------------------------------
+
+/// An IPv6 prefix.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct Prefix {
+    addr: Ipv6Addr,
+    len: u32,
+}
+
+impl Prefix {
+    /// Returns whether `addr` is covered by the prefix.
+    fn contains(&self, addr: &Ipv6Addr) -> bool {
+        let (addr, net) = (addr.octets(), self.addr.octets());
+        let full = (self.len / 8) as usize;
+        if addr[..full] != net[..full] {
+            return false
+        }
+        let bits = self.len % 8;
+        bits == 0 || (addr[full] ^ net[full]) & (0xff << (8 - bits)) == 0
+    }
+
+    /// Parses the prefix of a `scopev4` line.
+    ///
+    /// This is either an IPv4-mapped IPv6 prefix or an IPv4 prefix which
+    /// is converted into the former.
+    fn from_v4_str(s: &str) -> Result<Self, Error> {
+        if let Ok(res) = Self::from_str(s) {
+            if res.len >= 96 && mapped_v4(&res.addr) {
+                return Ok(res)
+            }
+            return Err(Error::ParseError)
+        }
+        let mut parts = s.splitn(2, '/');
+        let addr = Ipv4Addr::from_str(parts.next().unwrap())
+                            .map_err(|_| Error::ParseError)?;
+        let len = match parts.next() {
+            Some(len) => u32::from_str(len).map_err(|_| Error::ParseError)?,
+            None => 32
+        };
+        if len > 32 {
+            return Err(Error::ParseError)
+        }
+        Ok(Prefix { addr: addr.to_ipv6_mapped(), len: len + 96 })
+    }
+}
+
+impl FromStr for Prefix {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut parts = s.splitn(2, '/');
+        let addr = Ipv6Addr::from_str(parts.next().unwrap())
+                            .map_err(|_| Error::ParseError)?;
+        let len = match parts.next() {
+            Some(len) => u32::from_str(len).map_err(|_| Error::ParseError)?,
+            None => 128
+        };
+        if len > 128 {
+            return Err(Error::ParseError)
+        }
+        Ok(Prefix { addr: addr, len: len })
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// The scope of link-local addresses.
+const SCOPE_LINK_LOCAL: u32 = 2;
+
+/// The scope of site-local addresses.
+const SCOPE_SITE_LOCAL: u32 = 5;
+
+/// The scope of global addresses.
+const SCOPE_GLOBAL: u32 = 14;
+
+/// Returns the value of the longest prefix in `table` matching `addr`.
+fn lookup(table: &[(Prefix, u32)], addr: &Ipv6Addr) -> Option<u32> {
+    table.iter().filter(|item| item.0.contains(addr))
+         .max_by_key(|item| item.0.len)
+         .map(|item| item.1)
+}
+
+/// Returns an address as an IPv6 address, mapping IPv4 addresses.
+fn mapped(addr: &IpAddr) -> Ipv6Addr {
+    match *addr {
+        IpAddr::V4(addr) => addr.to_ipv6_mapped(),
+        IpAddr::V6(addr) => addr,
+    }
+}
+
+/// Returns whether an IPv6 address is an IPv4-mapped address.
+fn mapped_v4(addr: &Ipv6Addr) -> bool {
+    let segments = addr.segments();
+    segments[..5].iter().all(|&segment| segment == 0)
+        && segments[5] == 0xffff
+}
+
+
+//------------ Error ---------------------------------------------------------
+
+/// An error happend during parsing a gai.conf file.
+#[derive(Debug)]
+pub enum Error {
+    /// The file is kaputt.
+    ParseError,
+
+    /// Reading failed.
+    IoError(io::Error),
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::ParseError => "error parsing configuration",
+            Error::IoError(ref e) => e.description(),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::IoError(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use std::error::Error;
+
+        self.description().fmt(f)
+    }
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::*;
+
+    fn addrs(addrs: &[&str]) -> Vec<IpAddr> {
+        addrs.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn default_tables() {
+        let conf = GaiConf::new();
+        let mut res = addrs(&["192.0.2.1", "2002:c000:201::1",
+                              "2001:db8::1", "::1"]);
+        conf.sort(&mut res);
+        assert_eq!(res, addrs(&["::1", "2001:db8::1", "2002:c000:201::1",
+                                "192.0.2.1"]));
+        assert_eq!(conf.label(&"2001:db8::1".parse().unwrap()), Some(7));
+        assert_eq!(conf.scope(&"169.254.1.1".parse().unwrap()), 2);
+        assert_eq!(conf.scope(&"192.0.2.1".parse().unwrap()), 14);
+        assert_eq!(conf.scope(&"fe80::1".parse().unwrap()), 2);
+        assert_eq!(conf.scope(&"ff05::1".parse().unwrap()), 5);
+    }
+
+    #[test]
+    fn parse_tables() {
+        let conf = GaiConf::parse(&mut Cursor::new(
+            "# Prefer IPv4\n\
+             reload yes\n\
+             precedence ::1/128 50\n\
+             precedence ::/0 40\n\
+             precedence ::ffff:0:0/96 100\n\
+             scopev4 ::ffff:10.0.0.0/104 5\n\
+             scopev4 192.168.0.0/16 5\n\
+             label ::/0\n"
+        )).unwrap();
+        let mut res = addrs(&["2001:db8::1", "192.0.2.1"]);
+        conf.sort(&mut res);
+        assert_eq!(res, addrs(&["192.0.2.1", "2001:db8::1"]));
+        assert_eq!(conf.scope(&"10.1.2.3".parse().unwrap()), 5);
+        assert_eq!(conf.scope(&"192.168.1.1".parse().unwrap()), 5);
+
+        // Given scope table replaces the default one.
+        assert_eq!(conf.scope(&"127.0.0.1".parse().unwrap()), 14);
+
+        // The broken label line leaves the default table in place.
+        assert_eq!(conf.labels, GaiConf::new().labels);
+    }
+}
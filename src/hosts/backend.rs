@@ -0,0 +1,90 @@
+//! Resolvers backing the dns source.
+
+use std::{fmt, io};
+use std::net::IpAddr;
+use std::sync::Arc;
+use futures::{future, Future};
+use tokio_core::reactor;
+use super::{dns, HostEnt, ResolverOptions};
+
+
+//------------ DnsBackend ----------------------------------------------------
+
+/// A resolver backing the `dns` service of the hosts database.
+///
+/// By default, the `dns` service uses the resolver of the `domain` crate.
+/// Applications that already depend on a different resolver can hand an
+/// implementation of this trait to lookups via
+/// `ResolverOptions::dns_backend()` to avoid having two resolvers around.
+///
+/// Both methods return a future resolving into the host entry or `None` if
+/// the name or address doesn’t exist. The backend should apply the options
+/// relevant to DNS, such as the address families to query, as far as it
+/// is able to. The options that shape the resulting entry are applied to
+/// its result by the lookup.
+pub trait DnsBackend {
+    /// Looks up the host entry for a host name.
+    fn lookup_name(&self, name: &str, options: &ResolverOptions,
+                   reactor: &reactor::Handle)
+                   -> Box<Future<Item=Option<HostEnt>, Error=io::Error>>;
+
+    /// Looks up the host entry for an address.
+    fn lookup_addr(&self, addr: IpAddr, options: &ResolverOptions,
+                   reactor: &reactor::Handle)
+                   -> Box<Future<Item=Option<HostEnt>, Error=io::Error>>;
+}
+
+
+//------------ SharedBackend -------------------------------------------------
+
+/// A backend kept by the resolver options.
+///
+/// Cloning the value is cheap since the backend itself is shared.
+#[derive(Clone)]
+pub struct SharedBackend(Arc<DnsBackend + Send + Sync>);
+
+impl SharedBackend {
+    pub fn new<B: DnsBackend + Send + Sync + 'static>(backend: B) -> Self {
+        SharedBackend(Arc::new(backend))
+    }
+
+    pub fn get(&self) -> &(DnsBackend + Send + Sync) {
+        self.0.as_ref()
+    }
+}
+
+impl fmt::Debug for SharedBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedBackend(..)")
+    }
+}
+
+
+//------------ DomainBackend -------------------------------------------------
+
+/// The backend using the resolver of the `domain` crate.
+///
+/// This is the backend used if no other backend is given. It is available
+/// for backends that want to fall back to it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DomainBackend;
+
+impl DnsBackend for DomainBackend {
+    fn lookup_name(&self, name: &str, options: &ResolverOptions,
+                   reactor: &reactor::Handle)
+                   -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+        match dns::HostByName::new(name, options, reactor) {
+            Ok(lookup) => Box::new(lookup),
+            Err(err) => Box::new(future::err(err)),
+        }
+    }
+
+    fn lookup_addr(&self, addr: IpAddr, options: &ResolverOptions,
+                   reactor: &reactor::Handle)
+                   -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+        match dns::HostByAddr::new(addr, options, reactor) {
+            Ok(lookup) => Box::new(lookup),
+            Err(err) => Box::new(future::err(err)),
+        }
+    }
+}
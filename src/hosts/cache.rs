@@ -0,0 +1,457 @@
+//! Caching host entries.
+
+use std::io;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use futures::{Async, Future, Poll};
+use tokio_core::reactor;
+use ::dns::already_resolved;
+use super::{HostByName, HostEnt, ResolverOptions};
+
+
+//------------ Cache ---------------------------------------------------------
+
+/// An in-memory cache of host entries.
+///
+/// Entries are kept under the host name they were looked up for. Since
+/// host names are case-insensitive, the name is converted to lowercase
+/// ASCII and a trailing dot is dropped, so `Example.COM` and `example.com.`
+/// share an entry. Only successful lookups are cached.
+///
+/// A cache is bound to the resolver options it was created with, the
+/// default options unless created via `Cache::with_options()`. Lookups
+/// through the cache use these options. Entries are kept as found by the
+/// sources and the options that shape the result, such as the address
+/// family or shuffling, are applied whenever an entry is returned.
+///
+/// An entry expires once the smallest TTL of its addresses has passed.
+/// Entries from sources without TTLs, such as the hosts file, expire after
+/// a minute.
+///
+/// The cache holds at most a given number of entries, 1024 unless created
+/// via `Cache::with_capacity()`. If it is full, adding an entry evicts the
+/// least recently used one, i.e., the one that has gone the longest without
+/// being added or returned by a lookup.
+///
+/// The cache can be shared between threads. Cloning it is cheap and
+/// results in a value sharing the entries and statistics with the
+/// original.
+#[derive(Clone)]
+pub struct Cache(Arc<CacheInner>);
+
+struct CacheInner {
+    entries: Mutex<Entries>,
+
+    /// The maximum number of entries.
+    capacity: usize,
+
+    /// The options for lookups and for returning entries.
+    options: ResolverOptions,
+
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+    evictions: AtomicUsize,
+}
+
+/// How long entries without TTLs are kept in seconds.
+const UNTIMED_TTL: u64 = 60;
+
+/// The maximum number of entries of a cache created via `Cache::new()`.
+const DEFAULT_CAPACITY: usize = 1024;
+
+impl Cache {
+    /// Creates a new, empty cache with the default capacity.
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Creates a new, empty cache holding at most `capacity` entries.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_options(ResolverOptions::default(), capacity)
+    }
+
+    /// Creates a new, empty cache using the given resolver options.
+    ///
+    /// The cache holds at most `capacity` entries.
+    pub fn with_options(options: ResolverOptions, capacity: usize) -> Self {
+        Cache(Arc::new(CacheInner {
+            entries: Mutex::new(Entries::default()),
+            capacity: capacity,
+            options: options,
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+            evictions: AtomicUsize::new(0),
+        }))
+    }
+
+    /// Returns the maximum number of entries of the cache.
+    pub fn capacity(&self) -> usize {
+        self.0.capacity
+    }
+
+    /// Returns the resolver options of the cache.
+    pub fn options(&self) -> &ResolverOptions {
+        &self.0.options
+    }
+
+    /// Returns the entry for a host name if there is an unexpired one.
+    ///
+    /// The options of the cache are applied to the returned entry. If this
+    /// leaves the entry without addresses, `None` is returned.
+    pub fn get(&self, name: &str) -> Option<HostEnt> {
+        let key = cache_key(name);
+        let mut entries = self.entries();
+        let expired = match entries.map.get(&key) {
+            Some(entry) => entry.expires <= Instant::now(),
+            None => {
+                self.0.misses.fetch_add(1, Ordering::Relaxed);
+                return None
+            }
+        };
+        if expired {
+            entries.remove(&key);
+            self.0.evictions.fetch_add(1, Ordering::Relaxed);
+            self.0.misses.fetch_add(1, Ordering::Relaxed);
+            return None
+        }
+        self.0.hits.fetch_add(1, Ordering::Relaxed);
+        entries.touch(&key).clone().finalize(&self.0.options)
+    }
+
+    /// Adds the entry for a host name, replacing any existing one.
+    ///
+    /// The entry should be the one found by the sources, i.e., without the
+    /// resolver options applied.
+    ///
+    /// If the cache is full, the least recently used entry is evicted.
+    /// Entries with a TTL of zero are not added.
+    pub fn insert(&self, name: &str, ent: HostEnt) {
+        let ttl = ent.addr_ttls().iter().filter_map(|item| item.1).min()
+                     .unwrap_or(Duration::from_secs(UNTIMED_TTL));
+        if ttl == Duration::from_secs(0) || self.0.capacity == 0 {
+            return
+        }
+        let key = cache_key(name);
+        let mut entries = self.entries();
+        if entries.remove(&key).is_none()
+                && entries.map.len() >= self.0.capacity {
+            entries.evict_oldest();
+            self.0.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+        entries.insert(key, ent, Instant::now() + ttl);
+    }
+
+    /// Returns the current statistics of the cache.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.0.hits.load(Ordering::Relaxed) as u64,
+            misses: self.0.misses.load(Ordering::Relaxed) as u64,
+            evictions: self.0.evictions.load(Ordering::Relaxed) as u64,
+            entries: self.entries().map.len(),
+        }
+    }
+
+    /// Starts a lookup for a host name using the cache.
+    ///
+    /// If the cache has an entry for the name, the returned future resolves
+    /// into it right away. Otherwise, the name is looked up like by
+    /// `poll_host_by_name_with()` using the options of the cache and a
+    /// successful result is added to the cache.
+    pub fn poll_host_by_name(&self, name: &str, reactor: &reactor::Handle)
+                             -> CachedHostByName {
+        CachedHostByName {
+            cache: self.clone(),
+            name: name.into(),
+            state: match self.get(name) {
+                Some(ent) => Err(Some(ent)),
+                None => {
+                    Ok(HostByName::with_options(name, &self.0.options,
+                                                reactor).raw())
+                }
+            }
+        }
+    }
+
+    /// Returns the locked entries.
+    ///
+    /// The cache stays usable even if a thread panicked while holding the
+    /// lock since the entries can’t be left in an inconsistent state.
+    fn entries(&self) -> MutexGuard<Entries> {
+        match self.0.entries.lock() {
+            Ok(entries) => entries,
+            Err(err) => err.into_inner(),
+        }
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+//------------ Entries -------------------------------------------------------
+
+/// The entries of a cache in order of their use.
+#[derive(Default)]
+struct Entries {
+    /// The entries keyed by normalized name.
+    map: HashMap<String, Entry>,
+
+    /// The keys of the entries keyed by the tick of their last use.
+    order: BTreeMap<u64, String>,
+
+    /// The tick for the next use of an entry.
+    next_tick: u64,
+}
+
+struct Entry {
+    ent: HostEnt,
+    expires: Instant,
+
+    /// The tick of the last use of the entry.
+    tick: u64,
+}
+
+impl Entries {
+    /// Adds an entry that mustn’t be present yet.
+    fn insert(&mut self, key: String, ent: HostEnt, expires: Instant) {
+        let tick = self.tick();
+        self.order.insert(tick, key.clone());
+        self.map.insert(key, Entry { ent: ent, expires: expires, tick: tick });
+    }
+
+    /// Removes an entry and returns it if it was present.
+    fn remove(&mut self, key: &str) -> Option<Entry> {
+        let entry = self.map.remove(key)?;
+        self.order.remove(&entry.tick);
+        Some(entry)
+    }
+
+    /// Marks an entry that must be present as used and returns it.
+    fn touch(&mut self, key: &str) -> &HostEnt {
+        let tick = self.tick();
+        let entry = self.map.get_mut(key).unwrap();
+        let key = self.order.remove(&entry.tick).unwrap();
+        self.order.insert(tick, key);
+        entry.tick = tick;
+        &entry.ent
+    }
+
+    /// Removes the least recently used entry.
+    fn evict_oldest(&mut self) {
+        let tick = match self.order.keys().next() {
+            Some(tick) => *tick,
+            None => return
+        };
+        if let Some(key) = self.order.remove(&tick) {
+            self.map.remove(&key);
+        }
+    }
+
+    /// Returns the tick for a new use.
+    fn tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+}
+
+
+//------------ CacheStats ----------------------------------------------------
+
+/// Statistics of a cache.
+///
+/// The counters start at zero when the cache is created.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct CacheStats {
+    /// The number of lookups answered from the cache.
+    pub hits: u64,
+
+    /// The number of lookups not answered from the cache.
+    pub misses: u64,
+
+    /// The number of entries removed because they expired or to make room
+    /// for new entries.
+    pub evictions: u64,
+
+    /// The number of entries currently in the cache.
+    ///
+    /// This includes expired entries that haven’t been removed yet.
+    pub entries: usize,
+}
+
+
+//------------ CachedHostByName ----------------------------------------------
+
+/// The future returned by `Cache::poll_host_by_name()`.
+///
+/// Resolves into a `HostEnt` value if the lookup is successful or `None` if
+/// there is no such name.
+pub struct CachedHostByName {
+    cache: Cache,
+    name: String,
+
+    /// Either the lookup in progress or the entry found in the cache.
+    state: Result<HostByName, Option<HostEnt>>,
+}
+
+impl Future for CachedHostByName {
+    type Item = Option<HostEnt>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            Ok(ref mut lookup) => {
+                let ent = match try_ready!(lookup.poll()) {
+                    Some(ent) => ent,
+                    None => return Ok(Async::Ready(None))
+                };
+                self.cache.insert(&self.name, ent.clone());
+                Ok(Async::Ready(ent.finalize(&self.cache.0.options)))
+            }
+            Err(ref mut ent) => {
+                match ent.take() {
+                    Some(ent) => Ok(Async::Ready(Some(ent))),
+                    None => Err(already_resolved()),
+                }
+            }
+        }
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Returns the key for a host name.
+fn cache_key(name: &str) -> String {
+    let name = if name.len() > 1 { name.trim_right_matches('.') }
+               else { name };
+    name.to_ascii_lowercase()
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::Family;
+
+    #[test]
+    fn cold_and_warm() {
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        let cache = Cache::new();
+        for name in &["192.0.2.1", "192.0.2.1", "192.0.2.2", "192.0.2.1"] {
+            let ent = core.run(cache.poll_host_by_name(name, &handle))
+                          .unwrap().unwrap();
+            assert_eq!(ent.name(), *name);
+        }
+        assert_eq!(cache.stats(), CacheStats {
+            hits: 2, misses: 2, evictions: 0, entries: 2
+        });
+    }
+
+    #[test]
+    fn resolved_twice() {
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        let cache = Cache::new();
+        cache.insert("example.com",
+                     HostEnt::new("example.com".into(), Vec::new(),
+                                  vec!["192.0.2.1".parse().unwrap()]));
+        let mut lookup = cache.poll_host_by_name("example.com", &handle);
+        assert!(core.run(&mut lookup).unwrap().is_some());
+        assert_eq!(lookup.poll().unwrap_err().kind(), io::ErrorKind::Other);
+    }
+
+    #[test]
+    fn normalized_names() {
+        let cache = Cache::new();
+        let ent = HostEnt::new("example.com".into(), Vec::new(),
+                               vec!["192.0.2.1".parse().unwrap()]);
+        cache.insert("Example.COM", ent);
+        assert!(cache.get("example.com.").is_some());
+        assert!(cache.get("EXAMPLE.com").is_some());
+        assert!(cache.get("www.example.com").is_none());
+        assert_eq!(cache.stats().hits, 2);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn options_applied_on_return() {
+        let addrs = vec!["192.0.2.1".parse().unwrap(),
+                         "2001:db8::1".parse().unwrap(),
+                         "192.0.2.2".parse().unwrap()];
+        let v6 = Cache::with_options(
+            ResolverOptions::new().family(Family::V6), DEFAULT_CAPACITY
+        );
+        v6.insert("example.com",
+                  HostEnt::new("example.com".into(), Vec::new(),
+                               addrs.clone()));
+        assert_eq!(v6.get("example.com").unwrap().addrs(), &addrs[1..2]);
+
+        // The entry is stored without the options applied.
+        let first = Cache::with_options(
+            ResolverOptions::new().first_only(true), DEFAULT_CAPACITY
+        );
+        first.insert("example.com",
+                     HostEnt::new("example.com".into(), Vec::new(),
+                                  addrs.clone()));
+        assert_eq!(first.get("example.com").unwrap().addrs(),
+                   &addrs[..1]);
+        assert_eq!(first.entries().map["example.com"].ent.addrs(),
+                   addrs.as_slice());
+    }
+
+    #[test]
+    fn expired() {
+        let cache = Cache::new();
+        let mut ent = HostEnt::new("example.com".into(), Vec::new(),
+                                   vec!["192.0.2.1".parse().unwrap()]);
+        ent.ttls[0].1 = Some(Duration::from_secs(0));
+        cache.insert("example.com", ent);
+        assert_eq!(cache.stats().entries, 0);
+
+        let mut ent = HostEnt::new("example.com".into(), Vec::new(),
+                                   vec!["192.0.2.1".parse().unwrap()]);
+        ent.ttls[0].1 = Some(Duration::from_secs(3600));
+        cache.insert("example.com", ent);
+        cache.0.entries.lock().unwrap().map.get_mut("example.com").unwrap()
+             .expires = Instant::now();
+        assert!(cache.get("example.com").is_none());
+        assert_eq!(cache.stats(), CacheStats {
+            hits: 0, misses: 1, evictions: 1, entries: 0
+        });
+    }
+
+    #[test]
+    fn least_recently_used() {
+        let cache = Cache::with_capacity(3);
+        let insert = |name: &str| {
+            let addrs = vec!["192.0.2.1".parse().unwrap()];
+            cache.insert(name, HostEnt::new(name.into(), Vec::new(), addrs))
+        };
+        for name in &["one", "two", "three"] {
+            insert(name)
+        }
+        assert!(cache.get("one").is_some());
+        insert("four");
+        insert("five");
+        assert!(cache.get("two").is_none());
+        assert!(cache.get("three").is_none());
+        assert!(cache.get("one").is_some());
+        assert!(cache.get("four").is_some());
+        assert!(cache.get("five").is_some());
+        assert_eq!(cache.stats(), CacheStats {
+            hits: 4, misses: 2, evictions: 2, entries: 3
+        });
+
+        // Replacing an entry doesn’t evict another one.
+        insert("five");
+        assert_eq!(cache.stats().evictions, 2);
+    }
+}
@@ -0,0 +1,274 @@
+//! Cancelling lookups.
+
+use std::io;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use futures::{Async, Future, Poll};
+use futures::task::{self, Task};
+use tokio_core::reactor;
+use super::{HostByName, HostEnt, ResolverOptions};
+
+
+//------------ CancelToken ---------------------------------------------------
+
+/// A signal to abort lookups.
+///
+/// A token is handed to lookups started via
+/// `poll_host_by_name_cancellable()`. Once `cancel()` has been called, all
+/// these lookups resolve into an error of kind `Interrupted`, including
+/// lookups started later with the same token.
+///
+/// Cloning a token is cheap and results in a value sharing the signal with
+/// the original, so a clone can be moved to wherever cancellation is
+/// decided, e.g., another thread.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<CancelInner>);
+
+#[derive(Default)]
+struct CancelInner {
+    cancelled: AtomicBool,
+
+    /// The tasks to wake up when cancelling.
+    tasks: Mutex<Tasks>,
+}
+
+/// The tasks of the pending lookups using a token.
+///
+/// Each lookup has its own registration which it keeps updating with its
+/// current task and removes once it is done or dropped. This way, there is
+/// at most one task per pending lookup.
+#[derive(Default)]
+struct Tasks {
+    /// The current task of each registration.
+    map: HashMap<usize, Task>,
+
+    /// The key for the next registration.
+    next_key: usize,
+}
+
+impl CancelToken {
+    /// Creates a new token that hasn’t been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels all lookups using the token.
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        for (_, task) in self.tasks().map.drain() {
+            task.notify()
+        }
+    }
+
+    /// Returns whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns whether the token has been cancelled.
+    ///
+    /// If it hasn’t, the current task is stored in the registration
+    /// `key`, creating it if necessary, and woken up once it is.
+    fn poll_cancelled(&self, key: &mut Option<usize>) -> bool {
+        if self.is_cancelled() {
+            return true
+        }
+        {
+            let mut tasks = self.tasks();
+            let new_key = match *key {
+                Some(key) => key,
+                None => {
+                    tasks.next_key += 1;
+                    tasks.next_key
+                }
+            };
+            tasks.map.insert(new_key, task::current());
+            *key = Some(new_key);
+        }
+        // Check again in case we missed `cancel()` draining the tasks.
+        self.is_cancelled()
+    }
+
+    /// Removes the registration `key` if there is one.
+    fn unregister(&self, key: &mut Option<usize>) {
+        if let Some(key) = key.take() {
+            self.tasks().map.remove(&key);
+        }
+    }
+
+    /// Returns the locked tasks.
+    fn tasks(&self) -> MutexGuard<Tasks> {
+        match self.0.tasks.lock() {
+            Ok(tasks) => tasks,
+            Err(err) => err.into_inner(),
+        }
+    }
+}
+
+
+//------------ CancellableHostByName -----------------------------------------
+
+/// The future returned by `poll_host_by_name_cancellable()`.
+///
+/// Resolves into a `HostEnt` value if the lookup is successful or `None` if
+/// there is no such name. If the token is cancelled before the lookup
+/// completes, resolves into an error of kind `Interrupted`.
+pub struct CancellableHostByName {
+    lookup: HostByName,
+    token: CancelToken,
+
+    /// The key of our registration with the token, if we have one.
+    registration: Option<usize>,
+}
+
+impl CancellableHostByName {
+    /// Starts a lookup that can be cancelled via `token`.
+    pub fn new(name: &str, reactor: &reactor::Handle, token: &CancelToken)
+               -> Self {
+        Self::with_options(name, &ResolverOptions::default(), reactor, token)
+    }
+
+    /// Starts a lookup using the given options.
+    pub fn with_options(name: &str, options: &ResolverOptions,
+                        reactor: &reactor::Handle, token: &CancelToken)
+                        -> Self {
+        CancellableHostByName {
+            lookup: HostByName::with_options(name, options, reactor),
+            token: token.clone(),
+            registration: None,
+        }
+    }
+}
+
+impl Future for CancellableHostByName {
+    type Item = Option<HostEnt>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.token.poll_cancelled(&mut self.registration) {
+            self.token.unregister(&mut self.registration);
+            return Err(io::Error::new(io::ErrorKind::Interrupted,
+                                      "lookup cancelled"))
+        }
+        match self.lookup.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            res => {
+                self.token.unregister(&mut self.registration);
+                res
+            }
+        }
+    }
+}
+
+impl Drop for CancellableHostByName {
+    fn drop(&mut self) {
+        self.token.unregister(&mut self.registration)
+    }
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::{thread, time};
+    use std::net::IpAddr;
+    use futures::future;
+    use ::nsswitch::Conf;
+    use super::*;
+    use super::super::{NameSource, SourceRegistry};
+
+    /// A source that never answers.
+    struct SilentSource;
+
+    impl NameSource for SilentSource {
+        fn lookup_name(&self, _name: &str, _reactor: &reactor::Handle)
+                       -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+            Box::new(future::empty())
+        }
+
+        fn lookup_addr(&self, _addr: IpAddr, _reactor: &reactor::Handle)
+                       -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+            Box::new(future::empty())
+        }
+    }
+
+    #[test]
+    fn not_cancelled() {
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        let token = CancelToken::new();
+        let ent = core.run(CancellableHostByName::new("192.0.2.1", &handle,
+                                                      &token))
+                      .unwrap().unwrap();
+        assert_eq!(ent.name(), "192.0.2.1");
+        assert!(!token.is_cancelled());
+        assert!(token.tasks().map.is_empty());
+    }
+
+    #[test]
+    fn cancelled() {
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        let token = CancelToken::new();
+        token.cancel();
+        let err = core.run(CancellableHostByName::new("192.0.2.1", &handle,
+                                                      &token))
+                      .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    }
+
+    #[test]
+    fn one_registration_per_lookup() {
+        let conf = Conf::parse(&mut io::Cursor::new(
+            "hosts: silent\n"
+        )).unwrap();
+        let mut sources = SourceRegistry::new();
+        sources.register("silent", SilentSource);
+        let options = ResolverOptions::new().nsswitch_conf(conf)
+                                            .sources(sources);
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        let token = CancelToken::new();
+        let mut lookups: Vec<_> = (0..2).map(|_| {
+            CancellableHostByName::with_options(
+                "silent.netdb.invalid", &options, &handle, &token
+            )
+        }).collect();
+        core.run(future::lazy(|| {
+            for _ in 0..10 {
+                for lookup in &mut lookups {
+                    assert!(lookup.poll().unwrap().is_not_ready());
+                }
+            }
+            assert_eq!(token.tasks().map.len(), 2);
+            lookups.pop();
+            assert_eq!(token.tasks().map.len(), 1);
+            Ok::<(), ()>(())
+        })).unwrap();
+    }
+
+    #[test]
+    fn cancelled_while_pending() {
+        let conf = Conf::parse(&mut io::Cursor::new(
+            "hosts: silent\n"
+        )).unwrap();
+        let mut sources = SourceRegistry::new();
+        sources.register("silent", SilentSource);
+        let options = ResolverOptions::new().nsswitch_conf(conf)
+                                            .sources(sources);
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        let token = CancelToken::new();
+        let canceller = token.clone();
+        thread::spawn(move || {
+            thread::sleep(time::Duration::from_millis(50));
+            canceller.cancel()
+        });
+        let err = core.run(CancellableHostByName::with_options(
+            "silent.netdb.invalid", &options, &handle, &token
+        )).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    }
+}
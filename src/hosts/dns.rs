@@ -1,25 +1,257 @@
 //! The dns source for the hosts database.
 
 use std::{io, mem};
-use std::net::IpAddr;
-use domain::bits::DNameSlice;
-use domain::resolv::Resolver;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::str::FromStr;
+use std::time::Duration;
+use domain::bits::{DNameBuf, DNameSlice, MessageBuf, ParsedDName};
+use domain::iana::{Class, Rcode, Rtype};
+use domain::rdata::{A, Aaaa, Cname};
+use domain::resolv::{Query, Resolver};
 use domain::resolv::error::Error;
-use domain::resolv::lookup::host::{LookupHost, lookup_host};
 use domain::resolv::lookup::addr::{LookupAddr, lookup_addr};
 use futures::{Async, Future, Poll};
 use tokio_core::reactor;
-use super::HostEnt;
+use ::dns::{already_resolved, io_error};
+use ::resolv::ResolvConf;
+use super::{host_name, is_local_only, Family, HostEnt, Preference, QueryOrder,
+            ResolverOptions};
+
+
+//------------ resolver ------------------------------------------------------
+
+/// Returns the resolver configuration for a lookup with the given options.
+pub fn resolv_conf(options: &ResolverOptions) -> ResolvConf {
+    match options.resolv_conf {
+        Some(ref conf) => conf.clone(),
+        None => ResolvConf::system(),
+    }
+}
+
+/// Returns the resolver configuration for looking up the host name `name`.
+///
+/// This is the split DNS configuration for the name if the options have
+/// one and the general configuration otherwise.
+pub fn resolv_conf_for(options: &ResolverOptions, name: &str)
+                       -> ResolvConf {
+    match options.split_dns_conf(name) {
+        Some(conf) => conf.clone(),
+        None => resolv_conf(options),
+    }
+}
+
+/// Creates the resolver for a lookup with the given options.
+///
+/// If `conf` has no name servers, the resolver uses the one on the local
+/// host unless the options require name servers, in which case this fails.
+pub fn resolver(options: &ResolverOptions, conf: &ResolvConf,
+                reactor: &reactor::Handle) -> io::Result<Resolver> {
+    if options.tls_server.is_some() {
+        return Err(io::Error::new(io::ErrorKind::Other,
+                                  "DNS-over-TLS is not supported by the \
+                                   resolver"))
+    }
+    if options.require_nameservers && conf.servers().is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotConnected,
+                                  "no name servers configured"))
+    }
+    Ok(Resolver::from_conf(reactor, conf.to_domain_conf()))
+}
 
 
 //------------ HostByName ----------------------------------------------------
 
-pub struct HostByName(Result<LookupHost, Option<io::Error>>);
+/// A DNS lookup of the addresses for a host name.
+///
+/// Normally, queries for A and AAAA records are started in parallel and the
+/// future resolves once both are done. If the options ask for no IPv6, the
+/// AAAA query is skipped. The options’ query order can ask for the queries
+/// to be asked one after another, starting with the preferred family,
+/// optionally skipping the second query if the first one found addresses.
+///
+/// If the options ask for the first address only, the future resolves as
+/// soon as a query returns addresses. If, additionally, a family is
+/// preferred, the queries are asked as with `QueryOrder::Fallback`.
+///
+/// The host name is expanded using the search list of the resolver
+/// configuration as described with `search_names()`. The candidate names
+/// are tried in turn until the queries for one of them return addresses.
+///
+/// The addresses found are ordered according to the sortlist of the
+/// resolver configuration.
+pub struct HostByName {
+    resolver: Resolver,
+
+    /// The resolver configuration.
+    conf: ResolvConf,
+
+    /// The names to query in order.
+    names: Vec<DNameBuf>,
+
+    /// The index of the name currently queried.
+    pos: usize,
+
+    /// The queries in the order they are to be asked.
+    queries: Vec<(Rtype, MaybeDone)>,
+
+    /// Ask the queries one after another rather than all at once.
+    sequential: bool,
+
+    /// Stop at the first query that returns addresses.
+    stop_early: bool,
+
+    /// Collect the aliases from the CNAME records of the answers.
+    collect_aliases: bool,
+
+    /// The maximum number of records of a type used from an answer.
+    max_answer_records: usize,
+}
 
 impl HostByName {
-    pub fn new<N: AsRef<DNameSlice>>(name: N, reactor: &reactor::Handle)
-                                     -> Self {
-        HostByName(Ok(lookup_host(Resolver::new(reactor), name)))
+    pub fn new(name: &str, options: &ResolverOptions,
+               reactor: &reactor::Handle) -> io::Result<Self> {
+        let conf = resolv_conf_for(options, name);
+        let resolver = resolver(options, &conf, reactor)?;
+        Self::with_resolver(name, options, &conf, resolver)
+    }
+
+    /// Creates a lookup using an existing resolver.
+    ///
+    /// The resolver configuration `conf` provides the search list.
+    pub fn with_resolver(name: &str, options: &ResolverOptions,
+                         conf: &ResolvConf, resolver: Resolver)
+                         -> io::Result<Self> {
+        let names = search_names(name, conf);
+        if names.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "invalid host name"))
+        }
+        let a = if options.family.has_v4() { MaybeDone::Waiting }
+                else { MaybeDone::Skipped };
+        let aaaa = if options.no_ipv6 || !options.family.has_v6()
+                      || !ipv6_wanted(options) {
+            MaybeDone::Skipped
+        }
+        else {
+            MaybeDone::Waiting
+        };
+        let queries = match options.preference {
+            Preference::PreferV6 => {
+                vec![(Rtype::Aaaa, aaaa), (Rtype::A, a)]
+            }
+            _ => vec![(Rtype::A, a), (Rtype::Aaaa, aaaa)]
+        };
+        Ok(HostByName {
+            resolver: resolver,
+            conf: conf.clone(),
+            names: names,
+            pos: 0,
+            queries: queries,
+            sequential: options.query_order != QueryOrder::Parallel
+                        || (options.first_only
+                            && options.preference != Preference::Any)
+                        || single_request(options, conf),
+            stop_early: options.first_only
+                        || options.query_order == QueryOrder::Fallback,
+            collect_aliases: options.collect_aliases,
+            max_answer_records: options.max_answer_records,
+        })
+    }
+
+    /// Finishes the queries for the current name.
+    ///
+    /// Queries that haven’t completed are dropped.
+    fn finish(&mut self)
+              -> Result<Option<(HostEnt, Vec<MessageBuf>)>, io::Error> {
+        let answers: Vec<_> = self.queries.iter_mut()
+            .filter_map(|&mut (rtype, ref mut query)| {
+                query.take().map(|answer| (rtype, answer))
+            }).collect();
+        let mut res = from_answers(&self.names[self.pos], answers,
+                                   self.collect_aliases,
+                                   self.max_answer_records)?;
+        if let Some((ref mut ent, _)) = res {
+            apply_sortlist(ent, &self.conf)
+        }
+        Ok(res)
+    }
+
+    /// Moves on to the next name if there is one.
+    fn next_name(&mut self) -> bool {
+        if self.pos + 1 >= self.names.len() {
+            return false
+        }
+        self.pos += 1;
+        for &mut (_, ref mut query) in &mut self.queries {
+            if let MaybeDone::Skipped = *query { }
+            else {
+                *query = MaybeDone::Waiting
+            }
+        }
+        true
+    }
+}
+
+/// # Raw Answers
+///
+impl HostByName {
+    /// Polls for the host entry and the answers it was created from.
+    ///
+    /// Once the lookup has resolved, polling it again results in an error.
+    fn poll_raw(&mut self)
+                -> Poll<Option<(HostEnt, Vec<MessageBuf>)>, io::Error> {
+        if self.pos >= self.names.len() {
+            return Err(already_resolved())
+        }
+        let res = self.poll_names();
+        match res {
+            Ok(Async::NotReady) => { }
+            _ => self.pos = self.names.len()
+        }
+        res
+    }
+
+    /// Polls the queries for the names in turn.
+    fn poll_names(&mut self)
+                  -> Poll<Option<(HostEnt, Vec<MessageBuf>)>, io::Error> {
+        loop {
+            let mut all_done = true;
+            for &mut (rtype, ref mut query) in &mut self.queries {
+                if let MaybeDone::Waiting = *query {
+                    if self.sequential && !all_done {
+                        break
+                    }
+                    *query = MaybeDone::NotYet(
+                        self.resolver.query((&self.names[self.pos], rtype,
+                                             Class::In))
+                    );
+                }
+                if !query.poll() {
+                    all_done = false;
+                    continue
+                }
+                if self.stop_early && query.has_addrs(rtype) {
+                    all_done = true;
+                    break
+                }
+            }
+            if !all_done {
+                return Ok(Async::NotReady)
+            }
+            match self.finish()? {
+                Some(res) => return Ok(Async::Ready(Some(res))),
+                None => {
+                    if !self.next_name() {
+                        return Ok(Async::Ready(None))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Converts the lookup into one that keeps the DNS answers.
+    pub fn raw(self) -> RawHostByName {
+        RawHostByName(self)
     }
 }
 
@@ -28,29 +260,376 @@ impl Future for HostByName {
     type Error = io::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        match self.0 {
-            Ok(ref mut lookup) => {
-                match lookup.poll() {
-                    Ok(Async::Ready(found)) => {
-                        Ok(Async::Ready(Some(HostEnt {
-                            name: format!("{}", found.canonical_name()),
-                            aliases: Vec::new(),
-                            addrs: found.iter().collect(),
-                        })))
-                    }
-                    Ok(Async::NotReady) => Ok(Async::NotReady),
-                    Err(Error::Question(err))
-                        => panic!("Question error: {}", err),
-                    Err(Error::Io(err)) => Err(err),
-                    _ => Ok(Async::Ready(None)),
-                }
+        let res = try_ready!(self.poll_raw());
+        Ok(Async::Ready(res.map(|(ent, _)| ent)))
+    }
+}
+
+
+//------------ RawHostByName -------------------------------------------------
+
+/// A DNS lookup of a host name keeping the answers.
+///
+/// This is identical to `HostByName` except that it resolves into the
+/// answer messages the host entry was created from, too.
+pub struct RawHostByName(HostByName);
+
+impl Future for RawHostByName {
+    type Item = Option<(HostEnt, Vec<MessageBuf>)>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.0.poll_raw()
+    }
+}
+
+/// Returns whether queries are to be asked one after another.
+///
+/// This is the case if the resolver configuration asks for it or if the
+/// name server is systemd-resolved’s stub resolver and the options allow
+/// adjusting to it.
+fn single_request(options: &ResolverOptions, conf: &ResolvConf) -> bool {
+    conf.single_request() || (options.systemd_stub && conf.is_systemd_stub())
+}
+
+/// Returns whether AAAA queries are worthwhile according to the probe.
+fn ipv6_wanted(options: &ResolverOptions) -> bool {
+    if options.family == Family::V6 {
+        return true
+    }
+    match options.ipv6_probe {
+        Some(probe) => probe(),
+        None => true
+    }
+}
+
+/// Returns whether the host has IPv6 connectivity.
+///
+/// This connects an unbound UDP socket to a global IPv6 address, which
+/// doesn’t send any packets but makes the system pick a source address.
+/// If this fails, there is no route. If the source address is loopback or
+/// link-local, there is no usable global address.
+pub fn has_ipv6_connectivity() -> bool {
+    let probe = SocketAddr::new(
+        IpAddr::V6(Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888)),
+        53
+    );
+    let sock = match UdpSocket::bind("[::]:0") {
+        Ok(sock) => sock,
+        Err(_) => return false
+    };
+    if sock.connect(probe).is_err() {
+        return false
+    }
+    match sock.local_addr() {
+        Ok(addr) => !is_local_only(&addr.ip()),
+        Err(_) => false
+    }
+}
+
+/// Returns the names to query for a host name in order.
+///
+/// This follows glibc: A name ending in a dot is absolute and is queried
+/// as is without applying the search list. Otherwise, the name is combined
+/// with each domain of the search list in turn. The name itself is tried
+/// before the search list if it contains at least as many dots as the
+/// `ndots` option demands and after it otherwise.
+///
+/// Since domain names are case-insensitive, the names are converted to
+/// lowercase ASCII so that the queries for a name are the same however it
+/// was spelled. The canonical name of a host entry still has the casing
+/// used by the server in its CNAME records.
+///
+/// Names that aren’t valid domain names are dropped.
+pub fn search_names(name: &str, conf: &ResolvConf) -> Vec<DNameBuf> {
+    let name = name.to_ascii_lowercase();
+    let name = name.as_str();
+    if name.ends_with('.') {
+        return DNameBuf::from_str(name).into_iter().collect()
+    }
+    let as_is = DNameBuf::from_str(&format!("{}.", name)).ok();
+    let as_is_first = name.matches('.').count() >= conf.ndots();
+    let mut res = Vec::new();
+    if as_is_first {
+        res.extend(as_is.clone())
+    }
+    for suffix in conf.search() {
+        let suffix = format!("{}", suffix).to_ascii_lowercase();
+        let candidate = format!("{}.{}.", name,
+                                suffix.trim_right_matches('.'));
+        if let Ok(candidate) = DNameBuf::from_str(&candidate) {
+            res.push(candidate)
+        }
+    }
+    if !as_is_first {
+        res.extend(as_is)
+    }
+    res
+}
+
+/// Creates a host entry from the answers to the address queries.
+///
+/// The answers are given as pairs of the record type asked for and the
+/// result of the query. If the answers don’t contain a canonical name,
+/// `qname` is used instead. The entry is marked as authenticated only if
+/// all answers that contributed addresses had the AD bit set.
+///
+/// If some of the queries failed, the addresses from the others are used.
+/// If no query returned addresses, the result is an error only if all of
+/// them failed. Otherwise, at least one query has definitively answered
+/// that there are no addresses and the result is `None`. A query failed
+/// if it resulted in an error other than a non-existing name or in a
+/// response with a response code indicating a server side problem.
+///
+/// If `collect_aliases` is true, the owner names of the CNAME records in
+/// the answers that contributed addresses become the aliases of the entry.
+///
+/// At most `limit` address records and `limit` CNAME records are used from
+/// each answer. If an answer has more, the entry is marked as truncated.
+/// If the CNAME records of an answer contributing addresses form a chain
+/// longer than `MAX_CNAME_CHAIN` or a loop, the result is an error of kind
+/// `InvalidData`.
+///
+/// Returns the entry together with the answers that contributed addresses.
+fn from_answers<I>(qname: &DNameSlice, answers: I, collect_aliases: bool,
+                   limit: usize)
+                   -> Result<Option<(HostEnt, Vec<MessageBuf>)>, io::Error>
+                where I: IntoIterator<Item=(Rtype, Result<MessageBuf, Error>)> {
+    let mut name = None;
+    let mut addrs = Vec::new();
+    let mut aliases = Vec::new();
+    let mut messages = Vec::new();
+    let mut authenticated = true;
+    let mut answered = false;
+    let mut truncated = false;
+    let mut err = None;
+    for (rtype, answer) in answers {
+        let msg = match answer {
+            Ok(msg) => msg,
+            Err(Error::NoName) => { answered = true; continue }
+            Err(e) => { err = Some(io_error(e)); continue }
+        };
+        match msg.header().rcode() {
+            Rcode::NoError | Rcode::NXDomain => answered = true,
+            rcode => {
+                err = Some(io::Error::new(io::ErrorKind::Other,
+                                          format!("query failed: {}",
+                                                  rcode)));
+                continue
             }
-            Err(ref mut inner) => {
-                match mem::replace(inner, None) {
-                    Some(err) => Err(err),
-                    None => panic!("polling a resolved HostByName"),
+        }
+        let count = addrs.len();
+        truncated |= push_addrs(&msg, rtype, limit, &mut addrs);
+        if addrs.len() > count {
+            authenticated = authenticated && msg.header().ad();
+            let cname = canonical_name_of(&msg)?;
+            if name.is_none() {
+                name = cname;
+            }
+            if collect_aliases {
+                truncated |= push_aliases(&msg, limit, &mut aliases);
+            }
+            messages.push(msg);
+        }
+    }
+    if addrs.is_empty() {
+        return match err {
+            Some(err) if !answered => Err(err),
+            _ => Ok(None)
+        }
+    }
+    Ok(Some((HostEnt {
+        name: name.unwrap_or_else(|| host_name(qname)),
+        aliases: aliases,
+        addrs: addrs.iter().map(|item| item.0).collect(),
+        ttls: addrs.into_iter().map(|(addr, ttl)| (addr, Some(ttl)))
+                   .collect(),
+        files: Vec::new(),
+        authenticated: authenticated,
+        resolved_via: Some(host_name(qname)),
+        truncated: truncated,
+    }, messages)))
+}
+
+/// Appends the owner names of the CNAME records in the answer of `msg`.
+///
+/// Names already present are skipped, as are records that fail to parse.
+/// At most `limit` records are looked at. Returns whether there were more.
+fn push_aliases(msg: &MessageBuf, limit: usize, aliases: &mut Vec<String>)
+                -> bool {
+    let section = match msg.answer() {
+        Ok(section) => section,
+        Err(_) => return false
+    };
+    for (i, record) in section.limit_to::<Cname<ParsedDName>>().enumerate() {
+        if i >= limit {
+            return true
+        }
+        if let Ok(record) = record {
+            let name = host_name(record.name());
+            if !aliases.contains(&name) {
+                aliases.push(name)
+            }
+        }
+    }
+    false
+}
+
+/// Returns the canonical name for the question of `msg`.
+///
+/// Starting with the question name, the CNAME records in the answer are
+/// followed for at most `MAX_CNAME_CHAIN` steps. If the chain is longer,
+/// which includes any loop, the result is an error of kind `InvalidData`.
+/// Records that fail to parse are skipped. Returns `None` if the message
+/// has no question.
+fn canonical_name_of(msg: &MessageBuf) -> io::Result<Option<String>> {
+    let mut name = match msg.first_question() {
+        Some(question) => host_name(question.qname()),
+        None => return Ok(None)
+    };
+    let cnames: Vec<_> = match msg.answer() {
+        Ok(section) => {
+            section.limit_to::<Cname<ParsedDName>>()
+                   .filter_map(Result::ok)
+                   .map(|record| {
+                       (host_name(record.name()),
+                        host_name(record.data().cname()))
+                   }).collect()
+        }
+        Err(_) => Vec::new()
+    };
+    let mut steps = 0;
+    while let Some(&(_, ref target)) = cnames.iter().find(|item| {
+        item.0.eq_ignore_ascii_case(&name)
+    }) {
+        if steps == MAX_CNAME_CHAIN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "CNAME loop or chain too long"))
+        }
+        name = target.clone();
+        steps += 1;
+    }
+    Ok(Some(name))
+}
+
+/// Orders the addresses of `ent` according to the sortlist of `conf`.
+///
+/// The sort is stable, so addresses with the same rank keep the order they
+/// were received in.
+fn apply_sortlist(ent: &mut HostEnt, conf: &ResolvConf) {
+    if conf.sortlist().is_empty() {
+        return
+    }
+    ent.ttls.sort_by_key(|item| conf.sortlist_rank(&item.0));
+    ent.addrs = ent.ttls.iter().map(|item| item.0).collect();
+}
+
+/// Appends the addresses of the given type in the answer section of `msg`.
+///
+/// Each address is paired with the TTL of its record. Records that fail to
+/// parse are skipped. At most `limit` records are looked at. Returns
+/// whether there were more.
+fn push_addrs(msg: &MessageBuf, rtype: Rtype, limit: usize,
+              addrs: &mut Vec<(IpAddr, Duration)>) -> bool {
+    let section = match msg.answer() {
+        Ok(section) => section,
+        Err(_) => return false
+    };
+    if rtype == Rtype::A {
+        for (i, record) in section.limit_to::<A>().enumerate() {
+            if i >= limit {
+                return true
+            }
+            if let Ok(record) = record {
+                addrs.push((IpAddr::V4(record.data().addr()),
+                            Duration::from_secs(record.ttl().into())))
+            }
+        }
+    }
+    else {
+        for (i, record) in section.limit_to::<Aaaa>().enumerate() {
+            if i >= limit {
+                return true
+            }
+            if let Ok(record) = record {
+                addrs.push((IpAddr::V6(record.data().addr()),
+                            Duration::from_secs(record.ttl().into())))
+            }
+        }
+    }
+    false
+}
+
+
+//------------ MaybeDone -----------------------------------------------------
+
+/// A DNS query that may or may not have completed yet.
+enum MaybeDone {
+    /// The query hasn’t been started yet.
+    Waiting,
+
+    /// The query is in progress.
+    NotYet(Query),
+
+    /// The query has completed.
+    Done(Result<MessageBuf, Error>),
+
+    /// The query is not to be asked at all.
+    Skipped,
+
+    /// The result of the query has been taken.
+    Gone,
+}
+
+impl MaybeDone {
+    /// Polls the query and returns whether it has completed.
+    fn poll(&mut self) -> bool {
+        let res = match *self {
+            MaybeDone::NotYet(ref mut query) => {
+                match query.poll() {
+                    Ok(Async::NotReady) => return false,
+                    Ok(Async::Ready(msg)) => Ok(msg),
+                    Err(err) => Err(err),
                 }
             }
+            MaybeDone::Done(_) | MaybeDone::Skipped | MaybeDone::Gone => {
+                return true
+            }
+            MaybeDone::Waiting => return false,
+        };
+        *self = MaybeDone::Done(res);
+        true
+    }
+
+    /// Returns whether the query has completed with addresses of `rtype`.
+    fn has_addrs(&self, rtype: Rtype) -> bool {
+        match *self {
+            MaybeDone::Done(Ok(ref msg)) => {
+                let mut addrs = Vec::new();
+                push_addrs(msg, rtype, 1, &mut addrs);
+                !addrs.is_empty()
+            }
+            _ => false
+        }
+    }
+
+    /// Takes the result out of a completed query.
+    ///
+    /// Returns `None` if the query hasn’t completed or was skipped. A
+    /// query still in progress is dropped. A skipped query stays skipped so
+    /// that it isn’t asked for the next name either.
+    fn take(&mut self) -> Option<Result<MessageBuf, Error>> {
+        match mem::replace(self, MaybeDone::Gone) {
+            MaybeDone::Done(res) => Some(res),
+            MaybeDone::Waiting => {
+                *self = MaybeDone::Waiting;
+                None
+            }
+            MaybeDone::Skipped => {
+                *self = MaybeDone::Skipped;
+                None
+            }
+            _ => None,
         }
     }
 }
@@ -64,11 +643,14 @@ pub struct HostByAddr {
 }
 
 impl HostByAddr {
-    pub fn new(addr: IpAddr, reactor: &reactor::Handle) -> Self {
-        HostByAddr {
+    pub fn new(addr: IpAddr, options: &ResolverOptions,
+               reactor: &reactor::Handle) -> io::Result<Self> {
+        Ok(HostByAddr {
             addr: addr,
-            result: Ok(lookup_addr(Resolver::new(reactor), addr))
-        }
+            result: Ok(lookup_addr(
+                resolver(options, &resolv_conf(options), reactor)?, addr
+            ))
+        })
     }
 }
 
@@ -77,36 +659,679 @@ impl Future for HostByAddr {
     type Error = io::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        match self.result {
+        let res = match self.result {
             Ok(ref mut lookup) => {
                 match lookup.poll() {
                     Ok(Async::Ready(found)) => {
                         let mut iter = found.iter();
-                        let name = match iter.next() {
-                            None => return Ok(Async::Ready(None)),
-                            Some(name) => format!("{}", name)
-                        };
-                        Ok(Async::Ready(Some(HostEnt {
-                            name: name,
-                            aliases: iter.map(|n| format!("{}", n)).collect(),
-                            addrs: vec![self.addr],
-                        })))
+                        match iter.next() {
+                            None => Ok(None),
+                            Some(name) => {
+                                Ok(Some(HostEnt::untimed(
+                                    host_name(name),
+                                    iter.map(host_name).collect(),
+                                    vec![self.addr]
+                                )))
+                            }
+                        }
                     }
-                    Ok(Async::NotReady) => Ok(Async::NotReady),
-                    Err(Error::Question(err))
-                        => panic!("Question error: {}", err),
-                    Err(Error::Io(err)) => Err(err),
-                    _ => Ok(Async::Ready(None)),
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(Error::NoName) => Ok(None),
+                    Err(err) => Err(io_error(err)),
                 }
             }
             Err(ref mut inner) => {
-                match mem::replace(inner, None) {
-                    Some(err) => Err(err),
-                    None => panic!("polling a resolved HostByAddr")
+                Err(inner.take().unwrap_or_else(already_resolved))
+            }
+        };
+        self.result = Err(None);
+        res.map(Async::Ready)
+    }
+}
+
+
+//------------ FqdnLookup ----------------------------------------------------
+
+/// A DNS lookup of the canonical name for a host name.
+///
+/// Only A records are asked for. The names produced by the search list are
+/// tried in turn until the answer for one of them contains either addresses
+/// or CNAME records. The name at the end of the CNAME chain of that answer
+/// is the result.
+pub struct FqdnLookup {
+    resolver: Resolver,
+
+    /// The names to query in order.
+    names: Vec<DNameBuf>,
+
+    /// The index of the name currently queried.
+    pos: usize,
+
+    /// The query for the current name if it has been started.
+    query: Option<Query>,
+}
+
+impl FqdnLookup {
+    pub fn new(name: &str, options: &ResolverOptions,
+               reactor: &reactor::Handle) -> io::Result<Self> {
+        let conf = resolv_conf_for(options, name);
+        let resolver = resolver(options, &conf, reactor)?;
+        let names = search_names(name, &conf);
+        if names.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "invalid host name"))
+        }
+        Ok(FqdnLookup {
+            resolver: resolver,
+            names: names,
+            pos: 0,
+            query: None,
+        })
+    }
+}
+
+impl Future for FqdnLookup {
+    type Item = Option<String>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let answer = {
+                let resolver = &self.resolver;
+                let qname = &self.names[self.pos];
+                let query = self.query.get_or_insert_with(|| {
+                    resolver.query((qname, Rtype::A, Class::In))
+                });
+                match query.poll() {
+                    Ok(Async::Ready(msg)) => Ok(msg),
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(err) => Err(err),
                 }
+            };
+            self.query = None;
+            if let Some(name) = fqdn_from(&self.names[self.pos], answer)? {
+                return Ok(Async::Ready(Some(name)))
             }
+            if self.pos + 1 >= self.names.len() {
+                return Ok(Async::Ready(None))
+            }
+            self.pos += 1;
+        }
+    }
+}
+
+/// Determines the canonical name from the answer to an A query for `qname`.
+///
+/// Returns `None` if the name doesn’t exist or the answer has neither
+/// addresses nor CNAME records.
+fn fqdn_from(qname: &DNameSlice, answer: Result<MessageBuf, Error>)
+             -> io::Result<Option<String>> {
+    let msg = match answer {
+        Ok(msg) => msg,
+        Err(Error::NoName) => return Ok(None),
+        Err(err) => return Err(io_error(err)),
+    };
+    match msg.header().rcode() {
+        Rcode::NoError => { }
+        Rcode::NXDomain => return Ok(None),
+        rcode => {
+            return Err(io::Error::new(io::ErrorKind::Other,
+                                      format!("query failed: {}", rcode)))
         }
     }
+    let mut addrs = Vec::new();
+    let mut aliases = Vec::new();
+    push_addrs(&msg, Rtype::A, 1, &mut addrs);
+    push_aliases(&msg, 1, &mut aliases);
+    if addrs.is_empty() && aliases.is_empty() {
+        return Ok(None)
+    }
+    Ok(Some(canonical_name_of(&msg)?.unwrap_or_else(|| host_name(qname))))
 }
 
 
+//------------ Helpers -------------------------------------------------------
+
+/// The maximum number of CNAME records followed to the canonical name.
+const MAX_CNAME_CHAIN: usize = 16;
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use ::hosts::MAX_ANSWER_RECORDS;
+    use super::*;
+
+    /// A response for `example.com. A` with the AD bit set.
+    const AUTHENTICATED_A: &'static [u8] = &[
+        0x00, 0x00, 0x81, 0xa0, 0x00, 0x01, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x00,
+        7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0,
+        0x00, 0x01, 0x00, 0x01,
+        0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10,
+        0x00, 0x04, 192, 0, 2, 1
+    ];
+
+    /// A response for `www.example.com. A` via a CNAME to `example.com.`
+    const CNAME_A: &'static [u8] = &[
+        0x00, 0x00, 0x81, 0x80, 0x00, 0x01, 0x00, 0x02,
+        0x00, 0x00, 0x00, 0x00,
+        3, b'w', b'w', b'w',
+        7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0,
+        0x00, 0x01, 0x00, 0x01,
+        0xc0, 0x0c, 0x00, 0x05, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10,
+        0x00, 0x02, 0xc0, 0x10,
+        0xc0, 0x10, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10,
+        0x00, 0x04, 192, 0, 2, 1
+    ];
+
+    /// A response for `www.example.com. A` with a CNAME loop between
+    /// `www.example.com.` and `example.com.`
+    const CNAME_LOOP: &'static [u8] = &[
+        0x00, 0x00, 0x81, 0x80, 0x00, 0x01, 0x00, 0x03,
+        0x00, 0x00, 0x00, 0x00,
+        3, b'w', b'w', b'w',
+        7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0,
+        0x00, 0x01, 0x00, 0x01,
+        0xc0, 0x0c, 0x00, 0x05, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10,
+        0x00, 0x02, 0xc0, 0x10,
+        0xc0, 0x10, 0x00, 0x05, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10,
+        0x00, 0x02, 0xc0, 0x0c,
+        0xc0, 0x10, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10,
+        0x00, 0x04, 192, 0, 2, 1
+    ];
+
+    /// A response for `www.example.com. A` via a CNAME to `Example.COM.`
+    const MIXED_CASE_CNAME_A: &'static [u8] = &[
+        0x00, 0x00, 0x81, 0x80, 0x00, 0x01, 0x00, 0x02,
+        0x00, 0x00, 0x00, 0x00,
+        3, b'w', b'w', b'w',
+        7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0,
+        0x00, 0x01, 0x00, 0x01,
+        0xc0, 0x0c, 0x00, 0x05, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10,
+        0x00, 0x0d,
+        7, b'E', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'C', b'O', b'M', 0,
+        0xc0, 0x2d, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10,
+        0x00, 0x04, 192, 0, 2, 1
+    ];
+
+    /// A response for `example.com. AAAA` with a TTL of 300 seconds.
+    const SHORT_AAAA: &'static [u8] = &[
+        0x00, 0x00, 0x81, 0x80, 0x00, 0x01, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x00,
+        7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0,
+        0x00, 0x1c, 0x00, 0x01,
+        0xc0, 0x0c, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x00, 0x01, 0x2c,
+        0x00, 0x10, 0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 1
+    ];
+
+    /// A response for `example.com. A` with two addresses.
+    const TWO_A: &'static [u8] = &[
+        0x00, 0x00, 0x81, 0x80, 0x00, 0x01, 0x00, 0x02,
+        0x00, 0x00, 0x00, 0x00,
+        7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0,
+        0x00, 0x01, 0x00, 0x01,
+        0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10,
+        0x00, 0x04, 192, 0, 2, 1,
+        0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10,
+        0x00, 0x04, 192, 0, 2, 2
+    ];
+
+    fn message(data: &[u8]) -> MessageBuf {
+        MessageBuf::from_vec(data.into()).unwrap()
+    }
+
+    #[test]
+    fn authenticated() {
+        let qname = DNameBuf::from_str("example.com.").unwrap();
+        let (ent, answers) = from_answers(
+            &qname, vec![(Rtype::A, Ok(message(AUTHENTICATED_A))),
+                         (Rtype::Aaaa, Err(Error::NoName))],
+            true, MAX_ANSWER_RECORDS
+        ).unwrap().unwrap();
+        assert!(ent.is_authenticated());
+        assert_eq!(ent.addrs(), &["192.0.2.1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(answers.len(), 1);
+        assert!(answers[0].header().ad());
+
+        let mut plain = AUTHENTICATED_A.to_vec();
+        plain[3] = 0x80;
+        let ent = from_answers(&qname,
+                               vec![(Rtype::A, Ok(message(&plain))),
+                                    (Rtype::Aaaa, Err(Error::NoName))],
+                               true, MAX_ANSWER_RECORDS)
+                      .unwrap().unwrap().0;
+        assert!(!ent.is_authenticated());
+    }
+
+    #[test]
+    fn partial_failure() {
+        let qname = DNameBuf::from_str("example.com.").unwrap();
+        let mut servfail = AUTHENTICATED_A.to_vec();
+        servfail[3] = 0xa2;
+        let failed = || {
+            Err(Error::Io(io::Error::new(io::ErrorKind::TimedOut,
+                                         "timed out")))
+        };
+
+        // One family fails, the other has addresses.
+        let ent = from_answers(&qname,
+                               vec![(Rtype::A, Ok(message(AUTHENTICATED_A))),
+                                    (Rtype::Aaaa, failed())],
+                               true, MAX_ANSWER_RECORDS)
+                      .unwrap().unwrap().0;
+        assert_eq!(ent.addrs(), &["192.0.2.1".parse::<IpAddr>().unwrap()]);
+
+        // One family fails, the other definitely has no addresses.
+        assert!(from_answers(&qname,
+                             vec![(Rtype::A, Ok(message(&servfail))),
+                                  (Rtype::Aaaa, Err(Error::NoName))],
+                             true, MAX_ANSWER_RECORDS)
+                    .unwrap().is_none());
+
+        // Both families fail.
+        let err = from_answers(&qname,
+                               vec![(Rtype::A, Ok(message(&servfail))),
+                                    (Rtype::Aaaa, failed())],
+                               true, MAX_ANSWER_RECORDS)
+                      .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn canonical_name() {
+        let qname = DNameBuf::from_str("www.example.com.").unwrap();
+        let ent = from_answers(&qname,
+                               vec![(Rtype::A, Ok(message(CNAME_A)))],
+                               true, MAX_ANSWER_RECORDS)
+                      .unwrap().unwrap().0;
+        assert_eq!(ent.name(), "example.com");
+        assert_eq!(ent.resolved_via(), Some("www.example.com"));
+        assert_eq!(ent.aliases(), ["www.example.com"]);
+    }
+
+    #[test]
+    fn no_nameservers() {
+        let core = reactor::Core::new().unwrap();
+        let conf = ResolvConf::parse(&mut io::Cursor::new(
+            "search example.com\n"
+        )).unwrap();
+        assert!(conf.servers().is_empty());
+        let options = ResolverOptions::new().resolv_conf(conf);
+        assert!(HostByName::new("www", &options, &core.handle()).is_ok());
+        let options = options.require_nameservers(true);
+        let err = HostByName::new("www", &options, &core.handle())
+                             .err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+        let err = HostByAddr::new("192.0.2.1".parse().unwrap(), &options,
+                                  &core.handle()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+    }
+
+    #[test]
+    fn lowercase_query_names() {
+        let conf = ResolvConf::parse(&mut io::Cursor::new(
+            "search Corp.Example\n"
+        )).unwrap();
+        let names = |name: &str| -> Vec<String> {
+            search_names(name, &conf).iter().map(|name| format!("{}", name))
+                                     .collect()
+        };
+        assert_eq!(names("WWW.Example.COM"), names("www.example.com"));
+        assert_eq!(names("Host"), ["host.corp.example.", "host."]);
+
+        let qname = DNameBuf::from_str("www.example.com.").unwrap();
+        let ent = from_answers(&qname,
+                               vec![(Rtype::A,
+                                     Ok(message(MIXED_CASE_CNAME_A)))],
+                               true, MAX_ANSWER_RECORDS)
+                      .unwrap().unwrap().0;
+        assert_eq!(ent.name(), "Example.COM");
+        assert_eq!(ent.addrs(), &["192.0.2.1".parse::<IpAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn cname_loop() {
+        let qname = DNameBuf::from_str("www.example.com.").unwrap();
+        let err = from_answers(&qname,
+                               vec![(Rtype::A, Ok(message(CNAME_LOOP)))],
+                               true, MAX_ANSWER_RECORDS).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fqdn_from(&qname, Ok(message(CNAME_LOOP)))
+                       .unwrap_err().kind(),
+                   io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn answer_limit() {
+        let qname = DNameBuf::from_str("example.com.").unwrap();
+        let ent = from_answers(&qname, vec![(Rtype::A, Ok(message(TWO_A)))],
+                               true, 1)
+                      .unwrap().unwrap().0;
+        assert_eq!(ent.addrs(), &["192.0.2.1".parse::<IpAddr>().unwrap()]);
+        assert!(ent.is_truncated());
+
+        let ent = from_answers(&qname, vec![(Rtype::A, Ok(message(TWO_A)))],
+                               true, 2)
+                      .unwrap().unwrap().0;
+        assert_eq!(ent.addrs().len(), 2);
+        assert!(!ent.is_truncated());
+
+        let qname = DNameBuf::from_str("www.example.com.").unwrap();
+        let ent = from_answers(&qname, vec![(Rtype::A, Ok(message(CNAME_A)))],
+                               true, 1)
+                      .unwrap().unwrap().0;
+        assert_eq!(ent.aliases(), ["www.example.com"]);
+        assert!(!ent.is_truncated());
+    }
+
+    #[test]
+    fn fqdn() {
+        let conf = ResolvConf::parse(&mut io::Cursor::new(
+            "search corp.example example.com\n"
+        )).unwrap();
+        let names = search_names("www", &conf);
+        assert_eq!(names[0], DNameBuf::from_str("www.corp.example.").unwrap());
+        assert_eq!(fqdn_from(&names[0], Err(Error::NoName)).unwrap(), None);
+        assert_eq!(names[1], DNameBuf::from_str("www.example.com.").unwrap());
+        assert_eq!(fqdn_from(&names[1], Ok(message(CNAME_A))).unwrap(),
+                   Some("example.com".into()));
+
+        let qname = DNameBuf::from_str("example.com.").unwrap();
+        assert_eq!(fqdn_from(&qname, Ok(message(AUTHENTICATED_A))).unwrap(),
+                   Some("example.com".into()));
+        assert_eq!(fqdn_from(&qname, Ok(message(SHORT_AAAA))).unwrap(),
+                   None);
+    }
+
+    #[test]
+    fn collect_aliases() {
+        let qname = DNameBuf::from_str("www.example.com.").unwrap();
+        let ent = from_answers(&qname,
+                               vec![(Rtype::A, Ok(message(CNAME_A)))],
+                               false, MAX_ANSWER_RECORDS)
+                      .unwrap().unwrap().0;
+        assert_eq!(ent.name(), "example.com");
+        assert!(ent.aliases().is_empty());
+        assert_eq!(ent.addrs(), &["192.0.2.1".parse::<IpAddr>().unwrap()]);
+
+        let ent = from_answers(&qname,
+                               vec![(Rtype::A, Ok(message(CNAME_A)))],
+                               true, MAX_ANSWER_RECORDS)
+                      .unwrap().unwrap().0;
+        let ent = ent.finalize(&ResolverOptions::new().collect_aliases(false))
+                     .unwrap();
+        assert!(ent.aliases().is_empty());
+        assert_eq!(ent.addrs(), &["192.0.2.1".parse::<IpAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn addr_ttls() {
+        let qname = DNameBuf::from_str("example.com.").unwrap();
+        let ent = from_answers(&qname,
+                               vec![(Rtype::A, Ok(message(AUTHENTICATED_A))),
+                                    (Rtype::Aaaa, Ok(message(SHORT_AAAA)))],
+                               true, MAX_ANSWER_RECORDS)
+                      .unwrap().unwrap().0;
+        assert_eq!(ent.addr_ttls(),
+                   &[("192.0.2.1".parse().unwrap(),
+                      Some(Duration::from_secs(3600))),
+                     ("2001:db8::1".parse().unwrap(),
+                      Some(Duration::from_secs(300)))]);
+    }
+
+    #[test]
+    fn sortlist() {
+        let conf = ResolvConf::parse(&mut io::Cursor::new(
+            "sortlist 198.51.100.0/255.255.255.0 10.0.0.0\n"
+        )).unwrap();
+        let addrs = ["192.0.2.1", "2001:db8::1", "10.1.1.1", "198.51.100.7",
+                     "192.0.2.2"];
+        let mut ent = HostEnt::untimed(
+            "example.com".into(), Vec::new(),
+            addrs.iter().map(|addr| addr.parse().unwrap()).collect()
+        );
+        apply_sortlist(&mut ent, &conf);
+        let expected: Vec<IpAddr> = ["198.51.100.7", "10.1.1.1", "192.0.2.1",
+                                     "2001:db8::1", "192.0.2.2"]
+            .iter().map(|addr| addr.parse().unwrap()).collect();
+        assert_eq!(ent.addrs(), &expected[..]);
+        assert_eq!(ent.addr_ttls().iter().map(|item| item.0)
+                      .collect::<Vec<_>>(),
+                   expected);
+    }
+
+    fn names(names: &[&str]) -> Vec<DNameBuf> {
+        names.iter().map(|name| DNameBuf::from_str(name).unwrap()).collect()
+    }
+
+    #[test]
+    fn search_list() {
+        let conf = ResolvConf::parse(&mut io::Cursor::new(
+            "search corp.example lab.example\n"
+        )).unwrap();
+        assert_eq!(search_names("example.com.", &conf),
+                   names(&["example.com."]));
+        assert_eq!(search_names("foo", &conf),
+                   names(&["foo.corp.example.", "foo.lab.example.",
+                           "foo."]));
+        assert_eq!(search_names("foo.bar", &conf),
+                   names(&["foo.bar.", "foo.bar.corp.example.",
+                           "foo.bar.lab.example."]));
+    }
+
+    #[test]
+    fn resolved_via_search_list() {
+        let conf = ResolvConf::parse(&mut io::Cursor::new(
+            "search corp.example\n"
+        )).unwrap();
+        let names = search_names("example", &conf);
+        let ent = from_answers(&names[0],
+                               vec![(Rtype::A, Ok(message(AUTHENTICATED_A)))],
+                               true, MAX_ANSWER_RECORDS)
+                      .unwrap().unwrap().0;
+        assert_eq!(ent.resolved_via(), Some("example.corp.example"));
+    }
+
+    #[test]
+    fn no_ipv6_skips_aaaa() {
+        let core = reactor::Core::new().unwrap();
+        let name = "example.com.";
+        let options = ResolverOptions::new().no_ipv6(true);
+        let lookup = HostByName::new(name, &options, &core.handle())
+                                .unwrap();
+        match lookup.queries[1] {
+            (Rtype::Aaaa, MaybeDone::Skipped) => { }
+            _ => panic!("AAAA query not skipped")
+        }
+    }
+
+    #[test]
+    fn ipv6_probe_skips_aaaa() {
+        fn no_ipv6() -> bool { false }
+
+        let core = reactor::Core::new().unwrap();
+        let mut options = ResolverOptions::new();
+        options.ipv6_probe = Some(no_ipv6);
+        let lookup = HostByName::new("example.com.", &options,
+                                     &core.handle()).unwrap();
+        match lookup.queries[1] {
+            (Rtype::Aaaa, MaybeDone::Skipped) => { }
+            _ => panic!("AAAA query not skipped")
+        }
+
+        let options = options.family(Family::V6);
+        let lookup = HostByName::new("example.com.", &options,
+                                     &core.handle()).unwrap();
+        match lookup.queries[1] {
+            (Rtype::Aaaa, MaybeDone::Waiting) => { }
+            _ => panic!("AAAA query skipped despite explicit family")
+        }
+    }
+
+    #[test]
+    fn family_skips_queries() {
+        let core = reactor::Core::new().unwrap();
+        let name = "example.com.";
+        let options = ResolverOptions::new().family(Family::V6);
+        let lookup = HostByName::new(name, &options, &core.handle())
+                                .unwrap();
+        match lookup.queries[0] {
+            (Rtype::A, MaybeDone::Skipped) => { }
+            _ => panic!("A query not skipped")
+        }
+        match lookup.queries[1] {
+            (Rtype::Aaaa, MaybeDone::Waiting) => { }
+            _ => panic!("AAAA query skipped")
+        }
+    }
+
+    #[test]
+    fn first_only_skips_second_query() {
+        let core = reactor::Core::new().unwrap();
+        let name = "example.com.";
+        let options = ResolverOptions::new()
+                                      .first_only(true)
+                                      .preference(Preference::PreferV4);
+        let mut lookup = HostByName::new(name, &options, &core.handle())
+                                    .unwrap();
+        assert!(lookup.sequential);
+        lookup.queries[0].1 = MaybeDone::Done(Ok(message(AUTHENTICATED_A)));
+
+        // Polling outside of a task would panic if the AAAA query were
+        // started, so this also checks that it is never asked.
+        match lookup.poll() {
+            Ok(Async::Ready(Some(ent))) => {
+                assert_eq!(ent.addrs(),
+                           &["192.0.2.1".parse::<IpAddr>().unwrap()]);
+            }
+            _ => panic!("lookup not finished")
+        }
+        match lookup.queries[1] {
+            (Rtype::Aaaa, MaybeDone::Waiting) => { }
+            _ => panic!("AAAA query was started")
+        }
+    }
+
+    #[test]
+    fn injected_errors() {
+        let core = reactor::Core::new().unwrap();
+        let options = ResolverOptions::new();
+        let failed = || {
+            MaybeDone::Done(Err(Error::Io(io::Error::new(
+                io::ErrorKind::TimedOut, "injected"
+            ))))
+        };
+
+        // Failing queries end the lookup with their error and polling it
+        // again is an error, too, rather than a panic.
+        let mut lookup = HostByName::new("example.com.", &options,
+                                         &core.handle()).unwrap();
+        for query in &mut lookup.queries {
+            query.1 = failed();
+        }
+        assert_eq!(lookup.poll().unwrap_err().kind(),
+                   io::ErrorKind::TimedOut);
+        assert_eq!(lookup.poll().unwrap_err().kind(),
+                   io::ErrorKind::Other);
+
+        let mut lookup = HostByAddr {
+            addr: "192.0.2.1".parse().unwrap(),
+            result: Err(Some(io::ErrorKind::TimedOut.into())),
+        };
+        assert_eq!(lookup.poll().unwrap_err().kind(),
+                   io::ErrorKind::TimedOut);
+        assert_eq!(lookup.poll().unwrap_err().kind(),
+                   io::ErrorKind::Other);
+
+        // A skipped query stays skipped when its result is taken.
+        let mut query = MaybeDone::Skipped;
+        assert!(query.take().is_none());
+        assert!(query.poll());
+        match query {
+            MaybeDone::Skipped => { }
+            _ => panic!("skipped query was reset")
+        }
+    }
+
+    #[test]
+    fn query_order() {
+        let core = reactor::Core::new().unwrap();
+        let name = "example.com.";
+
+        let conf = ResolvConf::parse(&mut io::Cursor::new(
+            "nameserver 127.0.0.1\n"
+        )).unwrap();
+        let options = ResolverOptions::new().resolv_conf(conf);
+        let lookup = HostByName::new(name, &options, &core.handle())
+                                .unwrap();
+        assert!(!lookup.sequential);
+        assert!(!lookup.stop_early);
+
+        let options = ResolverOptions::new()
+                                      .query_order(QueryOrder::Sequential)
+                                      .preference(Preference::PreferV6);
+        let lookup = HostByName::new(name, &options, &core.handle())
+                                .unwrap();
+        assert!(lookup.sequential);
+        assert!(!lookup.stop_early);
+        assert_eq!(lookup.queries[0].0, Rtype::Aaaa);
+
+        let options = ResolverOptions::new()
+                                      .query_order(QueryOrder::Fallback)
+                                      .preference(Preference::PreferV6);
+        let lookup = HostByName::new(name, &options, &core.handle())
+                                .unwrap();
+        assert!(lookup.sequential);
+        assert!(lookup.stop_early);
+        assert_eq!(lookup.queries[0].0, Rtype::Aaaa);
+    }
+
+    #[test]
+    fn systemd_stub() {
+        let core = reactor::Core::new().unwrap();
+        let name = "example.com.";
+        let conf = ResolvConf::parse(&mut io::Cursor::new(
+            "nameserver 127.0.0.53\n"
+        )).unwrap();
+
+        let options = ResolverOptions::new().resolv_conf(conf.clone());
+        let lookup = HostByName::new(name, &options, &core.handle())
+                                .unwrap();
+        assert!(lookup.sequential);
+
+        let options = options.systemd_stub(false);
+        let lookup = HostByName::new(name, &options, &core.handle())
+                                .unwrap();
+        assert!(!lookup.sequential);
+    }
+
+    #[test]
+    fn split_dns() {
+        let conf = |server: &str| {
+            ResolvConf::parse(&mut io::Cursor::new(
+                format!("nameserver {}\n", server)
+            )).unwrap()
+        };
+        let server = |options: &ResolverOptions, name: &str| {
+            resolv_conf_for(options, name).servers()[0]
+        };
+        let options = ResolverOptions::new()
+            .resolv_conf(conf("192.0.2.1"))
+            .split_dns("corp", conf("10.0.0.1"))
+            .split_dns("Lab.Corp.", conf("10.1.0.1"));
+        assert_eq!(server(&options, "example.com"),
+                   SocketAddr::from_str("192.0.2.1:53").unwrap());
+        assert_eq!(server(&options, "intranet.corp"),
+                   SocketAddr::from_str("10.0.0.1:53").unwrap());
+        assert_eq!(server(&options, "corp."),
+                   SocketAddr::from_str("10.0.0.1:53").unwrap());
+        assert_eq!(server(&options, "host.LAB.corp"),
+                   SocketAddr::from_str("10.1.0.1:53").unwrap());
+        assert_eq!(server(&options, "notcorp"),
+                   SocketAddr::from_str("192.0.2.1:53").unwrap());
+    }
+}
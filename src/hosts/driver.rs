@@ -0,0 +1,206 @@
+//! The hosts database for the lookup engine.
+
+use std::io;
+use std::net::IpAddr;
+use domain::bits::{DNameBuf, MessageBuf};
+use futures::{future, Future};
+use tokio_core::reactor;
+use ::lookup::{Lookupable, SourceLookup};
+use ::nsswitch::{Database, Rule, Service};
+use super::{dns, files, HostEnt, ResolverOptions, ResolverPool};
+
+
+//------------ Key -----------------------------------------------------------
+
+/// What a lookup is looking for.
+pub enum Key {
+    /// A host name, both as a domain name and as given by the user.
+    Name(DNameBuf, String),
+
+    /// An address.
+    Addr(IpAddr),
+}
+
+
+//------------ HostSources ---------------------------------------------------
+
+/// The sources of the hosts database.
+pub struct HostSources {
+    options: ResolverOptions,
+    reactor: reactor::Handle,
+
+    /// The pool to take DNS resolvers from if there is one.
+    pool: Option<ResolverPool>,
+}
+
+impl HostSources {
+    pub fn new(options: &ResolverOptions, reactor: &reactor::Handle)
+               -> Self {
+        HostSources {
+            options: options.clone(),
+            reactor: reactor.clone(),
+            pool: None,
+        }
+    }
+
+    /// Creates the sources for lookups through a resolver pool.
+    pub fn pooled(pool: &ResolverPool) -> Self {
+        HostSources {
+            options: pool.options().clone(),
+            reactor: pool.reactor().clone(),
+            pool: Some(pool.clone()),
+        }
+    }
+
+    /// Starts a DNS lookup with the backend given in the options.
+    ///
+    /// Returns `None` if there is no such backend or if the lookup goes
+    /// through a pool which always uses its own resolvers. Backends are
+    /// also skipped if DNS-over-TLS is asked for since they can’t be told
+    /// about it.
+    fn backend_lookup(&self, key: &Key) -> Option<SourceLookup<HostEnt>> {
+        if self.pool.is_some() || self.options.tls_server.is_some() {
+            return None
+        }
+        let backend = match self.options.dns_backend {
+            Some(ref backend) => backend.get(),
+            None => return None
+        };
+        Some(match *key {
+            Key::Name(_, ref name) => {
+                backend.lookup_name(name, &self.options, &self.reactor)
+            }
+            Key::Addr(addr) => {
+                backend.lookup_addr(addr, &self.options, &self.reactor)
+            }
+        })
+    }
+
+    /// Creates a DNS lookup for a host name.
+    ///
+    /// Names with a split DNS configuration never use the pool.
+    fn dns_by_name(&self, name: &str) -> io::Result<dns::HostByName> {
+        match self.pool {
+            Some(ref pool)
+                    if self.options.split_dns_conf(name).is_none() => {
+                dns::HostByName::with_resolver(name, &self.options,
+                                               pool.resolv_conf(),
+                                               pool.resolver())
+            }
+            _ => dns::HostByName::new(name, &self.options, &self.reactor)
+        }
+    }
+}
+
+impl Lookupable for HostSources {
+    type Key = Key;
+    type Item = HostEnt;
+
+    fn database() -> Database {
+        Database::Hosts
+    }
+
+    fn default_rules() -> Vec<Rule> {
+        vec![Rule::Service(Service::Files), Rule::Service(Service::Dns)]
+    }
+
+    fn start(&self, service: &Service, key: &Key)
+             -> Option<SourceLookup<HostEnt>> {
+        if *service == Service::Dns {
+            if let Some(lookup) = self.backend_lookup(key) {
+                return Some(lookup)
+            }
+        }
+        match (service, key) {
+            (&Service::Files, &Key::Name(ref name, _)) => {
+                Some(Box::new(future::result(
+                    files::get_host_by_name(name, &self.options)
+                )))
+            }
+            (&Service::Files, &Key::Addr(addr)) => {
+                Some(Box::new(future::result(
+                    files::get_host_by_addr(addr, &self.options)
+                )))
+            }
+            (&Service::Dns, &Key::Name(_, ref name)) => {
+                Some(match self.dns_by_name(name) {
+                    Ok(lookup) => Box::new(lookup) as SourceLookup<_>,
+                    Err(err) => Box::new(future::err(err)),
+                })
+            }
+            (&Service::Dns, &Key::Addr(addr)) => {
+                Some(match dns::HostByAddr::new(addr, &self.options,
+                                                &self.reactor) {
+                    Ok(lookup) => Box::new(lookup) as SourceLookup<_>,
+                    Err(err) => Box::new(future::err(err)),
+                })
+            }
+            (&Service::Other(ref other), key) => {
+                let source = match self.options.sources.get(other) {
+                    Some(source) => source,
+                    None => return None,
+                };
+                Some(match *key {
+                    Key::Name(_, ref name) => {
+                        source.lookup_name(name, &self.reactor)
+                    }
+                    Key::Addr(addr) => {
+                        source.lookup_addr(addr, &self.reactor)
+                    }
+                })
+            }
+            _ => None
+        }
+    }
+
+    fn merge(&self, found: &mut HostEnt, other: HostEnt) {
+        found.merge(other)
+    }
+}
+
+
+//------------ RawHostSources ------------------------------------------------
+
+/// The sources of the hosts database keeping the DNS answers.
+///
+/// Entries from sources other than DNS come with an empty list of answers.
+pub struct RawHostSources(HostSources);
+
+impl RawHostSources {
+    pub fn new(options: &ResolverOptions, reactor: &reactor::Handle)
+               -> Self {
+        RawHostSources(HostSources::new(options, reactor))
+    }
+}
+
+impl Lookupable for RawHostSources {
+    type Key = Key;
+    type Item = (HostEnt, Vec<MessageBuf>);
+
+    fn database() -> Database {
+        HostSources::database()
+    }
+
+    fn default_rules() -> Vec<Rule> {
+        HostSources::default_rules()
+    }
+
+    fn start(&self, service: &Service, key: &Key)
+             -> Option<SourceLookup<Self::Item>> {
+        if let (&Service::Dns, &Key::Name(_, ref name)) = (service, key) {
+            return Some(match self.0.dns_by_name(name) {
+                Ok(lookup) => Box::new(lookup.raw()) as SourceLookup<_>,
+                Err(err) => Box::new(future::err(err)),
+            })
+        }
+        self.0.start(service, key).map(|lookup| {
+            Box::new(lookup.map(|res| res.map(|ent| (ent, Vec::new()))))
+                as SourceLookup<_>
+        })
+    }
+
+    fn merge(&self, found: &mut Self::Item, other: Self::Item) {
+        found.0.merge(other.0);
+        found.1.extend(other.1);
+    }
+}
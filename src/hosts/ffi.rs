@@ -0,0 +1,141 @@
+//! Conversion of host entries into C’s `struct hostent`.
+//!
+//! This module is only available with the `ffi` feature. It is intended
+//! for implementing the classic `gethostbyname()` family of functions on
+//! top of this crate.
+
+use std::{mem, ptr};
+use std::ffi::CString;
+use std::net::IpAddr;
+use std::os::raw::{c_char, c_int};
+use libc;
+use super::HostEnt;
+
+
+//------------ HostEntBuf ----------------------------------------------------
+
+/// A `struct hostent` together with all the data it points to.
+///
+/// The value owns the name, the aliases, and the addresses referenced by
+/// the pointers in the `hostent`. It is returned boxed so that the
+/// `hostent` itself has a stable address, too. All pointers remain valid
+/// for as long as the value is alive.
+///
+/// Since a `hostent` can only contain addresses of a single family, only
+/// the addresses of the same family as the first address of the host
+/// entry are included. If there are no addresses at all, the family is
+/// `AF_INET` and the address list is empty.
+///
+/// Names containing NUL characters cannot be represented and are replaced
+/// with an empty string or, for aliases, skipped.
+pub struct HostEntBuf {
+    hostent: libc::hostent,
+    _name: CString,
+    _aliases: Vec<CString>,
+    _alias_ptrs: Vec<*mut c_char>,
+    _addrs: Vec<u8>,
+    _addr_ptrs: Vec<*mut c_char>,
+}
+
+impl HostEntBuf {
+    /// Creates the C layout for the given host entry.
+    pub fn new(ent: &HostEnt) -> Box<Self> {
+        let v6 = match ent.addrs().first() {
+            Some(&IpAddr::V6(_)) => true,
+            _ => false,
+        };
+        let len = if v6 { 16 } else { 4 };
+
+        let name = CString::new(ent.name()).unwrap_or_default();
+        let aliases: Vec<_> = ent.aliases().iter().filter_map(|alias| {
+            CString::new(alias.as_str()).ok()
+        }).collect();
+        let mut alias_ptrs: Vec<_> = aliases.iter().map(|alias| {
+            alias.as_ptr() as *mut c_char
+        }).collect();
+        alias_ptrs.push(ptr::null_mut());
+
+        let mut addrs = Vec::new();
+        for addr in ent.addrs() {
+            match (*addr, v6) {
+                (IpAddr::V4(addr), false) => {
+                    addrs.extend_from_slice(&addr.octets())
+                }
+                (IpAddr::V6(addr), true) => {
+                    addrs.extend_from_slice(&addr.octets())
+                }
+                _ => { }
+            }
+        }
+        let mut addr_ptrs: Vec<_> = addrs.chunks(len).map(|addr| {
+            addr.as_ptr() as *mut c_char
+        }).collect();
+        addr_ptrs.push(ptr::null_mut());
+
+        let mut res = Box::new(HostEntBuf {
+            hostent: unsafe { mem::zeroed() },
+            _name: name,
+            _aliases: aliases,
+            _alias_ptrs: alias_ptrs,
+            _addrs: addrs,
+            _addr_ptrs: addr_ptrs,
+        });
+        res.hostent = libc::hostent {
+            h_name: res._name.as_ptr() as *mut c_char,
+            h_aliases: res._alias_ptrs.as_mut_ptr(),
+            h_addrtype: if v6 { libc::AF_INET6 } else { libc::AF_INET },
+            h_length: len as c_int,
+            h_addr_list: res._addr_ptrs.as_mut_ptr(),
+        };
+        res
+    }
+
+    /// Returns a reference to the `struct hostent`.
+    pub fn hostent(&self) -> &libc::hostent {
+        &self.hostent
+    }
+
+    /// Returns a pointer to the `struct hostent`.
+    ///
+    /// The pointer is valid for as long as `self` is alive.
+    pub fn as_ptr(&self) -> *const libc::hostent {
+        &self.hostent
+    }
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CStr;
+    use std::slice;
+    use super::*;
+
+    #[test]
+    fn layout() {
+        let ent = HostEnt::untimed(
+            "localhost".into(), vec!["loopback".into()],
+            vec!["127.0.0.1".parse().unwrap(), "::1".parse().unwrap(),
+                 "127.0.1.1".parse().unwrap()]
+        );
+        let buf = HostEntBuf::new(&ent);
+        let hostent = unsafe { &*buf.as_ptr() };
+        unsafe {
+            assert_eq!(CStr::from_ptr(hostent.h_name).to_str().unwrap(),
+                       "localhost");
+            assert_eq!(CStr::from_ptr(*hostent.h_aliases).to_str().unwrap(),
+                       "loopback");
+            assert!((*hostent.h_aliases.offset(1)).is_null());
+            assert_eq!(hostent.h_addrtype, libc::AF_INET);
+            assert_eq!(hostent.h_length, 4);
+            assert_eq!(slice::from_raw_parts(*hostent.h_addr_list as *const u8,
+                                             4),
+                       &[127, 0, 0, 1]);
+            assert_eq!(slice::from_raw_parts(
+                           *hostent.h_addr_list.offset(1) as *const u8, 4),
+                       &[127, 0, 1, 1]);
+            assert!((*hostent.h_addr_list.offset(2)).is_null());
+        }
+    }
+}
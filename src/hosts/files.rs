@@ -1,44 +1,191 @@
 //! The files source for the hosts database.
 
-use std::{convert, error, fmt, fs, io, net, slice, result};
+use std::{convert, error, fmt, fs, io, mem, net, slice, result};
 use std::collections::HashMap;
 use std::net::IpAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::SystemTime;
 use domain::bits::name::{self, DNameSlice, DNameBuf};
-use super::HostEnt;
+use ::blocking::Blocking;
+use ::resolv::strip_comment;
+use ::trace;
+use super::{HostEnt, ResolverOptions};
 
 
-pub fn get_host_by_name<N: AsRef<DNameSlice>>(name: N)
+/// The system’s hosts file.
+const SYSTEM_HOSTS: &'static str = "/etc/hosts";
+
+pub fn get_host_by_name<N: AsRef<DNameSlice>>(name: N,
+                                              options: &ResolverOptions)
                                               -> io::Result<Option<HostEnt>> {
-    let hosts = Hosts::default();
-    match hosts.lookup_host(name.as_ref()) {
-        Some(iter) => {
-            Ok(Some(HostEnt {
-                name: format!("{}", name.as_ref()),
-                aliases: Vec::new(),
-                addrs: iter.map(|addr| *addr).collect(),
-            }))
+    let paths = hosts_paths(options);
+    let several = paths.len() > 1;
+    let mut addrs = Vec::new();
+    let mut aliases: Vec<String> = Vec::new();
+    let mut files = Vec::new();
+    for path in paths {
+        let hosts = match load(path, options)? {
+            Some(hosts) => hosts,
+            None => continue
+        };
+        if let Some(iter) = hosts.lookup_aliases(name.as_ref()) {
+            for alias in iter {
+                if !aliases.iter().any(|item| same_name(item, alias)) {
+                    aliases.push(alias.clone())
+                }
+            }
+        }
+        if let Some(iter) = hosts.lookup_host(name.as_ref()) {
+            for addr in iter {
+                if several {
+                    if addrs.contains(addr) {
+                        continue
+                    }
+                    files.push((*addr, path.to_path_buf()));
+                }
+                addrs.push(*addr);
+            }
         }
-        None => Ok(None)
     }
+    if addrs.is_empty() {
+        return Ok(None)
+    }
+    let mut res = HostEnt::untimed(format!("{}", name.as_ref()), aliases,
+                                   addrs);
+    res.files = files;
+    Ok(Some(res))
 }
 
-pub fn get_host_by_addr(addr: IpAddr) -> io::Result<Option<HostEnt>> {
-    let hosts = Hosts::default();
-    match hosts.lookup_addr(addr) {
-        Some(mut iter) => {
+pub fn get_host_by_addr(addr: IpAddr, options: &ResolverOptions)
+                        -> io::Result<Option<HostEnt>> {
+    let paths = hosts_paths(options);
+    let several = paths.len() > 1;
+    for path in paths {
+        let hosts = match load(path, options)? {
+            Some(hosts) => hosts,
+            None => continue
+        };
+        if let Some(mut iter) = hosts.lookup_addr(addr) {
             let name = match iter.next() {
-                None => return Ok(None),
+                None => continue,
                 Some(name) => format!("{}", name)
             };
-            Ok(Some(HostEnt {
-                name: name,
-                aliases: iter.map(|n| format!("{}", n)).collect(),
-                addrs: vec![addr],
-            }))
+            let mut res = HostEnt::untimed(
+                name, iter.map(|n| format!("{}", n)).collect(), vec![addr]
+            );
+            if several {
+                res.files.push((addr, path.to_path_buf()))
+            }
+            return Ok(Some(res))
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the aliases given for the canonical name `name`.
+///
+/// All lines of the hosts files of `options` whose canonical name, i.e.,
+/// first name, is `name` are collected regardless of their address. Names
+/// are compared ignoring case and a trailing dot. The aliases are returned
+/// in order of their first appearance and each only once. Lines that fail
+/// to parse or are longer than the maximum line length are treated the same
+/// way as by lookups: they are skipped unless the options ask for strict
+/// parsing, in which case they result in an error of kind `InvalidData`.
+pub fn aliases_of(name: &str, options: &ResolverOptions)
+                  -> io::Result<Vec<String>> {
+    let name = name.trim_right_matches('.');
+    let mut res: Vec<String> = Vec::new();
+    for path in hosts_paths(options) {
+        let file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(err) => {
+                not_found_or::<()>(err)?;
+                continue
+            }
+        };
+        let lines = Lines::new(io::BufReader::new(file),
+                               options.max_hosts_line_len);
+        for (i, line) in lines.enumerate() {
+            let names = match line? {
+                Some(line) => match parse_line(&line) {
+                    Ok(Some((_, names))) => names,
+                    Ok(None) => continue,
+                    Err(_) if options.strict_hosts_files => {
+                        return Err(Error::InvalidLine(i + 1).into())
+                    }
+                    Err(_) => continue
+                },
+                None if options.strict_hosts_files => {
+                    return Err(Error::InvalidLine(i + 1).into())
+                }
+                None => {
+                    trace::skipped_line(i + 1, "line too long");
+                    continue
+                }
+            };
+            if !same_name(&names[0], name) {
+                continue
+            }
+            for alias in &names[1..] {
+                if !res.iter().any(|known| same_name(known, alias)) {
+                    res.push(alias.clone())
+                }
+            }
         }
-        None => Ok(None)
+    }
+    Ok(res)
+}
+
+/// Returns whether two names are equal ignoring case and a trailing dot.
+fn same_name(left: &str, right: &str) -> bool {
+    left.trim_right_matches('.')
+        .eq_ignore_ascii_case(right.trim_right_matches('.'))
+}
+
+/// Returns the paths of the hosts files to consult in order.
+fn hosts_paths(options: &ResolverOptions) -> Vec<&Path> {
+    if options.hosts_files.is_empty() {
+        vec![Path::new(SYSTEM_HOSTS)]
+    }
+    else {
+        options.hosts_files.iter().map(AsRef::as_ref).collect()
+    }
+}
+
+/// Loads the hosts file at `path` for a lookup.
+///
+/// Returns `None` if the file doesn’t exist. Lines that fail to parse are
+/// skipped unless the options ask for strict parsing, in which case they
+/// result in an error of kind `InvalidData`.
+fn load(path: &Path, options: &ResolverOptions)
+        -> io::Result<Option<Hosts>> {
+    let mut hosts = Hosts::new();
+    hosts.set_max_line_len(options.max_hosts_line_len);
+    let res = if options.strict_hosts_files {
+        hosts.parse_file_strict(path)
+    }
+    else {
+        hosts.parse_file(path)
+    };
+    match res {
+        Ok(()) | Err(Error::ParseError) => { }
+        Err(Error::IoError(err)) => return not_found_or(err),
+        Err(err) => return Err(err.into()),
+    }
+    hosts.set_wildcards(options.hosts_wildcards);
+    Ok(Some(hosts))
+}
+
+
+/// Converts an error reading the hosts file into a lookup result.
+///
+/// A missing file means there simply are no entries and therefore results
+/// in an empty result. All other errors are returned as is.
+fn not_found_or<T>(err: io::Error) -> io::Result<Option<T>> {
+    match err.kind() {
+        io::ErrorKind::NotFound => Ok(None),
+        _ => Err(err)
     }
 }
 
@@ -65,10 +212,37 @@ pub fn get_host_by_addr(addr: IpAddr) -> io::Result<Option<HostEnt>> {
 /// that these calls don’t have to add matching information but rather
 /// the forward (host name to address) and reverse (address to host name)
 /// are independent.
+///
+/// # Wildcard Entries
+///
+/// As a non-standard extension, names in the table starting with a `*`
+/// label, such as `*.local.test`, can be treated as suffix wildcards that
+/// match any name below the rest of the name, e.g., `foo.local.test` or
+/// `a.b.local.test` but not `local.test` itself. An exact entry for a name
+/// always takes precedence and otherwise the longest matching wildcard
+/// wins. This is not supported by POSIX or any other implementation and
+/// is therefore disabled by default. Enable it with `set_wildcards()`.
+///
+/// # Line Length
+///
+/// When parsing a hosts file from an untrusted source, a single huge line
+/// could exhaust memory. A maximum line length can be set via
+/// `set_max_line_len()`. Longer lines are skipped without keeping more of
+/// them in memory than the reader’s buffer and are reported as a warning
+/// if the `tracing` feature is enabled. By default, lines can be of any
+/// length.
 #[derive(Clone, Debug, Default)]
 pub struct Hosts {
     forward: HashMap<DNameBuf, Vec<IpAddr>>,
     reverse: HashMap<IpAddr, Vec<DNameBuf>>,
+
+    /// The other names given on the lines of a name in file order.
+    aliases: HashMap<DNameBuf, Vec<String>>,
+
+    wildcards: bool,
+
+    /// The maximum length of a line in bytes.
+    max_line_len: Option<usize>,
 }
 
 
@@ -79,7 +253,10 @@ impl Hosts {
     pub fn new() -> Self {
         Hosts {
             forward: HashMap::new(),
-            reverse: HashMap::new()
+            reverse: HashMap::new(),
+            aliases: HashMap::new(),
+            wildcards: false,
+            max_line_len: None,
         }
     }
 
@@ -91,7 +268,46 @@ impl Hosts {
         let _ = res.parse_file("/etc/hosts");
         res
     }
+
+    /// Creates the hosts table for this system, returning read errors.
+    ///
+    /// Unlike `default()`, this fails if the hosts file cannot be read.
+    /// Lines that fail to parse are still skipped.
+    ///
+    /// XXX This currently only works for Unix-y systems.
+    pub fn system() -> io::Result<Self> {
+        let mut res = Hosts::new();
+        match res.parse_file(SYSTEM_HOSTS) {
+            Ok(()) => Ok(res),
+            Err(Error::IoError(err)) => Err(err),
+            Err(Error::ParseError) => Ok(res),
+        }
+    }
+
+    /// Returns a future resolving into the hosts table for this system.
+    ///
+    /// This is identical to `system()` except that the file is read on a
+    /// thread of its own so that a reactor isn’t blocked during startup.
+    pub fn poll_system() -> Blocking<Self> {
+        Blocking::spawn(Self::system)
+    }
  
+    /// Enables or disables the interpretation of wildcard entries.
+    ///
+    /// See the section on wildcard entries above for details.
+    pub fn set_wildcards(&mut self, enable: bool) {
+        self.wildcards = enable
+    }
+
+    /// Sets the maximum length of a line in bytes for parsing.
+    ///
+    /// Longer lines, not counting the line break, are skipped. If `None`,
+    /// the default, lines can be of any length. See the section on line
+    /// length above for details.
+    pub fn set_max_line_len(&mut self, len: Option<usize>) {
+        self.max_line_len = len
+    }
+
     /// Adds a host to IP mapping.
     pub fn add_forward(&mut self, name: &DNameBuf, addr: IpAddr) {
         if let Some(ref mut vec) = self.forward.get_mut(name) {
@@ -118,13 +334,44 @@ impl Hosts {
 ///
 impl Hosts {
     /// Looks up the address of a host.
+    ///
+    /// The addresses are returned in the order they were added. For a
+    /// table parsed from a hosts file, this is the order in which the
+    /// lines for the name appear in the file, top to bottom.
     pub fn lookup_host<N: AsRef<DNameSlice>>(&self, name: N)
                                              -> Option<slice::Iter<IpAddr>> {
         self._lookup_host(name.as_ref())
     }
 
     fn _lookup_host(&self, name: &DNameSlice) -> Option<slice::Iter<IpAddr>> {
-        self.forward.get(name).map(|vec| vec.iter())
+        if let Some(vec) = self.forward.get(name) {
+            return Some(vec.iter())
+        }
+        if !self.wildcards {
+            return None
+        }
+        let star = DNameBuf::from_str("*").unwrap();
+        let mut parent = name.parent();
+        while let Some(suffix) = parent {
+            if let Ok(wildcard) = star.join(suffix) {
+                if let Some(vec) = self.forward.get(&wildcard) {
+                    return Some(vec.iter())
+                }
+            }
+            parent = suffix.parent();
+        }
+        None
+    }
+
+    /// Looks up the other names given for a host.
+    ///
+    /// These are all names appearing on the lines of a parsed hosts file
+    /// that contain `name`, in the order of their first appearance, each
+    /// only once, and without a trailing dot. Wildcard entries are not
+    /// considered.
+    pub fn lookup_aliases<N>(&self, name: N) -> Option<slice::Iter<String>>
+                          where N: AsRef<DNameSlice> {
+        self.aliases.get(name.as_ref()).map(|vec| vec.iter())
     }
 
     /// Looks up the hostname of an address.
@@ -145,12 +392,43 @@ impl Hosts {
 
     /// Reads hosts from a reader and adds them.
     ///
-    /// The format is that of the /etc/hosts file.
+    /// The format is that of the /etc/hosts file. Lines longer than the
+    /// maximum line length are skipped.
     pub fn parse<R: io::Read>(&mut self, reader: &mut R) -> Result<()> {
-        use std::io::BufRead;
+        let lines = Lines::new(io::BufReader::new(reader), self.max_line_len);
+        for (i, line) in lines.enumerate() {
+            match try!(line) {
+                Some(line) => { let _ = self.parse_line(line); }
+                None => trace::skipped_line(i + 1, "line too long"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the hosts listed in a file, failing on malformed lines.
+    pub fn parse_file_strict<P: AsRef<Path>>(&mut self, path: P)
+                                             -> Result<()> {
+        let mut file = try!(fs::File::open(path));
+        self.parse_strict(&mut file)
+    }
 
-        for line in io::BufReader::new(reader).lines() {
-            let _ = self.parse_line(try!(line));
+    /// Reads hosts from a reader, failing on malformed lines.
+    ///
+    /// Whereas `parse()` skips lines it can’t make sense of, this stops at
+    /// the first such line and returns `Error::InvalidLine` with its line
+    /// number. The hosts from all lines before it have been added. Lines
+    /// longer than the maximum line length are treated the same way.
+    pub fn parse_strict<R: io::Read>(&mut self, reader: &mut R)
+                                     -> Result<()> {
+        let lines = Lines::new(io::BufReader::new(reader), self.max_line_len);
+        for (i, line) in lines.enumerate() {
+            let ok = match try!(line) {
+                Some(line) => self.parse_line(line).is_ok(),
+                None => false
+            };
+            if !ok {
+                return Err(Error::InvalidLine(i + 1))
+            }
         }
         Ok(())
     }
@@ -159,24 +437,29 @@ impl Hosts {
     ///
     /// Returns a result only so we can use `try!()`.
     fn parse_line(&mut self, line: String) -> Result<()> {
-        let line: &str = match line.find('#') {
-            Some(pos) => line.split_at(pos).0,
-            None => &line
+        let (addr, names) = match try!(parse_line(&line)) {
+            Some(entry) => entry,
+            None => return Ok(())
         };
-        let line = line.trim();
-        if line.is_empty() { return Ok(()) }
-        let mut words = line.split_whitespace();
-
-        let addr = try!(words.next().ok_or(Error::ParseError));
-        let addr = try!(IpAddr::from_str(addr));
+        for name in &names {
+            let others = self.aliases.entry(try!(DNameBuf::from_str(name)))
+                                     .or_insert_with(Vec::new);
+            for other in &names {
+                if !same_name(other, name)
+                        && !others.iter().any(|item| same_name(item, other)) {
+                    others.push(other.trim_right_matches('.').into())
+                }
+            }
+        }
+        let mut names = names.iter();
 
-        let cname = try!(words.next().ok_or(Error::ParseError));
+        let cname = try!(names.next().ok_or(Error::ParseError));
         let cname = try!(DNameBuf::from_str(cname));
 
         self.add_forward(&cname, addr);
         self.add_reverse(addr, cname);
 
-        for name in words {
+        for name in names {
             let name = try!(DNameBuf::from_str(name));
             self.add_forward(&name, addr);
         }
@@ -185,6 +468,218 @@ impl Hosts {
 }
 
 
+//------------ HostsDb -------------------------------------------------------
+
+/// A hosts table kept in sync with a hosts file.
+///
+/// Parsing a very large hosts file for every lookup is slow. This type
+/// parses the file once into the forward and reverse maps of a `Hosts`
+/// value and only parses it again if the file’s modification time or size
+/// has changed when `refresh()` is called.
+///
+/// If the `mmap` feature is enabled, the file is memory-mapped rather than
+/// read for parsing.
+#[derive(Clone, Debug)]
+pub struct HostsDb {
+    path: PathBuf,
+    hosts: Hosts,
+
+    /// The modification time and size of the file when it was parsed.
+    stamp: Option<(SystemTime, u64)>,
+}
+
+impl HostsDb {
+    /// Opens and parses the hosts file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut res = HostsDb {
+            path: path.as_ref().into(),
+            hosts: Hosts::new(),
+            stamp: None,
+        };
+        res.refresh()?;
+        Ok(res)
+    }
+
+    /// Parses the file again if it has changed.
+    ///
+    /// Returns whether the file was parsed.
+    pub fn refresh(&mut self) -> io::Result<bool> {
+        let file = fs::File::open(&self.path)?;
+        let metadata = file.metadata()?;
+        let stamp = Some((metadata.modified()?, metadata.len()));
+        if stamp == self.stamp {
+            return Ok(false)
+        }
+        let mut hosts = Hosts::new();
+        hosts.wildcards = self.hosts.wildcards;
+        hosts.max_line_len = self.hosts.max_line_len;
+        Self::parse(&mut hosts, file)?;
+        self.hosts = hosts;
+        self.stamp = stamp;
+        Ok(true)
+    }
+
+    #[cfg(feature = "mmap")]
+    fn parse(hosts: &mut Hosts, file: fs::File) -> io::Result<()> {
+        if file.metadata()?.len() == 0 {
+            return Ok(())
+        }
+        let map = unsafe { ::memmap::Mmap::map(&file)? };
+        Self::parse_bytes(hosts, &map)
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    fn parse(hosts: &mut Hosts, mut file: fs::File) -> io::Result<()> {
+        use std::io::Read;
+
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        Self::parse_bytes(hosts, &data)
+    }
+
+    fn parse_bytes(hosts: &mut Hosts, mut data: &[u8]) -> io::Result<()> {
+        match hosts.parse(&mut data) {
+            Err(Error::IoError(err)) => Err(err),
+            _ => Ok(())
+        }
+    }
+
+    /// Returns the hosts table.
+    pub fn hosts(&self) -> &Hosts {
+        &self.hosts
+    }
+
+    /// Returns the hosts table for modification.
+    ///
+    /// Changes are lost when the file is parsed again.
+    pub fn hosts_mut(&mut self) -> &mut Hosts {
+        &mut self.hosts
+    }
+}
+
+
+//------------ Lines --------------------------------------------------------
+
+/// An iterator over the lines of a reader with a maximum line length.
+///
+/// Yields `None` for lines that are too long. Only the part of such a line
+/// that has already been read into the reader’s buffer is kept in memory.
+struct Lines<R> {
+    reader: R,
+    max_len: Option<usize>,
+    buf: Vec<u8>,
+}
+
+impl<R: io::BufRead> Lines<R> {
+    fn new(reader: R, max_len: Option<usize>) -> Self {
+        Lines { reader: reader, max_len: max_len, buf: Vec::new() }
+    }
+
+    /// Reads the next line into the buffer.
+    ///
+    /// Returns `None` at the end of the reader or whether the line was
+    /// short enough otherwise.
+    fn read_line(&mut self) -> io::Result<Option<bool>> {
+        self.buf.clear();
+        let mut started = false;
+        let mut too_long = false;
+        loop {
+            let (used, done) = {
+                let available = match self.reader.fill_buf() {
+                    Ok(available) => available,
+                    Err(ref err)
+                        if err.kind() == io::ErrorKind::Interrupted => {
+                        continue
+                    }
+                    Err(err) => return Err(err)
+                };
+                if available.is_empty() {
+                    break
+                }
+                let newline = available.iter().position(|&ch| ch == b'\n');
+                let (used, done) = match newline {
+                    Some(pos) => (pos + 1, true),
+                    None => (available.len(), false),
+                };
+                if !too_long {
+                    self.buf.extend_from_slice(&available[..used]);
+                    if done {
+                        self.buf.pop();
+                    }
+                    if let Some(max_len) = self.max_len {
+                        if self.buf.len() > max_len {
+                            self.buf.clear();
+                            too_long = true;
+                        }
+                    }
+                }
+                (used, done)
+            };
+            self.reader.consume(used);
+            started = true;
+            if done {
+                break
+            }
+        }
+        if started { Ok(Some(!too_long)) }
+        else { Ok(None) }
+    }
+}
+
+impl<R: io::BufRead> Iterator for Lines<R> {
+    type Item = io::Result<Option<String>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_line() {
+            Ok(Some(true)) => { }
+            Ok(Some(false)) => return Some(Ok(None)),
+            Ok(None) => return None,
+            Err(err) => return Some(Err(err)),
+        }
+        let buf = mem::replace(&mut self.buf, Vec::new());
+        Some(String::from_utf8(buf).map(Some).map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidData, err)
+        }))
+    }
+}
+
+
+//------------ parse_line ---------------------------------------------------
+
+/// Parses a single line of a hosts file.
+///
+/// Returns `Ok(None)` if the line is empty or contains only a comment.
+/// Otherwise returns the address and the names given for it, canonical
+/// name first followed by the aliases. Names are returned as they appear in
+/// the line but are checked to be valid domain names.
+///
+/// Fields may be separated by any white space, including tabs. Comments
+/// start with `#` or with a `;` at the start of the line or after white
+/// space and extend to the end of the line. A trailing carriage
+/// return as well as a UTF-8 byte order mark, which files edited on
+/// Windows may start with, are ignored.
+pub fn parse_line(line: &str) -> Result<Option<(IpAddr, Vec<String>)>> {
+    let line = strip_comment(line).trim_matches(|ch: char| {
+        ch.is_whitespace() || ch == '\u{feff}'
+    });
+    if line.is_empty() { return Ok(None) }
+    let mut words = line.split_whitespace();
+
+    let addr = try!(words.next().ok_or(Error::ParseError));
+    let addr = try!(IpAddr::from_str(addr));
+
+    let mut names = Vec::new();
+    for name in words {
+        try!(DNameBuf::from_str(name));
+        names.push(name.into());
+    }
+    if names.is_empty() {
+        return Err(Error::ParseError)
+    }
+    Ok(Some((addr, names)))
+}
+
+
 //------------ Error and Result ---------------------------------------------
 
 /// An error happend during parsing a hosts file.
@@ -193,6 +688,9 @@ pub enum Error {
     /// The host file is kaputt.
     ParseError,
 
+    /// The line with the given number, counting from one, is kaputt.
+    InvalidLine(usize),
+
     /// Reading failed.
     IoError(io::Error),
 }
@@ -201,6 +699,7 @@ impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
             Error::ParseError => "error parsing configuration",
+            Error::InvalidLine(_) => "invalid line in hosts file",
             Error::IoError(ref e) => e.description(),
         }
     }
@@ -212,6 +711,20 @@ impl convert::From<io::Error> for Error {
     }
 }
 
+impl convert::From<Error> for io::Error {
+    /// Converts the error into an IO error.
+    ///
+    /// Reading errors are returned unchanged. Parse errors become errors of
+    /// kind `InvalidData`.
+    fn from(error: Error) -> io::Error {
+        match error {
+            Error::IoError(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("{}", err)),
+        }
+    }
+}
+
 impl convert::From<name::FromStrError> for Error {
     fn from(_: name::FromStrError) -> Error {
         Error::ParseError
@@ -232,13 +745,304 @@ impl convert::From<net::AddrParseError> for Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use std::error::Error;
-
-        self.description().fmt(f)
+        match *self {
+            Error::InvalidLine(line) => {
+                write!(f, "invalid line {} in hosts file", line)
+            }
+            _ => error::Error::description(self).fmt(f)
+        }
     }
 }
 
 pub type Result<T> = result::Result<T, Error>;
 
 
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_single_line() {
+        assert!(parse_line("").unwrap().is_none());
+        assert!(parse_line("   # just a comment").unwrap().is_none());
+        assert_eq!(parse_line("127.0.0.1\tlocalhost\tloopback # lo")
+                       .unwrap(),
+                   Some((IpAddr::from_str("127.0.0.1").unwrap(),
+                         vec!["localhost".into(), "loopback".into()])));
+        assert_eq!(parse_line("::1 ip6-localhost").unwrap(),
+                   Some((IpAddr::from_str("::1").unwrap(),
+                         vec!["ip6-localhost".into()])));
+        assert!(parse_line("127.0.0.256 localhost").is_err());
+        assert!(parse_line("localhost 127.0.0.1").is_err());
+        assert!(parse_line("127.0.0.1").is_err());
+    }
+
+    #[test]
+    fn file_order() {
+        let mut hosts = Hosts::new();
+        hosts.parse(&mut io::Cursor::new(
+            "192.0.2.3 multi.example\n\
+             2001:db8::2 multi.example\n\
+             192.0.2.1 other.example multi.example\n\
+             2001:db8::1 multi.example\n\
+             192.0.2.2 multi.example\n"
+        )).unwrap();
+        let addrs = hosts.lookup_host(
+            DNameBuf::from_str("multi.example").unwrap()
+        ).unwrap().map(|addr| *addr).collect::<Vec<_>>();
+        assert_eq!(addrs,
+                   ["192.0.2.3", "2001:db8::2", "192.0.2.1", "2001:db8::1",
+                    "192.0.2.2"].iter().map(|addr| {
+                        IpAddr::from_str(addr).unwrap()
+                    }).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn wildcards() {
+        let mut hosts = Hosts::new();
+        hosts.parse(&mut io::Cursor::new(
+            "127.0.0.1 *.local.test\n\
+             127.0.0.2 *.b.local.test\n\
+             127.0.0.3 a.b.local.test\n"
+        )).unwrap();
+        let lookup = |hosts: &Hosts, name: &str| {
+            hosts.lookup_host(DNameBuf::from_str(name).unwrap())
+                 .map(|mut iter| *iter.next().unwrap())
+        };
+        assert_eq!(lookup(&hosts, "foo.local.test"), None);
+
+        hosts.set_wildcards(true);
+        assert_eq!(lookup(&hosts, "foo.local.test"),
+                   Some(IpAddr::from_str("127.0.0.1").unwrap()));
+        assert_eq!(lookup(&hosts, "x.foo.local.test"),
+                   Some(IpAddr::from_str("127.0.0.1").unwrap()));
+        assert_eq!(lookup(&hosts, "x.b.local.test"),
+                   Some(IpAddr::from_str("127.0.0.2").unwrap()));
+        assert_eq!(lookup(&hosts, "a.b.local.test"),
+                   Some(IpAddr::from_str("127.0.0.3").unwrap()));
+        assert_eq!(lookup(&hosts, "local.test"), None);
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let mut res = ::std::env::temp_dir();
+        res.push(format!("netdb-{}-{}", ::std::process::id(), name));
+        res
+    }
+
+    #[test]
+    fn hosts_db_many_lookups() {
+        use std::io::Write;
+
+        let path = temp_path("hosts-many");
+        {
+            let mut file = fs::File::create(&path).unwrap();
+            for i in 0..5000 {
+                writeln!(file, "10.{}.{}.1 host{}.example", i / 256, i % 256,
+                         i).unwrap();
+            }
+        }
+        let db = HostsDb::open(&path).unwrap();
+        for _ in 0..10 {
+            for i in 0..5000 {
+                let name = DNameBuf::from_str(&format!("host{}.example", i))
+                                    .unwrap();
+                let addr = *db.hosts().lookup_host(&name).unwrap()
+                              .next().unwrap();
+                assert_eq!(addr, IpAddr::from([10, (i / 256) as u8,
+                                               (i % 256) as u8, 1]));
+            }
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn file_quirks() {
+        let lookup = |data: &str, name: &str| {
+            let mut hosts = Hosts::new();
+            hosts.parse(&mut data.as_bytes()).unwrap();
+            let name = DNameBuf::from_str(name).unwrap();
+            hosts.lookup_host(&name).map(|mut iter| *iter.next().unwrap())
+        };
+        let one = Some(IpAddr::from_str("192.0.2.1").unwrap());
+        let two = Some(IpAddr::from_str("192.0.2.2").unwrap());
+
+        // Missing trailing newline.
+        let data = "192.0.2.1 one.example\n192.0.2.2 two.example";
+        assert_eq!(lookup(data, "two.example"), two);
+
+        // Byte order mark.
+        let data = "\u{feff}192.0.2.1 one.example\n";
+        assert_eq!(lookup(data, "one.example"), one);
+
+        // CRLF line endings, with and without a final line break.
+        let data = "192.0.2.1 one.example\r\n192.0.2.2 two.example\r\n";
+        assert_eq!(lookup(data, "one.example"), one);
+        assert_eq!(lookup(data, "two.example"), two);
+        let data = "# hosts\r\n192.0.2.2 two.example\r";
+        assert_eq!(lookup(data, "two.example"), two);
+
+        // Semicolon comments.
+        let data = "; 192.0.2.2 two.example\n\
+                    \t;192.0.2.2 two.example\n\
+                    192.0.2.1 one.example ;two.example\n";
+        assert_eq!(lookup(data, "one.example"), one);
+        assert_eq!(lookup(data, "two.example"), None);
+    }
+
+    #[test]
+    fn max_line_len() {
+        let data = format!("192.0.2.1 {}.example\n\
+                            192.0.2.2 two.example\n",
+                           "a".repeat(100_000));
+        let mut hosts = Hosts::new();
+        hosts.set_max_line_len(Some(1024));
+        hosts.parse(&mut data.as_bytes()).unwrap();
+        assert!(hosts.lookup_addr(IpAddr::from_str("192.0.2.1").unwrap())
+                     .is_none());
+        let name = DNameBuf::from_str("two.example").unwrap();
+        assert_eq!(hosts.lookup_host(&name).unwrap().next(),
+                   Some(&IpAddr::from_str("192.0.2.2").unwrap()));
+
+        let mut hosts = Hosts::new();
+        hosts.set_max_line_len(Some(1024));
+        match hosts.parse_strict(&mut data.as_bytes()) {
+            Err(Error::InvalidLine(1)) => { }
+            _ => panic!("long line not rejected")
+        }
+    }
+
+    #[test]
+    fn hosts_db_refresh() {
+        use std::io::Write;
+
+        let path = temp_path("hosts-refresh");
+        fs::File::create(&path).unwrap()
+                               .write_all(b"192.0.2.1 one.example\n")
+                               .unwrap();
+        let mut db = HostsDb::open(&path).unwrap();
+        let two = DNameBuf::from_str("two.example").unwrap();
+        assert!(db.hosts().lookup_host(&two).is_none());
+        assert!(!db.refresh().unwrap());
+
+        fs::File::create(&path).unwrap()
+                               .write_all(b"192.0.2.1 one.example\n\
+                                            192.0.2.2 two.example\n")
+                               .unwrap();
+        assert!(db.refresh().unwrap());
+        assert!(db.hosts().lookup_host(&two).is_some());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_file() {
+        let mut hosts = Hosts::new();
+        match hosts.parse_file("/nonexistent/netdb/hosts") {
+            Err(Error::IoError(err)) => {
+                assert!(not_found_or::<HostEnt>(err).unwrap().is_none());
+            }
+            _ => panic!("reading a missing file succeeded")
+        }
+    }
+
+    #[test]
+    fn several_files() {
+        use std::io::Write;
+
+        let first = temp_path("hosts-first");
+        let second = temp_path("hosts-second");
+        fs::File::create(&first).unwrap()
+                                .write_all(b"192.0.2.1 multi.example\n")
+                                .unwrap();
+        fs::File::create(&second).unwrap()
+                                 .write_all(b"192.0.2.1 multi.example\n\
+                                              192.0.2.2 multi.example\n")
+                                 .unwrap();
+        let name = DNameBuf::from_str("multi.example.").unwrap();
+        let one = IpAddr::from_str("192.0.2.1").unwrap();
+        let two = IpAddr::from_str("192.0.2.2").unwrap();
 
+        let files = vec![first.clone(), second.clone()];
+        let options = ResolverOptions::new().hosts_files(files);
+        let ent = get_host_by_name(&name, &options).unwrap().unwrap();
+        assert_eq!(ent.addrs(), &[one, two]);
+        assert_eq!(ent.addr_file(&one), Some(first.as_path()));
+        assert_eq!(ent.addr_file(&two), Some(second.as_path()));
+        let ent = get_host_by_addr(two, &options).unwrap().unwrap();
+        assert_eq!(ent.addr_file(&two), Some(second.as_path()));
+
+        let options = ResolverOptions::new().hosts_files(vec![second.clone()]);
+        let ent = get_host_by_name(&name, &options).unwrap().unwrap();
+        assert_eq!(ent.addr_file(&two), None);
+
+        fs::remove_file(&first).unwrap();
+        fs::remove_file(&second).unwrap();
+    }
+
+    #[test]
+    fn aliases() {
+        use std::io::Write;
+
+        let path = temp_path("hosts-aliases");
+        fs::File::create(&path).unwrap()
+            .write_all(b"192.0.2.1 web.example www ftp
+                         192.0.2.2 other.example web.example
+                         2001:db8::1 WEB.example. www WWW. mail # v6
+")
+            .unwrap();
+        let options = ResolverOptions::new().hosts_files(vec![path.clone()]);
+        assert_eq!(aliases_of("web.example", &options).unwrap(),
+                   ["www", "ftp", "mail"]);
+        assert_eq!(aliases_of("web.example.", &options).unwrap(),
+                   ["www", "ftp", "mail"]);
+        assert!(aliases_of("other.example", &options).unwrap().is_empty());
+        assert!(aliases_of("www", &options).unwrap().is_empty());
+
+        // Overlong lines are skipped or rejected just like for lookups.
+        fs::File::create(&path).unwrap()
+            .write_all(format!("192.0.2.1 web.example {}.example\n\
+                                192.0.2.2 web.example www\n",
+                               "a".repeat(100_000)).as_bytes())
+            .unwrap();
+        let options = options.max_hosts_line_len(1024);
+        assert_eq!(aliases_of("web.example", &options).unwrap(), ["www"]);
+        let err = aliases_of("web.example", &options.strict_hosts_files(true))
+                      .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn strict_errors() {
+        use std::io::Write;
+
+        let name = DNameBuf::from_str("strict.example.").unwrap();
+        let path = temp_path("hosts-strict");
+        fs::File::create(&path).unwrap()
+                               .write_all(b"192.0.2.1 strict.example\n\
+                                            # comment\n\
+                                            192.0.2.300 broken.example\n")
+                               .unwrap();
+        let options = ResolverOptions::new().hosts_files(vec![path.clone()]);
+        assert!(get_host_by_name(&name, &options).unwrap().is_some());
+        let options = options.strict_hosts_files(true);
+        let err = get_host_by_name(&name, &options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "invalid line 3 in hosts file");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o000))
+               .unwrap();
+            // Privileged users can read the file regardless.
+            if fs::File::open(&path).is_err() {
+                let err = get_host_by_name(&name, &options).unwrap_err();
+                assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+            }
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
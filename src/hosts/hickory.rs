@@ -0,0 +1,199 @@
+//! The hickory-dns backend for the dns source.
+//!
+//! The hickory-dns project was formerly known as trust-dns. This backend
+//! uses the `trust-dns-resolver` releases built on futures 0.1 and
+//! tokio-core like the rest of this crate.
+
+use std::io;
+use std::net::IpAddr;
+use std::str::FromStr;
+use futures::Future;
+use tokio_core::reactor;
+use trust_dns_resolver::ResolverFuture;
+use trust_dns_resolver::config::{LookupIpStrategy, NameServerConfig,
+                                 Protocol, ResolverConfig, ResolverOpts};
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+use trust_dns_resolver::Name;
+use ::resolv::ResolvConf;
+use super::{dns, host_name, DnsBackend, Family, HostEnt, Preference,
+            ResolverOptions};
+
+
+//------------ HickoryBackend ------------------------------------------------
+
+/// The backend using the resolver of the hickory-dns project.
+///
+/// The resolver is configured from the resolver configuration, including
+/// any split DNS configuration, and the options of the lookup. The name
+/// servers, search list, `ndots`, timeout, and attempts are taken from the
+/// former, the address families to query from the latter. Like with the
+/// default backend, the name server on the local host is used if the
+/// configuration has none.
+///
+/// This type is only available if the `hickory` feature is enabled.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HickoryBackend;
+
+impl HickoryBackend {
+    /// Creates a new backend.
+    pub fn new() -> Self {
+        HickoryBackend
+    }
+
+    /// Creates the resolver for a lookup with the given configuration.
+    fn resolver(&self, conf: ResolvConf, options: &ResolverOptions,
+                reactor: &reactor::Handle) -> ResolverFuture {
+        let search: Vec<_> = conf.search().iter().filter_map(|name| {
+            Name::from_str(&format!("{}", name)).ok()
+        }).collect();
+        let servers = conf.effective_servers().iter().map(|addr| {
+            NameServerConfig { socket_addr: *addr, protocol: Protocol::Udp }
+        }).collect();
+        let domain = search.first().cloned().unwrap_or_else(Name::root);
+        let opts = ResolverOpts {
+            ndots: conf.ndots(),
+            timeout: conf.timeout(),
+            attempts: conf.attempts(),
+            ip_strategy: ip_strategy(options),
+            .. ResolverOpts::default()
+        };
+        ResolverFuture::new(ResolverConfig::from_parts(domain, search,
+                                                       servers),
+                            opts, reactor)
+    }
+}
+
+impl DnsBackend for HickoryBackend {
+    fn lookup_name(&self, name: &str, options: &ResolverOptions,
+                   reactor: &reactor::Handle)
+                   -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+        let qname = name.to_owned();
+        let conf = dns::resolv_conf_for(options, name);
+        Box::new(self.resolver(conf, options, reactor).lookup_ip(name)
+                     .then(move |res| match res {
+                         Ok(lookup) => Ok(from_addrs(&qname, lookup.iter())),
+                         Err(err) => not_found_or(err),
+                     }))
+    }
+
+    fn lookup_addr(&self, addr: IpAddr, options: &ResolverOptions,
+                   reactor: &reactor::Handle)
+                   -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+        let conf = dns::resolv_conf(options);
+        Box::new(self.resolver(conf, options, reactor).reverse_lookup(addr)
+                     .then(move |res| match res {
+                         Ok(lookup) => {
+                             let mut names = lookup.iter().map(host_name);
+                             Ok(names.next().map(|name| {
+                                 HostEnt::untimed(name, names.collect(),
+                                                  vec![addr])
+                             }))
+                         }
+                         Err(err) => not_found_or(err),
+                     }))
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Returns the lookup strategy for the families asked for by `options`.
+fn ip_strategy(options: &ResolverOptions) -> LookupIpStrategy {
+    match (options.family, options.no_ipv6, options.preference) {
+        (Family::V4, _, _) | (_, true, _) => LookupIpStrategy::Ipv4Only,
+        (Family::V6, _, _) => LookupIpStrategy::Ipv6Only,
+        (_, _, Preference::PreferV4) => LookupIpStrategy::Ipv4thenIpv6,
+        (_, _, Preference::PreferV6) => LookupIpStrategy::Ipv6thenIpv4,
+        _ => LookupIpStrategy::Ipv4AndIpv6,
+    }
+}
+
+/// Creates the host entry for `name` from the addresses found.
+///
+/// Returns `None` if there are no addresses.
+fn from_addrs<I: IntoIterator<Item=IpAddr>>(name: &str, addrs: I)
+                                            -> Option<HostEnt> {
+    let addrs: Vec<_> = addrs.into_iter().collect();
+    if addrs.is_empty() {
+        return None
+    }
+    Some(HostEnt::untimed(host_name(name), Vec::new(), addrs))
+}
+
+/// Converts a resolver error into a lookup result.
+///
+/// If there simply are no records, this is an empty result. IO errors keep
+/// their kind and timeouts become errors of kind `TimedOut` so that retry
+/// policies can recognize them as transient. All other errors are of kind
+/// `Other`.
+fn not_found_or<T>(err: ResolveError) -> io::Result<Option<T>> {
+    let kind = match *err.kind() {
+        ResolveErrorKind::NoRecordsFound(..) => return Ok(None),
+        ResolveErrorKind::Io(ref inner) => inner.kind(),
+        ResolveErrorKind::Timeout => io::ErrorKind::TimedOut,
+        _ => io::ErrorKind::Other,
+    };
+    Err(io::Error::new(kind, format!("{}", err)))
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use super::super::stub;
+
+    #[test]
+    fn equivalent_entry() {
+        let addrs: Vec<IpAddr> = vec!["192.0.2.1".parse().unwrap(),
+                                      "2001:db8::1".parse().unwrap()];
+        let ent = from_addrs("example.com.", addrs.iter().cloned()).unwrap();
+        assert_eq!(ent, HostEnt::untimed("example.com".into(), Vec::new(),
+                                         addrs));
+        assert!(from_addrs("example.com.", Vec::new()).is_none());
+    }
+
+    #[test]
+    fn strategy() {
+        assert_eq!(ip_strategy(&ResolverOptions::new()
+                                   .family(Family::V4)),
+                   LookupIpStrategy::Ipv4Only);
+        assert_eq!(ip_strategy(&ResolverOptions::new()
+                                   .preference(Preference::PreferV6)),
+                   LookupIpStrategy::Ipv6thenIpv4);
+    }
+
+    #[test]
+    fn error_kinds() {
+        let err = ResolveError::from(
+            io::Error::new(io::ErrorKind::ConnectionRefused, "refused")
+        );
+        assert_eq!(not_found_or::<()>(err).unwrap_err().kind(),
+                   io::ErrorKind::ConnectionRefused);
+        let err = ResolveError::from(ResolveErrorKind::Timeout);
+        assert_eq!(not_found_or::<()>(err).unwrap_err().kind(),
+                   io::ErrorKind::TimedOut);
+        let err = ResolveError::from(ResolveErrorKind::Message("broken"));
+        assert_eq!(not_found_or::<()>(err).unwrap_err().kind(),
+                   io::ErrorKind::Other);
+    }
+
+    #[test]
+    fn lookup_name() {
+        let (server, _) = stub::server(Some(Ipv4Addr::new(192, 0, 2, 1)));
+        let conf = ResolvConf::parse(&mut io::Cursor::new(
+            format!("nameserver {}\n", server)
+        )).unwrap();
+        let options = ResolverOptions::new().resolv_conf(conf)
+                                            .family(Family::V4);
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        let ent = core.run(HickoryBackend::new().lookup_name(
+            "www.example.com.", &options, &handle
+        )).unwrap().unwrap();
+        assert_eq!(ent.name(), "www.example.com");
+        assert_eq!(ent.addrs(), &["192.0.2.1".parse::<IpAddr>().unwrap()]);
+    }
+}
@@ -4,20 +4,56 @@
 /// with network hosts. It allows lookups based on a given host name or a
 /// given IP address.
 
-use std::{io, mem};
-use std::net::IpAddr;
+use std::{env, error, fmt, fs, io, mem, slice};
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::cmp::Ordering;
+use std::io::Read;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use domain::bits::DNameBuf;
-use futures::{Async, Future, Poll};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use domain::bits::{DNameBuf, MessageBuf};
+use domain::iana::Rtype;
+use futures::{future, Async, Future, Poll, Stream};
 use tokio_core::reactor;
+use ::gaiconf::GaiConf;
+use ::nsswitch::{Conf, Rule, Service};
+use ::lookup::{self, Lookup, Lookupable};
+use ::resolv::{ResolvConf, TlsServer};
+use ::trace::Span;
+use self::backend::SharedBackend;
+use self::driver::{HostSources, Key, RawHostSources};
 
 
 //============ Low-level API =================================================
 //
-// Currently private.
+// Mostly private. The hosts table is available for applications that want
+// to consult a hosts file directly.
 
+mod backend;
+mod cache;
+mod cancel;
 mod dns;
+mod driver;
 mod files;
+#[cfg(feature = "hickory")] mod hickory;
+mod pool;
+mod retry;
+mod source;
+#[cfg(test)] mod stub;
+
+pub use self::backend::{DnsBackend, DomainBackend};
+pub use self::cache::{Cache, CacheStats, CachedHostByName};
+pub use self::cancel::{CancelToken, CancellableHostByName};
+pub use self::files::{Error, Hosts, HostsDb, parse_line};
+#[cfg(feature = "hickory")] pub use self::hickory::HickoryBackend;
+pub use self::pool::{ResolverPool, SharedResolver};
+pub use self::retry::{HostByNameRetry, RetryPolicy};
+pub use self::source::{NameSource, SourceRegistry};
+
+#[cfg(feature = "ffi")] pub mod ffi;
 
 
 //============ High-level API ================================================
@@ -38,14 +74,175 @@ mod files;
 ///
 /// # Limitations
 ///
-/// For this initial version of the crate, the lookup is a `files` lookup
-/// first and only if that does fail to yield a result, a DNS query for
-/// both A and AAAA records. This initial version also does not yet fill
-/// the aliases list of the returned `HostEnt`.
+/// The sources are consulted in the order given by the `hosts` line of
+/// the system’s `nsswitch.conf` until one of them yields a result. Only
+/// the `files` and `dns` services are supported, other services are
+/// skipped unless a source for them is registered via
+/// `ResolverOptions::sources()`. Action rules are honored. With a merge
+/// action, the names and addresses found by consecutive sources are
+/// combined into one entry. The hosts file source returns the other names
+/// given on the lines for the host name as aliases.
 pub fn get_host_by_name(name: &str) -> Result<Option<HostEnt>, io::Error> {
+    get_host_by_name_with(name, &ResolverOptions::default())
+}
+
+/// Returns host information for a given host name using specific options.
+///
+/// This is identical to `get_host_by_name()` except that the lookup is
+/// modified by `options`.
+pub fn get_host_by_name_with(name: &str, options: &ResolverOptions)
+                             -> Result<Option<HostEnt>, io::Error> {
+    if let Some(addr) = is_ip_literal(name) {
+        return Ok(HostEnt::literal(name, addr).finalize(options))
+    }
+    let mut core = reactor::Core::new()?;
+    let handle = core.handle();
+    core.run(poll_host_by_name_with(name, options, &handle))
+}
+
+/// Returns host information for a given host name using a specific
+/// name service configuration.
+///
+/// This is identical to `get_host_by_name()` except that the sources and
+/// action rules are taken from the `hosts` database of `conf` rather than
+/// the system’s `nsswitch.conf`. This allows different parts of an
+/// application to use different resolution policies.
+pub fn get_host_by_name_with_conf(name: &str, conf: &Conf)
+                                  -> Result<Option<HostEnt>, io::Error> {
+    get_host_by_name_with(name,
+                          &ResolverOptions::new().nsswitch_conf(conf.clone()))
+}
+
+/// Returns host information for a given host name using DNS only.
+///
+/// This is identical to `get_host_by_name()` except that the system
+/// configuration is ignored and only the DNS source is used. In particular,
+/// `/etc/hosts` is never consulted. This is useful if the local hosts
+/// table deliberately differs from DNS, e.g., when testing split-horizon
+/// setups.
+///
+/// Returns `Ok(None)` if the name does not exist in DNS.
+pub fn get_host_by_name_dns_only(name: &str)
+                                 -> Result<Option<HostEnt>, io::Error> {
+    if let Some(addr) = is_ip_literal(name) {
+        return Ok(HostEnt::literal(name, addr)
+                         .finalize(&ResolverOptions::default()))
+    }
+    let mut core = reactor::Core::new()?;
+    let handle = core.handle();
+    core.run(poll_host_by_name_dns_only(name, &handle))
+}
+
+/// Returns host information for a given host name using DNS-over-TLS.
+///
+/// This is identical to `get_host_by_name_dns_only()` except that the
+/// queries are sent to `server` via TLS as described with
+/// `ResolverOptions::dns_over_tls()`. As explained there, this currently
+/// always fails for names that aren’t IP literals.
+pub fn get_host_by_name_dot(name: &str, server: TlsServer)
+                            -> Result<Option<HostEnt>, io::Error> {
+    if let Some(addr) = is_ip_literal(name) {
+        return Ok(HostEnt::literal(name, addr)
+                         .finalize(&ResolverOptions::default()))
+    }
+    let mut core = reactor::Core::new()?;
+    let handle = core.handle();
+    core.run(poll_host_by_name_dot(name, server, &handle))
+}
+
+/// Returns host information for a given host name using a source order.
+///
+/// This is identical to `get_host_by_name()` except that the sources are
+/// consulted as given by `order` instead of as configured in the system’s
+/// `nsswitch.conf`. The explicit order always overrides the configuration.
+pub fn get_host_by_name_ordered(name: &str, order: SourceOrder)
+                                -> Result<Option<HostEnt>, io::Error> {
+    if let Some(addr) = is_ip_literal(name) {
+        return Ok(HostEnt::literal(name, addr)
+                         .finalize(&ResolverOptions::default()))
+    }
+    let mut core = reactor::Core::new()?;
+    let handle = core.handle();
+    core.run(poll_host_by_name_ordered(name, order, &handle))
+}
+
+/// Returns host information for a given host name and the raw DNS answers.
+///
+/// This is identical to `get_host_by_name()` except that the returned
+/// `HostEnt` value is accompanied by the DNS answer messages it was created
+/// from. These allow diagnostic tools to inspect record types, owner names,
+/// and TTLs of the answer records directly. If the entry stems from any
+/// source other than DNS or the name is an IP literal, the list of
+/// messages is empty.
+pub fn get_host_by_name_raw(name: &str)
+                            -> Result<Option<(HostEnt, Vec<MessageBuf>)>,
+                                      io::Error> {
+    if let Some(addr) = is_ip_literal(name) {
+        return Ok(HostEnt::literal(name, addr)
+                         .finalize(&ResolverOptions::default())
+                         .map(|ent| (ent, Vec::new())))
+    }
+    let mut core = reactor::Core::new()?;
+    let handle = core.handle();
+    core.run(poll_host_by_name_raw(name, &handle))
+}
+
+/// Returns host information for a given host name, retrying on failure.
+///
+/// This is like `get_host_by_name()` but a lookup that fails transiently,
+/// such as by a timeout, is attempted again as described by `policy`.
+/// Lookups that fail for other reasons, such as an invalid host name, are
+/// not retried. A host name that doesn’t exist is not a failure and thus
+/// is not retried either.
+pub fn get_host_by_name_retry(name: &str, policy: RetryPolicy)
+                              -> Result<Option<HostEnt>, io::Error> {
+    if let Some(addr) = is_ip_literal(name) {
+        return Ok(HostEnt::literal(name, addr)
+                         .finalize(&ResolverOptions::default()))
+    }
+    let mut core = reactor::Core::new()?;
+    let handle = core.handle();
+    core.run(poll_host_by_name_retry(name, &handle, policy))
+}
+
+/// Returns host information for a list of host names.
+///
+/// The names in `names` are separated by white space, commas, or both,
+/// which makes this convenient for processing command line arguments. All
+/// names are looked up in parallel just as by `get_host_by_name()`.
+///
+/// The function waits for all lookups to finish. It returns a pair of the
+/// name and its lookup result for every name in the order the names appear
+/// in `names`. An error is only returned if the lookups can’t be started
+/// at all.
+///
+/// All lookups share a single DNS resolver. See `SharedResolver` for
+/// details.
+pub fn get_hosts(names: &str)
+                 -> Result<Vec<(String, Result<Option<HostEnt>, io::Error>)>,
+                           io::Error> {
+    let mut core = reactor::Core::new()?;
+    let shared = SharedResolver::new(&ResolverOptions::default(),
+                                     &core.handle())?;
+    let lookups: Vec<_> = split_names(names).into_iter().map(|name| {
+        let owned = String::from(name);
+        poll_host_by_name_shared(name, &shared).then(move |res| {
+            Ok::<_, io::Error>((owned, res))
+        })
+    }).collect();
+    core.run(future::join_all(lookups))
+}
+
+/// Returns whether two host names resolve to overlapping addresses.
+///
+/// Both names are looked up in parallel just as by `get_host_by_name()`.
+/// Returns `Ok(true)` if at least one address was found for both names.
+/// If either name doesn’t exist, returns `Ok(false)`. If either lookup
+/// fails, its error is returned.
+pub fn addresses_overlap(a: &str, b: &str) -> Result<bool, io::Error> {
     let mut core = reactor::Core::new()?;
     let handle = core.handle();
-    core.run(poll_host_by_name(name, &handle))
+    core.run(poll_addresses_overlap(a, b, &handle))
 }
 
 /// Returns host information for a given IP address.
@@ -57,16 +254,142 @@ pub fn get_host_by_name(name: &str) -> Result<Option<HostEnt>, io::Error> {
 ///
 /// # Limitations
 ///
-/// For this initial version of the crate, the lookup is a `files` lookup
-/// first and only if that does fail to yield a result, a DNS query for
-/// PTR records. This initial version also does not yet fill
-/// the aliases list of the returned `HostEnt`.
+/// The sources are consulted in the order given by the `hosts` line of
+/// the system’s `nsswitch.conf` until one of them yields a result. Only
+/// the `files` and `dns` services are supported, other services are
+/// skipped unless a source for them is registered via
+/// `ResolverOptions::sources()`. Action rules are honored. With a merge
+/// action, the names and addresses found by consecutive sources are
+/// combined into one entry.
 pub fn get_host_by_addr(addr: IpAddr) -> Result<Option<HostEnt>, io::Error> {
     let mut core = reactor::Core::new()?;
     let handle = core.handle();
     core.run(poll_host_by_addr(addr, &handle))
 }
 
+/// Returns host information for a given IP address using specific options.
+///
+/// This is identical to `get_host_by_addr()` except that the lookup is
+/// modified by `options`.
+pub fn get_host_by_addr_with(addr: IpAddr, options: &ResolverOptions)
+                             -> Result<Option<HostEnt>, io::Error> {
+    let mut core = reactor::Core::new()?;
+    let handle = core.handle();
+    core.run(poll_host_by_addr_with(addr, options, &handle))
+}
+
+/// Returns the fully qualified domain name of the local host.
+///
+/// The host name of the local system as returned by `local_hostname()` is
+/// resolved into its fully qualified domain name as described with
+/// `get_fqdn_for()`. Returns `Ok(None)` if the host name can’t be
+/// resolved.
+pub fn get_local_fqdn() -> Result<Option<String>, io::Error> {
+    get_fqdn_for(&local_hostname()?)
+}
+
+/// Returns the fully qualified domain name for a host name.
+///
+/// This is what `get_local_fqdn()` does with the host name of the local
+/// system. The name is looked up exactly as by `get_host_by_name()`. The
+/// fully qualified name is the canonical name of the host entry if it
+/// contains a dot or, otherwise, the first alias that does. If neither
+/// does, the canonical name is returned as is.
+pub fn get_fqdn_for(hostname: &str) -> Result<Option<String>, io::Error> {
+    Ok(get_host_by_name(hostname)?.map(|ent| fqdn_of(&ent)))
+}
+
+/// Returns the host name of the local system.
+///
+/// If the environment variable `NETDB_HOSTNAME` is set and not empty, its
+/// value is returned. This allows forcing a name if the system’s host name
+/// is wrong, for instance in containers. Otherwise the name is read from
+/// the kernel via `/proc/sys/kernel/hostname` or, failing that, from
+/// `/etc/hostname`.
+///
+/// XXX This currently only works for Unix-y systems.
+pub fn local_hostname() -> Result<String, io::Error> {
+    local_hostname_with(env::var_os("NETDB_HOSTNAME"))
+}
+
+/// Returns the host name of the local system given the value of
+/// `NETDB_HOSTNAME`.
+fn local_hostname_with(forced: Option<OsString>)
+                       -> Result<String, io::Error> {
+    if let Some(name) = forced {
+        if !name.is_empty() {
+            return name.into_string().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData,
+                               "NETDB_HOSTNAME is not valid Unicode")
+            })
+        }
+    }
+    let mut name = String::new();
+    fs::File::open("/proc/sys/kernel/hostname")
+        .or_else(|_| fs::File::open("/etc/hostname"))?
+        .read_to_string(&mut name)?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound,
+                                  "host name not set"))
+    }
+    Ok(name.into())
+}
+
+/// Returns all aliases defined for a canonical host name in the hosts file.
+///
+/// Every line of the system’s hosts file whose canonical name is `name` is
+/// considered regardless of the address it is for, and the union of their
+/// aliases is returned in order of first appearance. Names are compared
+/// ignoring case and a trailing dot. Only the hosts file is consulted, no
+/// other sources.
+pub fn aliases_of(name: &str) -> Result<Vec<String>, io::Error> {
+    files::aliases_of(name, &ResolverOptions::default())
+}
+
+/// Returns the canonical name for a given host name.
+///
+/// The name is looked up exactly as by `get_host_by_name()` but only the
+/// canonical name of the resulting host entry is returned, i.e., the
+/// name at the end of any CNAME chain. Returns `Ok(None)` if the name
+/// doesn’t exist.
+pub fn get_canonical_name(name: &str) -> Result<Option<String>, io::Error> {
+    let mut core = reactor::Core::new()?;
+    let handle = core.handle();
+    core.run(poll_canonical_name(name, &handle))
+}
+
+/// Returns the canonical name for a given IP address.
+///
+/// The address is looked up exactly as by `get_host_by_addr()` but only
+/// the name of the resulting host entry, e.g., the name of a PTR record, is
+/// returned. Returns `Ok(None)` if there is no name for the address.
+pub fn get_canonical_name_for_addr(addr: IpAddr)
+                                   -> Result<Option<String>, io::Error> {
+    let mut core = reactor::Core::new()?;
+    let handle = core.handle();
+    core.run(poll_canonical_name_for_addr(addr, &handle))
+}
+
+/// Returns the fully qualified domain name for a given host name.
+///
+/// Unlike `get_canonical_name()`, this does only as much DNS work as
+/// necessary to determine the name: the search list of the resolver
+/// configuration is applied and CNAME records are followed, but only A
+/// queries are asked and the hosts file isn’t consulted. The result is the
+/// name at the end of the CNAME chain for the first candidate name that
+/// exists. Returns `Ok(None)` if none of them does.
+///
+/// If the name is an IP literal, it is returned unchanged.
+pub fn get_fqdn(name: &str) -> Result<Option<String>, io::Error> {
+    if is_ip_literal(name).is_some() {
+        return Ok(Some(name.into()))
+    }
+    let mut core = reactor::Core::new()?;
+    let handle = core.handle();
+    core.run(poll_fqdn(name, &handle))
+}
+
 /// Returns host information for a given host name.
 ///
 /// The name is either a hostname, an IPv4 or IPv6 address in its standard
@@ -82,15 +405,150 @@ pub fn get_host_by_addr(addr: IpAddr) -> Result<Option<HostEnt>, io::Error> {
 ///
 /// # Limitations
 ///
-/// For this initial version of the crate, the lookup is a `files` lookup
-/// first and only if that does fail to yield a result, a DNS query for
-/// both A and AAAA records. This initial version also does not yet fill
-/// the aliases list of the returned `HostEnt`.
+/// The sources are consulted in the order given by the `hosts` line of
+/// the system’s `nsswitch.conf` until one of them yields a result. Only
+/// the `files` and `dns` services are supported, other services are
+/// skipped unless a source for them is registered via
+/// `ResolverOptions::sources()`. Action rules are honored. With a merge
+/// action, the names and addresses found by consecutive sources are
+/// combined into one entry. The hosts file source returns the other names
+/// given on the lines for the host name as aliases.
 pub fn poll_host_by_name(name: &str, reactor: &reactor::Handle)
                          -> HostByName {
     HostByName::new(name, reactor)
 }
 
+/// Returns host information for a given host name using specific options.
+///
+/// This is identical to `poll_host_by_name()` except that the lookup is
+/// modified by `options`.
+pub fn poll_host_by_name_with(name: &str, options: &ResolverOptions,
+                              reactor: &reactor::Handle) -> HostByName {
+    HostByName::with_options(name, options, reactor)
+}
+
+/// Returns host information for a given host name using a specific
+/// name service configuration.
+///
+/// This is the asynchronous version of `get_host_by_name_with_conf()`.
+pub fn poll_host_by_name_with_conf(name: &str, conf: &Conf,
+                                   reactor: &reactor::Handle) -> HostByName {
+    HostByName::with_options(
+        name, &ResolverOptions::new().nsswitch_conf(conf.clone()), reactor
+    )
+}
+
+/// Returns host information for a given host name using DNS only.
+///
+/// This is identical to `poll_host_by_name()` except that the system
+/// configuration is ignored and only the DNS source is used. See
+/// `get_host_by_name_dns_only()` for details.
+pub fn poll_host_by_name_dns_only(name: &str, reactor: &reactor::Handle)
+                                  -> HostByName {
+    HostByName::dns_only(name, reactor)
+}
+
+/// Returns host information for a given host name using DNS-over-TLS.
+///
+/// This is identical to `poll_host_by_name_dns_only()` except that the
+/// queries are sent to `server` via TLS. See `get_host_by_name_dot()` for
+/// details.
+pub fn poll_host_by_name_dot(name: &str, server: TlsServer,
+                             reactor: &reactor::Handle) -> HostByName {
+    HostByName::dns_over_tls(name, server, reactor)
+}
+
+/// Returns host information for a given host name using a source order.
+///
+/// This is identical to `poll_host_by_name()` except that the sources are
+/// consulted as given by `order`. See `get_host_by_name_ordered()` for
+/// details.
+pub fn poll_host_by_name_ordered(name: &str, order: SourceOrder,
+                                 reactor: &reactor::Handle) -> HostByName {
+    HostByName::ordered(name, order, reactor)
+}
+
+/// Returns host information for a given host name using a resolver pool.
+///
+/// This is identical to `poll_host_by_name_with()` except that DNS queries
+/// are sent via the resolvers of `pool` and the options and reactor of the
+/// pool are used. This is useful for resolving large numbers of names
+/// concurrently.
+pub fn poll_host_by_name_pooled(name: &str, pool: &ResolverPool)
+                                -> HostByName {
+    HostByName::pooled(name, pool)
+}
+
+/// Returns host information for a given host name using a shared resolver.
+///
+/// This is identical to `poll_host_by_name_with()` except that DNS queries
+/// are sent via `resolver` and its options and reactor are used. Several
+/// lookups made this way reuse the resolver’s sockets.
+pub fn poll_host_by_name_shared(name: &str, resolver: &SharedResolver)
+                                -> HostByName {
+    resolver.poll_host_by_name(name)
+}
+
+/// Returns host information for a given host name using a cache.
+///
+/// This is identical to `poll_host_by_name_with()` using the options of
+/// `cache` except that the result is taken from `cache` if it has an
+/// unexpired entry for the name and a successful result is added to it
+/// otherwise.
+pub fn poll_host_by_name_cached(name: &str, cache: &Cache,
+                                reactor: &reactor::Handle)
+                                -> CachedHostByName {
+    cache.poll_host_by_name(name, reactor)
+}
+
+/// Returns host information for a given host name unless cancelled.
+///
+/// The lookup is performed like by `poll_host_by_name()`. If `token` is
+/// cancelled before it completes, the returned future resolves into an
+/// error of kind `Interrupted`.
+pub fn poll_host_by_name_cancellable(name: &str, reactor: &reactor::Handle,
+                                     token: &CancelToken)
+                                     -> CancellableHostByName {
+    CancellableHostByName::new(name, reactor, token)
+}
+
+/// Returns host information for a given host name and the raw DNS answers.
+///
+/// This is the asynchronous version of `get_host_by_name_raw()`.
+pub fn poll_host_by_name_raw(name: &str, reactor: &reactor::Handle)
+                             -> HostByNameRaw {
+    HostByNameRaw::new(name, reactor)
+}
+
+/// Returns host information for a given host name, retrying on failure.
+///
+/// This is the asynchronous version of `get_host_by_name_retry()`. The
+/// delays between attempts are waited for using the reactor given by
+/// `reactor`.
+pub fn poll_host_by_name_retry(name: &str, reactor: &reactor::Handle,
+                               policy: RetryPolicy) -> HostByNameRetry {
+    HostByNameRetry::new(name, reactor, policy)
+}
+
+/// Returns the addresses for a given host name as they arrive.
+///
+/// Rather than waiting for the complete host entry, the returned stream
+/// yields each address as soon as the lookup for its family completes.
+/// The lookups for IPv6 and IPv4 addresses run concurrently, each
+/// consulting the sources like `poll_host_by_name()` does.
+///
+/// Within a family, the addresses are yielded in the order of the host
+/// entry. There is no guarantee about the order of the two families:
+/// whichever lookup completes first delivers its addresses first. Only if
+/// both complete at the same time are the IPv6 addresses yielded first.
+///
+/// If the lookup for one family fails, its error is yielded by the stream.
+/// The stream continues with the addresses of the other family afterwards.
+pub fn stream_host_by_name(name: &str, reactor: &reactor::Handle)
+                           -> HostAddrs {
+    HostAddrs::new(name, &ResolverOptions::default(), reactor)
+}
+
 /// Returns host information for a given IP address.
 ///
 /// The IP address can either be an IPv4 or IPv6 address. The function returns
@@ -99,129 +557,1613 @@ pub fn poll_host_by_name(name: &str, reactor: &reactor::Handle)
 ///
 /// # Limitations
 ///
-/// For this initial version of the crate, the lookup is a `files` lookup
-/// first and only if that does fail to yield a result, a DNS query for
-/// PTR records. This initial version also does not yet fill
-/// the aliases list of the returned `HostEnt`.
+/// The sources are consulted in the order given by the `hosts` line of
+/// the system’s `nsswitch.conf` until one of them yields a result. Only
+/// the `files` and `dns` services are supported, other services are
+/// skipped unless a source for them is registered via
+/// `ResolverOptions::sources()`. Action rules are honored. With a merge
+/// action, the names and addresses found by consecutive sources are
+/// combined into one entry.
 pub fn poll_host_by_addr(addr: IpAddr, reactor: &reactor::Handle)
                          -> HostByAddr {
     HostByAddr::new(addr, reactor)
 }
 
+/// Returns host information for a given IP address using specific options.
+///
+/// This is identical to `poll_host_by_addr()` except that the lookup is
+/// modified by `options`.
+pub fn poll_host_by_addr_with(addr: IpAddr, options: &ResolverOptions,
+                              reactor: &reactor::Handle) -> HostByAddr {
+    HostByAddr::with_options(addr, options, reactor)
+}
+
+/// Returns the canonical name for a given host name.
+///
+/// This is the asynchronous version of `get_canonical_name()`.
+pub fn poll_canonical_name(name: &str, reactor: &reactor::Handle)
+                           -> CanonicalName {
+    HostByName::new(name, reactor).into()
+}
+
+/// Returns the canonical name for a given IP address.
+///
+/// This is the asynchronous version of `get_canonical_name_for_addr()`.
+pub fn poll_canonical_name_for_addr(addr: IpAddr, reactor: &reactor::Handle)
+                                    -> CanonicalName {
+    HostByAddr::new(addr, reactor).into()
+}
+
+/// Returns whether two host names resolve to overlapping addresses.
+///
+/// This is the asynchronous version of `addresses_overlap()`.
+pub fn poll_addresses_overlap(a: &str, b: &str, reactor: &reactor::Handle)
+                              -> AddressesOverlap {
+    AddressesOverlap(poll_host_by_name(a, reactor)
+                         .join(poll_host_by_name(b, reactor)))
+}
+
+/// Returns whether a host name currently resolves to a given address.
+///
+/// The name is looked up just as by `poll_host_by_name()`. The returned
+/// future resolves into `true` if `addr` is among the addresses found and
+/// into `false` if it isn’t or the name doesn’t exist. If the lookup
+/// fails, its error is returned.
+pub fn resolves_to(name: &str, addr: IpAddr, reactor: &reactor::Handle)
+                   -> ResolvesTo {
+    ResolvesTo { lookup: poll_host_by_name(name, reactor), addr: addr }
+}
+
+/// Returns the fully qualified domain name for a given host name.
+///
+/// This is the asynchronous version of `get_fqdn()`.
+pub fn poll_fqdn(name: &str, reactor: &reactor::Handle) -> Fqdn {
+    Fqdn::new(name, reactor)
+}
+
+/// Returns how a host name would be looked up.
+///
+/// The plan lists the sources a lookup for the name consults and the names
+/// DNS is queried for without performing the lookup. See `explain_with()`
+/// for details.
+pub fn explain(name: &str) -> ResolutionPlan {
+    explain_with(name, &ResolverOptions::default())
+}
+
+/// Returns how a host name would be looked up with the given options.
+///
+/// Configuration not given via the options is loaded from the system just
+/// as for a lookup. Other than that, no IO is performed.
+pub fn explain_with(name: &str, options: &ResolverOptions)
+                    -> ResolutionPlan {
+    ResolutionPlan::new(name, options)
+}
+
+/// Returns the address if a host name is an IP address literal.
+///
+/// Lookups by host name skip all sources for such names and return a host
+/// entry with the address as its sole address instead. A name is a literal
+/// if it is an IPv4 address in dotted-decimal notation or an IPv6 address
+/// in the standard text notation, exactly as accepted by
+/// `IpAddr::from_str()`.
+///
+/// In addition, an IPv6 address enclosed in square brackets as used in
+/// URLs, such as `[::1]`, is accepted. Only IPv6 addresses may be
+/// bracketed.
+pub fn is_ip_literal(name: &str) -> Option<IpAddr> {
+    if name.starts_with('[') && name.ends_with(']') && name.len() > 2 {
+        return Ipv6Addr::from_str(&name[1..name.len() - 1]).ok()
+                        .map(IpAddr::V6)
+    }
+    IpAddr::from_str(name).ok()
+}
+
+/// Creates the host entry for an IP address without any lookup.
+///
+/// The entry is the same one a lookup by host name returns for the
+/// address’s literal. Its name is the text form of the address, it has no
+/// aliases, and the address is its sole address.
+pub fn from_literal(addr: IpAddr) -> HostEnt {
+    HostEnt::literal(&addr.to_string(), addr)
+}
+
+/// Creates the host entry for an IP literal without any lookup.
+///
+/// Returns `None` if `name` isn’t a literal as described for
+/// `is_ip_literal()`. Otherwise returns the entry a lookup by host name for
+/// `name` would return: its name is `name` exactly as given, it has no
+/// aliases, and the literal’s address is its sole address.
+pub fn from_literal_str(name: &str) -> Option<HostEnt> {
+    is_ip_literal(name).map(|addr| HostEnt::literal(name, addr))
+}
+
 
 //------------ HostEnt -------------------------------------------------------
 
 /// The result of a host lookup.
 ///
+/// Host entries are ordered by their canonical name first and, for equal
+/// names, by their addresses compared as sorted lists. This gives a
+/// deterministic order for presenting lists of entries. Entries with the
+/// same name and the same set of addresses are equal. All other
+/// information, such as the aliases or the order of the addresses, is
+/// ignored when comparing.
+///
 /// > **Note.** This implementation is highly temporary. While will probably
 /// > keep the semantics, the actual types may change. 
+#[derive(Clone, Debug)]
 pub struct HostEnt {
     name: String,
     aliases: Vec<String>,
     addrs: Vec<IpAddr>,
+
+    /// The addresses paired with their time to live.
+    ///
+    /// This contains the same addresses in the same order as `addrs`.
+    ttls: Vec<(IpAddr, Option<Duration>)>,
+
+    /// The hosts files the addresses were taken from.
+    ///
+    /// This is only filled if several hosts files were consulted.
+    files: Vec<(IpAddr, PathBuf)>,
+
+    authenticated: bool,
+    resolved_via: Option<String>,
+
+    /// Answer records were ignored because there were too many.
+    truncated: bool,
 }
 
 impl HostEnt {
+    /// Creates a new entry from its name, aliases, and addresses.
+    ///
+    /// This is intended for sources provided by the application and for
+    /// restoring entries kept elsewhere. The addresses have no TTL, the
+    /// entry isn’t authenticated, and it wasn’t resolved via DNS.
+    pub fn new(name: String, aliases: Vec<String>, addrs: Vec<IpAddr>)
+               -> Self {
+        Self::untimed(name, aliases, addrs)
+    }
+
+    /// Creates an entry from a source that doesn’t know about TTLs.
+    fn untimed(name: String, aliases: Vec<String>, addrs: Vec<IpAddr>)
+               -> Self {
+        HostEnt {
+            name: name,
+            aliases: aliases,
+            ttls: addrs.iter().map(|addr| (*addr, None)).collect(),
+            addrs: addrs,
+            files: Vec::new(),
+            authenticated: false,
+            resolved_via: None,
+            truncated: false,
+        }
+    }
+
+    /// Creates the entry for an IP literal used as a host name.
+    fn literal(name: &str, addr: IpAddr) -> Self {
+        Self::untimed(name.into(), Vec::new(), vec![addr])
+    }
+
     /// The canoncial name of the host.
+    ///
+    /// The name never has a trailing dot, even if the lookup was for an
+    /// absolute name.
     pub fn name(&self) -> &str {
         &self.name
     }
 
     /// The aliases of the host.
     ///
+    /// For entries from DNS, these are the names of the CNAME records that
+    /// led to the canonical name. The list is always empty if the lookup
+    /// was made with `ResolverOptions::collect_aliases()` disabled.
+    ///
     /// > **Note.** Best to assume this is a slice of `str`.
     pub fn aliases(&self) -> &[String] {
         self.aliases.as_ref()
     }
 
+    /// Returns an iterator over the aliases of the host as string slices.
+    pub fn alias_strs(&self) -> AliasStrs {
+        AliasStrs(self.aliases.iter())
+    }
+
+    /// Returns whether the entry is for the host name `query`.
+    ///
+    /// This is the case if `query` is the canonical name or one of the
+    /// aliases of the entry. Names are compared ignoring ASCII case and a
+    /// trailing dot. Internationalized names are compared as given, so
+    /// they only match if both use the same form, e.g., the A-label.
+    pub fn matches_name(&self, query: &str) -> bool {
+        let query = host_name(query);
+        Some(&self.name).into_iter().chain(self.aliases.iter()).any(|name| {
+            host_name(name).eq_ignore_ascii_case(&query)
+        })
+    }
+
     /// The addresses of the host.
+    ///
+    /// Addresses from the hosts file are in file order, i.e., in the order
+    /// the lines for the host appear in the file. Addresses from DNS are in
+    /// the order of the answers, with the first query’s addresses first.
+    /// Any ordering requested through `ResolverOptions`, such as a family
+    /// preference, is applied to this order and keeps the relative order of
+    /// addresses it considers equal.
+    ///
+    /// Since the order of DNS answers may change between lookups, use
+    /// `addrs_sorted()` if you need a deterministic order.
     pub fn addrs(&self) -> &[IpAddr] {
         self.addrs.as_ref()
     }
-}
 
+    /// Returns the addresses of the host in numeric order.
+    ///
+    /// All IPv4 addresses come before all IPv6 addresses. Within each
+    /// family, addresses are ordered by their numeric value. Duplicates are
+    /// kept. The order only depends on the set of addresses and is
+    /// therefore stable across lookups, which is useful for comparing
+    /// results, e.g., in tests. It has nothing to do with which address
+    /// is best to connect to.
+    pub fn addrs_sorted(&self) -> Vec<IpAddr> {
+        let mut res = self.addrs.clone();
+        res.sort();
+        res
+    }
 
-//------------ HostByName ----------------------------------------------------
+    /// Returns an owned copy of the addresses of the host.
+    ///
+    /// The addresses are in the same order as those returned by `addrs()`.
+    pub fn to_owned_addrs(&self) -> Vec<IpAddr> {
+        self.addrs.clone()
+    }
 
-/// The future returned by `poll_host_by_name()`.
-///
-/// Resolves into a `HostEnt` value if the lookup is successful or `None` if
-/// there is no such name.
-pub struct HostByName(ByNameInner);
+    /// Returns an iterator over the IPv4 addresses of the host.
+    pub fn v4_addrs(&self) -> V4Addrs {
+        V4Addrs(self.addrs.iter())
+    }
 
-enum ByNameInner {
-    Files(HostEnt),
-    Dns(dns::HostByName),
-    Error(io::Error),
-    Done,
-}
+    /// Returns an iterator over the IPv6 addresses of the host.
+    pub fn v6_addrs(&self) -> V6Addrs {
+        V6Addrs(self.addrs.iter())
+    }
 
-impl HostByName {
-    pub fn new(name: &str, reactor: &reactor::Handle) -> Self {
-        if let Ok(addr) = IpAddr::from_str(name) {
-            return HostByName(ByNameInner::Files(HostEnt {
-                name: name.into(),
-                aliases: Vec::new(),
-                addrs: vec!(addr),
-            }))
-        }
-        let name = match DNameBuf::from_str(name) {
-            Ok(name) => name,
-            Err(e) => {
-                return HostByName(ByNameInner::Error(
-                    io::Error::new(io::ErrorKind::Other, e)
-                ))
-            }
-        };
-        HostByName(match files::get_host_by_name(&name) {
-            Ok(Some(ent)) => ByNameInner::Files(ent),
-            Ok(None) => ByNameInner::Dns(dns::HostByName::new(name, reactor)),
-            Err(err) => ByNameInner::Error(err),
-        })
+    /// Returns the addresses of the host together with their TTL.
+    ///
+    /// The addresses are the same and in the same order as those returned
+    /// by `addrs()`. Each is paired with the time to live of the DNS record
+    /// it was taken from, allowing caches to expire addresses
+    /// individually. Addresses from sources without a notion of TTLs, such
+    /// as the hosts file, have a TTL of `None`, meaning they don’t expire.
+    pub fn addr_ttls(&self) -> &[(IpAddr, Option<Duration>)] {
+        &self.ttls
     }
-}
 
+    /// Returns the first IPv4 and the first IPv6 address of the host.
+    ///
+    /// This is the pair of addresses a dual-stack client such as a Happy
+    /// Eyeballs dialer starts with. Either is `None` if the host has no
+    /// address of that family.
+    pub fn first_of_each_family(&self)
+                                -> (Option<Ipv4Addr>, Option<Ipv6Addr>) {
+        (self.v4_addrs().next(), self.v6_addrs().next())
+    }
 
-impl Future for HostByName {
-    type Item = Option<HostEnt>;
-    type Error = io::Error;
+    /// Converts the entry into its name, aliases, and addresses.
+    ///
+    /// This is handy for moving the result of a lookup into other data
+    /// structures without cloning. All other information of the entry is
+    /// dropped.
+    pub fn into_parts(self) -> (String, Vec<String>, Vec<IpAddr>) {
+        (self.name, self.aliases, self.addrs)
+    }
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        if let ByNameInner::Dns(ref mut lookup) = self.0 {
-            return lookup.poll();
+    /// Merges the entry found by a later source into this entry.
+    ///
+    /// This is what a `[SUCCESS=merge]` action in the name service
+    /// configuration does. The name of this entry is kept. The name and
+    /// aliases of `other` are added to the aliases and its addresses to
+    /// the addresses unless they are already present. The merged entry is
+    /// only authenticated if both entries were and truncated if either
+    /// was.
+    ///
+    /// Names are compared as by `matches_name()`. The aliases of this entry
+    /// keep their order and spelling, new ones are appended in the order
+    /// and spelling of `other`. Since sources are merged in the order of
+    /// the configuration, a `files [SUCCESS=merge] dns` rule always
+    /// results in the aliases from the hosts file in file order followed
+    /// by those only found via DNS.
+    fn merge(&mut self, other: HostEnt) {
+        let names = Some(other.name).into_iter().chain(other.aliases);
+        for name in names {
+            if !self.matches_name(&name) {
+                self.aliases.push(name)
+            }
         }
-        match mem::replace(&mut self.0, ByNameInner::Done) {
-            ByNameInner::Files(res) => Ok(Async::Ready(Some(res))),
-            ByNameInner::Error(err) => Err(err),
-            ByNameInner::Done => panic!("polling a resolved HostByName"),
-            _ => panic!()
+        for item in other.ttls {
+            if !self.addrs.contains(&item.0) {
+                self.addrs.push(item.0);
+                self.ttls.push(item);
+            }
+        }
+        for item in other.files {
+            if self.addr_file(&item.0).is_none() {
+                self.files.push(item)
+            }
+        }
+        self.authenticated = self.authenticated && other.authenticated;
+        self.truncated = self.truncated || other.truncated;
+        if self.resolved_via.is_none() {
+            self.resolved_via = other.resolved_via
         }
     }
-}
-
 
-//------------ HostByAddr ----------------------------------------------------
+    /// Applies the options that shape the result of a lookup.
+    ///
+    /// Returns `None` if no addresses are left afterwards.
+    fn finalize(mut self, options: &ResolverOptions) -> Option<Self> {
+        if !options.collect_aliases {
+            self.aliases = Vec::new()
+        }
+        if options.family != Family::Both {
+            self.addrs.retain(|addr| options.family.matches(addr))
+        }
+        if options.exclude_loopback
+                && self.addrs.iter().any(|addr| !is_local_only(addr)) {
+            self.addrs.retain(|addr| !is_local_only(addr))
+        }
+        if options.shuffle {
+            let seed = options.shuffle_seed.unwrap_or_else(random_seed);
+            shuffle(&mut self.addrs, &mut XorShift::new(seed));
+        }
+        else if let Some(ref conf) = options.gai_conf {
+            conf.sort(&mut self.addrs)
+        }
+        match options.preference {
+            Preference::Any => { }
+            Preference::PreferV4 => {
+                self.addrs.sort_by_key(|addr| addr.is_ipv6())
+            }
+            Preference::PreferV6 => {
+                self.addrs.sort_by_key(|addr| addr.is_ipv4())
+            }
+        }
+        if options.first_only {
+            self.addrs.truncate(1)
+        }
+        let addrs = &self.addrs;
+        self.files.retain(|item| addrs.contains(&item.0));
+        let ttls = mem::replace(&mut self.ttls, Vec::new());
+        self.ttls = self.addrs.iter().map(|addr| {
+            (*addr, ttls.iter().find(|item| item.0 == *addr)
+                                .and_then(|item| item.1))
+        }).collect();
+        if self.addrs.is_empty() {
+            return None
+        }
+        Some(self)
+    }
 
-/// The future returned by `poll_host_by_addr()`.
-///
-/// Resolves into a `HostEnt` value if the lookup is successful or `None` if
-/// there is no such address.
-pub struct HostByAddr(ByAddrInner);
+    /// Returns the name that was actually queried in DNS.
+    ///
+    /// If the entry was retrieved from DNS, this is the name the successful
+    /// queries were asked for, i.e., the host name with the search list
+    /// suffix applied, if any. For instance, if `foo` resolved through
+    /// the search domain `corp.example`, this is `foo.corp.example`. Like
+    /// `name()`, it doesn’t have a trailing dot. Unlike `name()`, it isn’t
+    /// affected by CNAME records.
+    ///
+    /// For all other entries, returns `None`.
+    pub fn resolved_via(&self) -> Option<&str> {
+        self.resolved_via.as_ref().map(AsRef::as_ref)
+    }
 
-enum ByAddrInner {
-    Files(HostEnt),
-    Dns(dns::HostByAddr),
-    Error(io::Error),
-    Done
-}
+    /// Returns the hosts file an address was taken from.
+    ///
+    /// This is only available if the lookup consulted several hosts files
+    /// as configured via `ResolverOptions::hosts_files()`, which helps
+    /// finding out which file takes precedence. For all other entries and
+    /// for addresses not taken from a hosts file, returns `None`.
+    pub fn addr_file(&self, addr: &IpAddr) -> Option<&Path> {
+        self.files.iter().find(|item| item.0 == *addr)
+                  .map(|item| item.1.as_ref())
+    }
+
+    /// Returns whether the entry was authenticated via DNSSEC.
+    ///
+    /// This is `true` only if the entry was retrieved from DNS and all
+    /// answers it was built from had the Authenticated Data bit set by a
+    /// validating resolver. Since this bit is only meaningful if the path
+    /// to the resolver is trusted, treat it with care.
+    ///
+    /// For all other entries, including those where the validation status
+    /// is unknown, returns `false`.
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticated
+    }
+
+    /// Returns whether answer records were ignored while building the entry.
+    ///
+    /// The DNS source processes only a limited number of records of each
+    /// type from a single response as set via
+    /// `ResolverOptions::max_answer_records()`. If a response had more,
+    /// the remaining ones were skipped and the entry may lack addresses or
+    /// aliases.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+
+impl PartialEq for HostEnt {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HostEnt { }
+
+impl PartialOrd for HostEnt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HostEnt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.name.cmp(&other.name).then_with(|| {
+            self.addrs_sorted().cmp(&other.addrs_sorted())
+        })
+    }
+}
+
+
+//------------ AliasStrs -----------------------------------------------------
+
+/// An iterator over the aliases of a host entry.
+#[derive(Clone, Debug)]
+pub struct AliasStrs<'a>(slice::Iter<'a, String>);
+
+impl<'a> Iterator for AliasStrs<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(AsRef::as_ref)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+
+//------------ V4Addrs and V6Addrs -------------------------------------------
+
+/// An iterator over the IPv4 addresses of a host entry.
+#[derive(Clone, Debug)]
+pub struct V4Addrs<'a>(slice::Iter<'a, IpAddr>);
+
+impl<'a> Iterator for V4Addrs<'a> {
+    type Item = Ipv4Addr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(addr) = self.0.next() {
+            if let IpAddr::V4(addr) = *addr {
+                return Some(addr)
+            }
+        }
+        None
+    }
+}
+
+/// An iterator over the IPv6 addresses of a host entry.
+#[derive(Clone, Debug)]
+pub struct V6Addrs<'a>(slice::Iter<'a, IpAddr>);
+
+impl<'a> Iterator for V6Addrs<'a> {
+    type Item = Ipv6Addr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(addr) = self.0.next() {
+            if let IpAddr::V6(addr) = *addr {
+                return Some(addr)
+            }
+        }
+        None
+    }
+}
+
+
+//------------ ResolverOptions -----------------------------------------------
+
+/// Options for host lookups.
+///
+/// The options start out with the defaults via `ResolverOptions::new()` or
+/// `ResolverOptions::default()` and can be changed by chaining calls to the
+/// builder methods.
+///
+/// The defaults consider the following environment variables:
+///
+/// * `NETDB_NO_IPV6`: if present and not empty, enables `no_ipv6`.
+///
+/// The system’s name service configuration is kept per thread and only
+/// read again when the modification time or size of the file changes.
+/// Creating defaults thus costs a `stat` of the file rather than parsing
+/// it, even for lookups started via the functions without options, while
+/// changes to the file are still picked up.
+#[derive(Clone, Debug)]
+pub struct ResolverOptions {
+    /// The resolver configuration to use instead of the system’s.
+    resolv_conf: Option<ResolvConf>,
+
+    /// Fail instead of falling back to a local name server.
+    require_nameservers: bool,
+
+    /// The server to query via DNS-over-TLS instead of the name servers.
+    tls_server: Option<TlsServer>,
+
+    /// Don’t query for IPv6 addresses.
+    no_ipv6: bool,
+
+    /// The address families to look up.
+    family: Family,
+
+    /// The probe for IPv6 connectivity if it should be used.
+    ipv6_probe: Option<fn() -> bool>,
+
+    /// The preferred address family.
+    preference: Preference,
+
+    /// Stop at the first address found.
+    first_only: bool,
+
+    /// How to ask the queries for the address families.
+    query_order: QueryOrder,
+
+    /// Drop loopback and link-local addresses if others are present.
+    exclude_loopback: bool,
+
+    /// Randomize the order of the addresses.
+    shuffle: bool,
+
+    /// The seed for shuffling if not a random one.
+    shuffle_seed: Option<u64>,
+
+    /// The address selection configuration for sorting the addresses.
+    gai_conf: Option<GaiConf>,
+
+    /// Treat `*` entries in the hosts file as wildcards.
+    hosts_wildcards: bool,
+
+    /// The hosts files to use instead of the system’s.
+    hosts_files: Vec<PathBuf>,
+
+    /// Fail on malformed lines in hosts files rather than skipping them.
+    strict_hosts_files: bool,
+
+    /// The maximum length of a line in hosts files.
+    max_hosts_line_len: Option<usize>,
+
+    /// Adjust DNS queries if systemd-resolved’s stub resolver is used.
+    systemd_stub: bool,
+
+    /// Fill the aliases of the host entry.
+    collect_aliases: bool,
+
+    /// The maximum number of records of a type taken from a DNS response.
+    max_answer_records: usize,
+
+    /// The name service configuration.
+    nsswitch: Conf,
+
+    /// The sources provided by the application.
+    sources: SourceRegistry,
+
+    /// The backend for the dns service if not the built-in one.
+    dns_backend: Option<SharedBackend>,
+
+    /// Resolver configurations for names within specific domains.
+    ///
+    /// The domains are kept lowercase and without a trailing dot.
+    split_dns: Vec<(String, ResolvConf)>,
+}
+
+impl ResolverOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Disables queries for IPv6 addresses.
+    ///
+    /// If enabled, lookups by host name will not ask DNS for AAAA records.
+    /// This is useful on hosts without working IPv6 where these queries
+    /// only cost time. IPv6 literals are still accepted as host names.
+    pub fn no_ipv6(mut self, value: bool) -> Self {
+        self.no_ipv6 = value;
+        self
+    }
+
+    /// Skips IPv6 queries if the host has no IPv6 connectivity.
+    ///
+    /// If enabled, the DNS source checks whether the host has a route to
+    /// the global IPv6 internet through a non-loopback, non-link-local
+    /// address before querying for AAAA records, much like glibc does for
+    /// `getaddrinfo()` with `AI_ADDRCONFIG`. If it doesn’t, the AAAA query
+    /// is skipped. This avoids waiting for answers that are useless anyway.
+    ///
+    /// The probe doesn’t send any packets. It is ignored if the lookup is
+    /// explicitly restricted to IPv6 via `family()`.
+    pub fn probe_ipv6(mut self, value: bool) -> Self {
+        self.ipv6_probe = if value { Some(dns::has_ipv6_connectivity) }
+                          else { None };
+        self
+    }
+
+    /// Restricts the lookup to an address family.
+    ///
+    /// Only addresses of the given family are queried for and returned. If
+    /// this leaves a host without addresses, the lookup resolves into
+    /// `None`. The default is `Family::Both`, i.e., no restriction.
+    pub fn family(mut self, family: Family) -> Self {
+        self.family = family;
+        self
+    }
+
+    /// Sets the preferred address family.
+    ///
+    /// Addresses of the preferred family are placed first in the addresses
+    /// of the host entry. The default is `Preference::Any`.
+    pub fn preference(mut self, preference: Preference) -> Self {
+        self.preference = preference;
+        self
+    }
+
+    /// Stops the lookup at the first address found.
+    ///
+    /// If enabled, the lookup finishes as soon as any source yields an
+    /// address and the resulting host entry contains only that one address.
+    /// If additionally a family is preferred, DNS is queried for the
+    /// preferred family first and the query for the other family is only
+    /// issued if the first one didn’t produce any addresses.
+    pub fn first_only(mut self, value: bool) -> Self {
+        self.first_only = value;
+        self
+    }
+
+    /// Sets how the DNS queries for the address families are asked.
+    ///
+    /// The default is `QueryOrder::Parallel` which asks for A and AAAA
+    /// records at the same time. The sequential orders start with the
+    /// family given via `preference()` or with IPv4 if there is none.
+    pub fn query_order(mut self, order: QueryOrder) -> Self {
+        self.query_order = order;
+        self
+    }
+
+    /// Excludes loopback and link-local addresses from the result.
+    ///
+    /// If enabled, addresses in `127.0.0.0/8`, `::1`, `169.254.0.0/16`,
+    /// and `fe80::/10` are removed from the host entry, which is useful
+    /// when looking for a routable address of a host. If the entry has
+    /// only such addresses, they are kept as a last resort.
+    ///
+    /// The addresses are removed before `first_only` is applied.
+    pub fn exclude_loopback(mut self, value: bool) -> Self {
+        self.exclude_loopback = value;
+        self
+    }
+
+    /// Randomizes the order of the addresses of the host entry.
+    ///
+    /// If enabled, the addresses are shuffled after they have been
+    /// collected so that clients connecting to the first address spread
+    /// their load over all addresses of the host. The shuffled order
+    /// replaces any order given by the sources, such as the sortlist of the
+    /// resolver configuration, and the addresses aren’t sorted via
+    /// `gai_conf()`. A family preference is still applied afterwards, and
+    /// `first_only` picks the first address of the shuffled order.
+    ///
+    /// Each lookup uses a new random seed unless one is set via
+    /// `shuffle_seed()`.
+    pub fn shuffle(mut self, value: bool) -> Self {
+        self.shuffle = value;
+        self
+    }
+
+    /// Sets the seed for shuffling the addresses.
+    ///
+    /// With a fixed seed, the same addresses are always shuffled into the
+    /// same order, which is mostly useful for testing. The seed only has an
+    /// effect if `shuffle()` is enabled.
+    pub fn shuffle_seed(mut self, seed: u64) -> Self {
+        self.shuffle_seed = Some(seed);
+        self
+    }
+
+    /// Orders the addresses of the host entry using address selection rules.
+    ///
+    /// If given, the addresses are sorted by the precedence and scope
+    /// tables of `conf` as described with `GaiConf::sort()`. Use
+    /// `GaiConf::system()` to follow the system’s `/etc/gai.conf` like
+    /// glibc’s `getaddrinfo()` does. By default, the addresses are not
+    /// sorted. If `shuffle()` is enabled, the shuffled order is used
+    /// instead and the tables are ignored.
+    pub fn gai_conf(mut self, conf: GaiConf) -> Self {
+        self.gai_conf = Some(conf);
+        self
+    }
+
+    /// Enables wildcard entries in the hosts file.
+    ///
+    /// If enabled, a name in the hosts file whose first label is `*`, for
+    /// instance `*.local.test`, matches any name below the remaining
+    /// suffix, e.g., `app.local.test`. Exact entries take precedence. This
+    /// is handy for local development setups.
+    ///
+    /// > **Note.** This is a non-standard extension not supported by POSIX
+    /// > or the system’s own resolver. It is therefore disabled by default.
+    pub fn hosts_wildcards(mut self, value: bool) -> Self {
+        self.hosts_wildcards = value;
+        self
+    }
+
+    /// Sets the hosts files consulted by the `files` service.
+    ///
+    /// By default, only the system’s hosts file `/etc/hosts` is used. If
+    /// several files are given, they are consulted in order and the
+    /// addresses for a name are collected from all of them. Each address is
+    /// attributed to the first file listing it, which is available via
+    /// `HostEnt::addr_file()`. For lookups by address, the first file
+    /// knowing the address wins. Files that don’t exist are skipped.
+    pub fn hosts_files<I>(mut self, paths: I) -> Self
+                       where I: IntoIterator, I::Item: Into<PathBuf> {
+        self.hosts_files = paths.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Enables strict parsing of hosts files.
+    ///
+    /// By default, lines of a hosts file that fail to parse are skipped,
+    /// just like the system’s own resolver does. If enabled, such a line
+    /// makes the `files` service fail with an error of kind `InvalidData`
+    /// whose message contains the number of the line. Errors reading the
+    /// file keep their kind either way. This helps diagnosing a corrupt
+    /// hosts file.
+    pub fn strict_hosts_files(mut self, value: bool) -> Self {
+        self.strict_hosts_files = value;
+        self
+    }
+
+    /// Sets the maximum length of a line in hosts files in bytes.
+    ///
+    /// Longer lines are skipped, or make the `files` service fail if
+    /// strict parsing is enabled, without reading them into memory in
+    /// full. This protects against hosts files from untrusted sources
+    /// with huge lines. By default, lines can be of any length.
+    pub fn max_hosts_line_len(mut self, len: usize) -> Self {
+        self.max_hosts_line_len = Some(len);
+        self
+    }
+
+    /// Adjusts DNS queries to systemd-resolved’s stub resolver.
+    ///
+    /// On systems using systemd-resolved, `resolv.conf` lists the stub
+    /// resolver on `127.0.0.53` as the name server. If such a name server
+    /// is detected, the DNS source asks the A and AAAA queries one after
+    /// another as if the `single-request` option was given, which is known
+    /// to work reliably with the stub. The query for the preferred family
+    /// is still asked first.
+    ///
+    /// The detection is enabled by default.
+    pub fn systemd_stub(mut self, value: bool) -> Self {
+        self.systemd_stub = value;
+        self
+    }
+
+    /// Enables collecting the aliases of a host.
+    ///
+    /// If disabled, the aliases of the resulting host entry are always
+    /// empty and the DNS source skips extracting them from the CNAME
+    /// records of its answers. This saves some processing and memory for
+    /// callers that never look at the aliases.
+    ///
+    /// Aliases are collected by default.
+    pub fn collect_aliases(mut self, value: bool) -> Self {
+        self.collect_aliases = value;
+        self
+    }
+
+    /// Sets the maximum number of answer records processed per response.
+    ///
+    /// A resolver returning an enormous response could otherwise make the
+    /// DNS source collect any number of addresses and aliases. Only the
+    /// first `limit` address records and the first `limit` CNAME records
+    /// of each response are used, the rest is ignored and the resulting
+    /// entry is marked as truncated. See `HostEnt::is_truncated()`.
+    ///
+    /// The default is `MAX_ANSWER_RECORDS`.
+    pub fn max_answer_records(mut self, limit: usize) -> Self {
+        self.max_answer_records = limit;
+        self
+    }
+
+    /// Sets the name service configuration.
+    ///
+    /// The rules of the `hosts` database determine which sources are
+    /// consulted in which order. By default, the system’s configuration as
+    /// returned by `Conf::system()` when the options were created is used.
+    /// If the configuration has no `hosts` database, the hosts file is
+    /// consulted first and DNS second.
+    pub fn nsswitch_conf(mut self, conf: Conf) -> Self {
+        self.nsswitch = conf;
+        self
+    }
+
+    /// Sets the sources provided by the application.
+    ///
+    /// Services in the name service configuration that this crate doesn’t
+    /// know are looked up in `sources`. See `NameSource` for details.
+    pub fn sources(mut self, sources: SourceRegistry) -> Self {
+        self.sources = sources;
+        self
+    }
+
+    /// Sets the resolver backing the `dns` service.
+    ///
+    /// By default, DNS lookups use the resolver of the `domain` crate. See
+    /// `DnsBackend` for details on providing a different one. With the
+    /// `hickory` feature enabled, `HickoryBackend` is available as an
+    /// alternative.
+    ///
+    /// Lookups that need access to the DNS messages, such as
+    /// `poll_host_by_name_raw()`, and lookups through a `ResolverPool`
+    /// always use the `domain` resolver.
+    pub fn dns_backend<B>(mut self, backend: B) -> Self
+                       where B: DnsBackend + Send + Sync + 'static {
+        self.dns_backend = Some(SharedBackend::new(backend));
+        self
+    }
+
+    /// Sets the resolver configuration for DNS lookups.
+    ///
+    /// By default, the system’s configuration as returned by
+    /// `ResolvConf::system()` is used. Providing a configuration allows,
+    /// for instance, to point lookups at a local test resolver.
+    pub fn resolv_conf(mut self, conf: ResolvConf) -> Self {
+        self.resolv_conf = Some(conf);
+        self
+    }
+
+    /// Fails DNS lookups if the configuration has no name servers.
+    ///
+    /// If the resolver configuration doesn’t list any name servers, glibc
+    /// sends queries to the name server on the local host at `127.0.0.1`.
+    /// This is the default. On hosts without a local name server, the
+    /// lookup then only fails after the queries have timed out. If this
+    /// option is enabled, the DNS source instead fails right away with an
+    /// error of kind `NotConnected`.
+    ///
+    /// The option only applies to the resolver of the `domain` crate, not
+    /// to a backend set via `dns_backend()`.
+    pub fn require_nameservers(mut self, value: bool) -> Self {
+        self.require_nameservers = value;
+        self
+    }
+
+    /// Sets the resolver configuration for names within a domain.
+    ///
+    /// DNS lookups for names that equal `domain` or end in it use `conf`
+    /// instead of the general configuration given via `resolv_conf()` or
+    /// the system’s. This allows sending queries for internal domains to
+    /// internal name servers while all other names are resolved via public
+    /// ones. The domain is matched on label boundaries and ignoring ASCII
+    /// case. If a name is within several of the domains, the longest one
+    /// wins. Calling the method again for the same domain replaces the
+    /// configuration.
+    ///
+    /// The name is matched as given, i.e., before the search list is
+    /// applied. Lookups by address always use the general configuration.
+    pub fn split_dns(mut self, domain: &str, conf: ResolvConf) -> Self {
+        let domain = domain.trim_right_matches('.').to_ascii_lowercase();
+        self.split_dns.retain(|item| item.0 != domain);
+        self.split_dns.push((domain, conf));
+        self
+    }
+
+    /// Sets a server to query via DNS-over-TLS.
+    ///
+    /// If set, DNS queries are sent to this server only, encrypted via TLS,
+    /// and the server’s certificate is validated against the server’s
+    /// name. The name servers of the resolver configuration are not used.
+    /// Backends set via `dns_backend()` are skipped for such lookups.
+    ///
+    /// > **Note.** The resolver of the `domain` crate currently in use
+    /// > only knows the UDP and TCP transports and has no way to plug in
+    /// > another one. Until it gains TLS support, a lookup that needs to
+    /// > use DNS fails with an error instead of silently sending queries
+    /// > in the clear.
+    pub fn dns_over_tls(mut self, server: TlsServer) -> Self {
+        self.tls_server = Some(server);
+        self
+    }
+}
+
+impl ResolverOptions {
+    /// Returns the split DNS configuration for `name` if there is one.
+    fn split_dns_conf(&self, name: &str) -> Option<&ResolvConf> {
+        let name = name.trim_right_matches('.').to_ascii_lowercase();
+        self.split_dns.iter().filter(|&&(ref domain, _)| {
+            domain.is_empty() || name == *domain
+            || (name.ends_with(domain.as_str())
+                && name[..name.len() - domain.len()].ends_with('.'))
+        }).max_by_key(|item| item.0.len()).map(|item| &item.1)
+    }
+
+    /// Returns the name service configuration to use.
+    fn nsswitch(&self) -> &Conf {
+        &self.nsswitch
+    }
+
+    /// Returns the address families queried for tracing.
+    fn family_str(&self) -> &'static str {
+        if self.no_ipv6 || self.family == Family::V4 {
+            return "ipv4"
+        }
+        if self.family == Family::V6 {
+            return "ipv6"
+        }
+        match self.preference {
+            Preference::Any => "any",
+            Preference::PreferV4 => "prefer-ipv4",
+            Preference::PreferV6 => "prefer-ipv6",
+        }
+    }
+}
+
+impl Default for ResolverOptions {
+    fn default() -> Self {
+        ResolverOptions {
+            resolv_conf: None,
+            require_nameservers: false,
+            tls_server: None,
+            no_ipv6: env_flag("NETDB_NO_IPV6"),
+            family: Family::Both,
+            ipv6_probe: None,
+            preference: Preference::Any,
+            first_only: false,
+            query_order: QueryOrder::Parallel,
+            exclude_loopback: false,
+            shuffle: false,
+            shuffle_seed: None,
+            gai_conf: None,
+            hosts_wildcards: false,
+            hosts_files: Vec::new(),
+            strict_hosts_files: false,
+            max_hosts_line_len: None,
+            systemd_stub: true,
+            collect_aliases: true,
+            max_answer_records: MAX_ANSWER_RECORDS,
+            nsswitch: Conf::system_cached(),
+            sources: SourceRegistry::new(),
+            dns_backend: None,
+            split_dns: Vec::new(),
+        }
+    }
+}
+
+
+//------------ Preference ----------------------------------------------------
+
+/// The address family preferred by a lookup.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Preference {
+    /// No family is preferred.
+    Any,
+
+    /// IPv4 addresses are preferred.
+    PreferV4,
+
+    /// IPv6 addresses are preferred.
+    PreferV6,
+}
+
+
+//------------ Family --------------------------------------------------------
+
+/// An address family.
+///
+/// This is used wherever a lookup can be limited to a particular family.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Family {
+    /// IPv4 only.
+    V4,
+
+    /// IPv6 only.
+    V6,
+
+    /// Both IPv4 and IPv6, i.e., no restriction.
+    Both,
+}
+
+impl Family {
+    /// Returns the family of an address.
+    pub fn of(addr: &IpAddr) -> Self {
+        match *addr {
+            IpAddr::V4(_) => Family::V4,
+            IpAddr::V6(_) => Family::V6,
+        }
+    }
+
+    /// Returns the family of the addresses in records of type `rtype`.
+    ///
+    /// Returns `None` if the record type isn’t an address record type.
+    pub fn from_rtype(rtype: Rtype) -> Option<Self> {
+        match rtype {
+            Rtype::A => Some(Family::V4),
+            Rtype::Aaaa => Some(Family::V6),
+            _ => None
+        }
+    }
+
+    /// Returns the address record types for the family.
+    pub fn rtypes(self) -> &'static [Rtype] {
+        const V4: &'static [Rtype] = &[Rtype::A];
+        const V6: &'static [Rtype] = &[Rtype::Aaaa];
+        const BOTH: &'static [Rtype] = &[Rtype::A, Rtype::Aaaa];
+        match self {
+            Family::V4 => V4,
+            Family::V6 => V6,
+            Family::Both => BOTH,
+        }
+    }
+
+    /// Returns whether an address belongs to the family.
+    ///
+    /// For `Family::Both`, this is true for all addresses.
+    pub fn matches(self, addr: &IpAddr) -> bool {
+        match self {
+            Family::V4 => addr.is_ipv4(),
+            Family::V6 => addr.is_ipv6(),
+            Family::Both => true,
+        }
+    }
+
+    /// Returns whether the family includes IPv4.
+    pub fn has_v4(self) -> bool {
+        self != Family::V6
+    }
+
+    /// Returns whether the family includes IPv6.
+    pub fn has_v6(self) -> bool {
+        self != Family::V4
+    }
+}
+
+impl Default for Family {
+    fn default() -> Self {
+        Family::Both
+    }
+}
+
+
+//------------ QueryOrder ----------------------------------------------------
+
+/// How DNS queries for the address families are asked.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum QueryOrder {
+    /// The A and AAAA queries are asked at the same time.
+    ///
+    /// This is the fastest option.
+    Parallel,
+
+    /// The queries are asked one after another.
+    ///
+    /// The query for the preferred family is asked first. The second query
+    /// is always asked once the first one has completed.
+    Sequential,
+
+    /// The second query is only asked if the first one found nothing.
+    ///
+    /// The query for the preferred family is asked first. If it returned
+    /// any addresses, the lookup finishes and the other family is never
+    /// asked for. This saves queries at the expense of potentially
+    /// missing addresses.
+    Fallback,
+}
+
+
+//------------ SourceOrder ---------------------------------------------------
+
+/// A simple choice of the sources consulted for a lookup.
+///
+/// This covers the common cases of changing the order of the hosts file
+/// and DNS without having to provide a name service configuration. A
+/// lookup with an explicit source order ignores the configuration
+/// entirely, both the system’s and one given via
+/// `ResolverOptions::nsswitch_conf()`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SourceOrder {
+    /// Consult the hosts file first, then DNS.
+    FilesFirst,
+
+    /// Consult DNS first, then the hosts file.
+    DnsFirst,
+
+    /// Only consult the hosts file.
+    FilesOnly,
+
+    /// Only consult DNS.
+    DnsOnly,
+}
+
+impl SourceOrder {
+    /// Returns the rules for the source order.
+    fn rules(self) -> Vec<Rule> {
+        let services = match self {
+            SourceOrder::FilesFirst => vec![Service::Files, Service::Dns],
+            SourceOrder::DnsFirst => vec![Service::Dns, Service::Files],
+            SourceOrder::FilesOnly => vec![Service::Files],
+            SourceOrder::DnsOnly => vec![Service::Dns],
+        };
+        services.into_iter().map(Rule::Service).collect()
+    }
+}
+
+impl Default for SourceOrder {
+    fn default() -> Self {
+        SourceOrder::FilesFirst
+    }
+}
+
+
+//------------ ResolutionPlan ------------------------------------------------
+
+/// How a host name would be looked up.
+///
+/// This is returned by `explain()` and `explain_with()`.
+///
+/// The sources are those of the `hosts` database of the name service
+/// configuration that a lookup can consult, in order. Services without a
+/// source, such as `compat` or unregistered ones, are left out. Whether a
+/// source is actually consulted depends on the outcome of the sources
+/// before it and the action rules following them.
+///
+/// If DNS is among the sources, the names are the domain names it queries
+/// for in turn after applying the search list of the resolver
+/// configuration, each with a trailing dot. Otherwise, there are none.
+///
+/// IP address literals and invalid names result in an empty plan since no
+/// source is consulted for them.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ResolutionPlan {
+    sources: Vec<Service>,
+    names: Vec<String>,
+}
+
+impl ResolutionPlan {
+    fn new(name: &str, options: &ResolverOptions) -> Self {
+        if HostByName::prepare(name).is_err() {
+            return Self::default()
+        }
+        let sources: Vec<_> = lookup::rules::<HostSources>(
+            &options.nsswitch()
+        ).into_iter().filter_map(|rule| {
+            match rule {
+                Rule::Service(Service::Other(other)) => {
+                    if options.sources.get(&other).is_some() {
+                        Some(Service::Other(other))
+                    }
+                    else {
+                        None
+                    }
+                }
+                Rule::Service(Service::Compat) => None,
+                Rule::Service(service) => Some(service),
+                Rule::Action(..) => None,
+            }
+        }).collect();
+        let names = if sources.contains(&Service::Dns) {
+            dns::search_names(name, &dns::resolv_conf_for(options, name))
+                .iter().map(|name| format!("{}", name)).collect()
+        }
+        else {
+            Vec::new()
+        };
+        ResolutionPlan { sources: sources, names: names }
+    }
+
+    /// Returns the sources that may be consulted in order.
+    pub fn sources(&self) -> &[Service] {
+        self.sources.as_ref()
+    }
+
+    /// Returns the names DNS is queried for in order.
+    pub fn names(&self) -> &[String] {
+        self.names.as_ref()
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// The maximum length of a host name in bytes.
+///
+/// This is the 255 octets a domain name may have on the wire minus the
+/// length octet of the first label and the root label.
+const MAX_NAME_LEN: usize = 253;
+
+/// The default maximum number of answer records of a type per response.
+///
+/// This is far more than any legitimate response for a host name will
+/// contain while still bounding the memory a single response can use.
+pub const MAX_ANSWER_RECORDS: usize = 4096;
+
+/// Returns whether the environment variable `key` is set and not empty.
+fn env_flag(key: &str) -> bool {
+    env::var_os(key).map(|value| !value.is_empty()).unwrap_or(false)
+}
+
+/// Returns the fully qualified domain name from a host entry.
+fn fqdn_of(ent: &HostEnt) -> String {
+    if ent.name.contains('.') {
+        return ent.name.clone()
+    }
+    match ent.aliases.iter().find(|alias| alias.contains('.')) {
+        Some(alias) => host_name(alias),
+        None => ent.name.clone(),
+    }
+}
+
+/// Returns a seed for shuffling addresses.
+///
+/// The seed is derived from the current time and a counter so that
+/// lookups in quick succession still get different seeds.
+fn random_seed() -> u64 {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let count = COUNTER.fetch_add(1, AtomicOrdering::Relaxed) as u64;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)
+                               .unwrap_or_else(|_| Duration::from_secs(0));
+    (now.as_secs() << 30) ^ u64::from(now.subsec_nanos())
+        ^ count.wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
+
+/// A xorshift pseudo random number generator.
+///
+/// This is good enough for spreading load and doesn’t require a
+/// dependency.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // The generator gets stuck at zero.
+        XorShift(if seed == 0 { 0x2545_f491_4f6c_dd1d } else { seed })
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// Shuffles `items` using the Fisher-Yates algorithm.
+fn shuffle<T>(items: &mut [T], rng: &mut XorShift) {
+    for i in (1..items.len()).rev() {
+        let j = (rng.next() % (i as u64 + 1)) as usize;
+        items.swap(i, j)
+    }
+}
+
+/// Returns whether an address is loopback or link-local.
+fn is_local_only(addr: &IpAddr) -> bool {
+    match *addr {
+        IpAddr::V4(addr) => addr.is_loopback() || addr.is_link_local(),
+        IpAddr::V6(addr) => {
+            addr.is_loopback() || addr.segments()[0] & 0xffc0 == 0xfe80
+        }
+    }
+}
+
+/// Returns the host name for a domain name.
+///
+/// Host names in host entries never have a trailing dot, even if they
+/// originate from an absolute domain name.
+fn host_name<N: fmt::Display>(name: N) -> String {
+    let mut res = format!("{}", name);
+    if res.len() > 1 && res.ends_with('.') {
+        res.pop();
+    }
+    res
+}
+
+/// Splits a list of names separated by white space and commas.
+fn split_names(names: &str) -> Vec<&str> {
+    names.split(|ch: char| ch == ',' || ch.is_whitespace())
+         .filter(|name| !name.is_empty())
+         .collect()
+}
+
+/// Returns the outcome of a successful lookup for tracing.
+fn outcome_str<T>(res: &Option<T>) -> &'static str {
+    if res.is_some() { "found" } else { "not found" }
+}
+
+
+//------------ HostByName ----------------------------------------------------
+
+/// The future returned by `poll_host_by_name()`.
+///
+/// Resolves into a `HostEnt` value if the lookup is successful or `None` if
+/// there is no such name.
+///
+/// If all sources fail, the future resolves into an error wrapping a
+/// `LookupError` with the errors of all sources.
+pub struct HostByName {
+    inner: ByNameInner<HostSources>,
+    options: ResolverOptions,
+    span: Span,
+
+    /// Whether to apply the options to the result.
+    ///
+    /// This is cleared for lookups by the cache which keeps raw entries.
+    finalize: bool,
+}
+
+/// The state of a lookup by name shared by `HostByName` and
+/// `HostByNameRaw`.
+enum ByNameInner<D: Lookupable> {
+    Found(D::Item),
+    Lookup(Lookup<D>),
+    Error(io::Error),
+    Done,
+}
+
+impl<D: Lookupable> Future for ByNameInner<D> {
+    type Item = Option<D::Item>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if let ByNameInner::Lookup(ref mut lookup) = *self {
+            return lookup.poll()
+        }
+        match mem::replace(self, ByNameInner::Done) {
+            ByNameInner::Found(res) => Ok(Async::Ready(Some(res))),
+            ByNameInner::Error(err) => Err(err),
+            ByNameInner::Done => panic!("polling a resolved lookup"),
+            ByNameInner::Lookup(_) => unreachable!(),
+        }
+    }
+}
+
+impl HostByName {
+    pub fn new(name: &str, reactor: &reactor::Handle) -> Self {
+        Self::with_options(name, &ResolverOptions::default(), reactor)
+    }
+
+    /// Creates a lookup using the given options.
+    ///
+    /// The sources are consulted in the order given by the `hosts`
+    /// database of the name service configuration.
+    pub fn with_options(name: &str, options: &ResolverOptions,
+                        reactor: &reactor::Handle) -> Self {
+        let rules = lookup::rules::<HostSources>(&options.nsswitch());
+        Self::with_rules(name, rules, options,
+                         HostSources::new(options, reactor))
+    }
+
+    /// Creates a lookup that only uses the DNS source.
+    pub fn dns_only(name: &str, reactor: &reactor::Handle) -> Self {
+        Self::ordered(name, SourceOrder::DnsOnly, reactor)
+    }
+
+    /// Creates a DNS only lookup sending queries via TLS to `server`.
+    pub fn dns_over_tls(name: &str, server: TlsServer,
+                        reactor: &reactor::Handle) -> Self {
+        let options = ResolverOptions::default().dns_over_tls(server);
+        let sources = HostSources::new(&options, reactor);
+        Self::with_rules(name, SourceOrder::DnsOnly.rules(), &options,
+                         sources)
+    }
+
+    /// Creates a lookup consulting the sources in the given order.
+    pub fn ordered(name: &str, order: SourceOrder,
+                   reactor: &reactor::Handle) -> Self {
+        let options = ResolverOptions::default();
+        let sources = HostSources::new(&options, reactor);
+        Self::with_rules(name, order.rules(), &options, sources)
+    }
+
+    /// Creates a lookup taking DNS resolvers from a pool.
+    ///
+    /// The lookup uses the options of the pool.
+    pub fn pooled(name: &str, pool: &ResolverPool) -> Self {
+        let rules = lookup::rules::<HostSources>(
+            &pool.options().nsswitch()
+        );
+        Self::with_rules(name, rules, pool.options(),
+                         HostSources::pooled(pool))
+    }
+
+    /// Creates a lookup consulting the sources given by `rules`.
+    fn with_rules(name: &str, rules: Vec<Rule>, options: &ResolverOptions,
+                  sources: HostSources) -> Self {
+        let span = Span::resolve(name, options.family_str());
+        let inner = match HostByName::prepare(name) {
+            Ok(qname) => {
+                ByNameInner::Lookup(Lookup::new(
+                    rules, sources, Key::Name(qname, name.into()),
+                    span.clone()
+                ))
+            }
+            Err(Ok(ent)) => ByNameInner::Found(ent),
+            Err(Err(err)) => ByNameInner::Error(err),
+        };
+        HostByName {
+            inner: inner,
+            options: options.clone(),
+            span: span,
+            finalize: true,
+        }
+    }
+
+    /// Makes the lookup resolve into the entry as found by the sources.
+    fn raw(mut self) -> Self {
+        self.finalize = false;
+        self
+    }
+
+    /// Prepares a host name for lookup.
+    ///
+    /// Returns the name as a domain name if lookups are necessary or the
+    /// final result for IP literals and invalid names.
+    ///
+    /// A trailing dot marks the name as absolute. It is dropped from the
+    /// returned name since the hosts file doesn’t distinguish between
+    /// absolute and relative names. The DNS source uses the name as given
+    /// and thus skips the search list for absolute names.
+    ///
+    /// Names longer than `MAX_NAME_LEN` bytes, not counting the trailing
+    /// dot, are rejected with an error of kind `InvalidInput`.
+    fn prepare(name: &str)
+               -> Result<DNameBuf, Result<HostEnt, io::Error>> {
+        if let Some(addr) = is_ip_literal(name) {
+            return Err(Ok(HostEnt::literal(name, addr)))
+        }
+        let relative = if name.len() > 1 && name.ends_with('.') {
+            &name[..name.len() - 1]
+        }
+        else {
+            name
+        };
+        if relative.len() > MAX_NAME_LEN {
+            return Err(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("host name too long: {} bytes, at most {} \
+                         allowed", relative.len(), MAX_NAME_LEN)
+            )))
+        }
+        DNameBuf::from_str(relative).map_err(|e| {
+            Err(io::Error::new(io::ErrorKind::Other, e))
+        })
+    }
+}
+
+
+impl Future for HostByName {
+    type Item = Option<HostEnt>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let res = match self.inner.poll() {
+            Ok(Async::Ready(res)) => res,
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(err) => {
+                self.span.outcome("error");
+                return Err(err)
+            }
+        };
+        self.span.outcome(outcome_str(&res));
+        if !self.finalize {
+            return Ok(Async::Ready(res))
+        }
+        Ok(Async::Ready(res.and_then(|ent| ent.finalize(&self.options))))
+    }
+}
+
+
+//------------ HostByNameRaw -------------------------------------------------
+
+/// The future returned by `poll_host_by_name_raw()`.
+///
+/// Resolves into a `HostEnt` value and the DNS answers it was created from
+/// if the lookup is successful or `None` if there is no such name.
+pub struct HostByNameRaw {
+    inner: ByNameInner<RawHostSources>,
+    options: ResolverOptions,
+    span: Span,
+}
+
+impl HostByNameRaw {
+    pub fn new(name: &str, reactor: &reactor::Handle) -> Self {
+        let options = ResolverOptions::default();
+        let span = Span::resolve(name, options.family_str());
+        let inner = match HostByName::prepare(name) {
+            Ok(qname) => {
+                ByNameInner::Lookup(lookup::run_lookup(
+                    &options.nsswitch(),
+                    RawHostSources::new(&options, reactor),
+                    Key::Name(qname, name.into()), span.clone()
+                ))
+            }
+            Err(Ok(ent)) => ByNameInner::Found((ent, Vec::new())),
+            Err(Err(err)) => ByNameInner::Error(err),
+        };
+        HostByNameRaw {
+            inner: inner,
+            options: options,
+            span: span,
+        }
+    }
+}
+
+impl Future for HostByNameRaw {
+    type Item = Option<(HostEnt, Vec<MessageBuf>)>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let res = match self.inner.poll() {
+            Ok(Async::Ready(res)) => res,
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(err) => {
+                self.span.outcome("error");
+                return Err(err)
+            }
+        };
+        self.span.outcome(outcome_str(&res));
+        Ok(Async::Ready(res.and_then(|(ent, answers)| {
+            ent.finalize(&self.options).map(|ent| (ent, answers))
+        })))
+    }
+}
+
+
+//------------ HostByAddr ----------------------------------------------------
+
+/// The future returned by `poll_host_by_addr()`.
+///
+/// Resolves into a `HostEnt` value if the lookup is successful or `None` if
+/// there is no such address.
+///
+/// If all sources fail, the future resolves into an error wrapping a
+/// `LookupError` with the errors of all sources.
+pub struct HostByAddr {
+    lookup: Lookup<HostSources>,
+    span: Span,
+}
 
 impl HostByAddr {
     pub fn new(addr: IpAddr, reactor: &reactor::Handle) -> Self {
-        HostByAddr(match files::get_host_by_addr(addr) {
-            Ok(Some(ent)) => ByAddrInner::Files(ent),
-            Ok(None) => ByAddrInner::Dns(dns::HostByAddr::new(addr, reactor)),
-            Err(err) => ByAddrInner::Error(err),
-        })
+        Self::with_options(addr, &ResolverOptions::default(), reactor)
+    }
+
+    /// Creates a lookup using the given options.
+    ///
+    /// The sources are consulted in the order given by the `hosts`
+    /// database of the name service configuration.
+    pub fn with_options(addr: IpAddr, options: &ResolverOptions,
+                        reactor: &reactor::Handle) -> Self {
+        let span = Span::resolve(addr, if addr.is_ipv4() { "ipv4" }
+                                       else { "ipv6" });
+        HostByAddr {
+            lookup: lookup::run_lookup(
+                &options.nsswitch(), HostSources::new(options, reactor),
+                Key::Addr(addr), span.clone()
+            ),
+            span: span,
+        }
     }
 }
 
@@ -230,15 +2172,933 @@ impl Future for HostByAddr {
     type Error = io::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        if let ByAddrInner::Dns(ref mut lookup) = self.0 {
-            return lookup.poll();
+        match self.lookup.poll() {
+            Ok(Async::Ready(res)) => {
+                self.span.outcome(outcome_str(&res));
+                Ok(Async::Ready(res))
+            }
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(err) => {
+                self.span.outcome("error");
+                Err(err)
+            }
+        }
+    }
+}
+
+
+//------------ CanonicalName -------------------------------------------------
+
+/// The future returned by `poll_canonical_name()` and friends.
+///
+/// Resolves into the name of the host entry found by a lookup or `None` if
+/// there is no such entry. A value can be created from a `HostByName` or
+/// `HostByAddr` future, which allows using lookups with specific options.
+pub struct CanonicalName(CanonicalInner);
+
+enum CanonicalInner {
+    Name(HostByName),
+    Addr(HostByAddr),
+}
+
+impl From<HostByName> for CanonicalName {
+    fn from(lookup: HostByName) -> Self {
+        CanonicalName(CanonicalInner::Name(lookup))
+    }
+}
+
+impl From<HostByAddr> for CanonicalName {
+    fn from(lookup: HostByAddr) -> Self {
+        CanonicalName(CanonicalInner::Addr(lookup))
+    }
+}
+
+impl Future for CanonicalName {
+    type Item = Option<String>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let res = match self.0 {
+            CanonicalInner::Name(ref mut lookup) => try_ready!(lookup.poll()),
+            CanonicalInner::Addr(ref mut lookup) => try_ready!(lookup.poll()),
+        };
+        Ok(Async::Ready(res.map(|ent| ent.name)))
+    }
+}
+
+
+//------------ AddressesOverlap ----------------------------------------------
+
+/// The future returned by `poll_addresses_overlap()`.
+///
+/// Resolves into whether the host entries found for both names share at
+/// least one address.
+pub struct AddressesOverlap(future::Join<HostByName, HostByName>);
+
+impl Future for AddressesOverlap {
+    type Item = bool;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        Ok(Async::Ready(match try_ready!(self.0.poll()) {
+            (Some(a), Some(b)) => {
+                a.addrs.iter().any(|addr| b.addrs.contains(addr))
+            }
+            _ => false
+        }))
+    }
+}
+
+
+//------------ ResolvesTo ----------------------------------------------------
+
+/// The future returned by `resolves_to()`.
+///
+/// Resolves into whether the host entry found for the name contains the
+/// address.
+pub struct ResolvesTo {
+    lookup: HostByName,
+    addr: IpAddr,
+}
+
+impl Future for ResolvesTo {
+    type Item = bool;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        Ok(Async::Ready(match try_ready!(self.lookup.poll()) {
+            Some(ent) => ent.addrs.contains(&self.addr),
+            None => false
+        }))
+    }
+}
+
+
+//------------ HostAddrs -----------------------------------------------------
+
+/// The stream returned by `stream_host_by_name()`.
+///
+/// Yields the addresses of the host as the lookups for their families
+/// complete.
+pub struct HostAddrs {
+    /// The lookups still in progress, one per family.
+    lookups: Vec<HostByName>,
+
+    /// The addresses found but not yet yielded.
+    addrs: VecDeque<IpAddr>,
+}
+
+impl HostAddrs {
+    fn new(name: &str, options: &ResolverOptions,
+           reactor: &reactor::Handle) -> Self {
+        let mut lookups = Vec::new();
+        if options.family.has_v6() && !options.no_ipv6 {
+            lookups.push(HostByName::with_options(
+                name, &options.clone().family(Family::V6), reactor
+            ))
+        }
+        if options.family.has_v4() {
+            lookups.push(HostByName::with_options(
+                name, &options.clone().family(Family::V4), reactor
+            ))
+        }
+        HostAddrs { lookups: lookups, addrs: VecDeque::new() }
+    }
+}
+
+impl Stream for HostAddrs {
+    type Item = IpAddr;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.addrs.is_empty() {
+            let mut i = 0;
+            while i < self.lookups.len() {
+                match self.lookups[i].poll() {
+                    Ok(Async::NotReady) => {
+                        i += 1;
+                        continue
+                    }
+                    Ok(Async::Ready(ent)) => {
+                        self.lookups.remove(i);
+                        if let Some(ent) = ent {
+                            self.addrs.extend(ent.addrs)
+                        }
+                    }
+                    Err(err) => {
+                        self.lookups.remove(i);
+                        return Err(err)
+                    }
+                }
+            }
+        }
+        match self.addrs.pop_front() {
+            Some(addr) => Ok(Async::Ready(Some(addr))),
+            None if self.lookups.is_empty() => Ok(Async::Ready(None)),
+            None => Ok(Async::NotReady)
+        }
+    }
+}
+
+
+//------------ Fqdn ----------------------------------------------------------
+
+/// The future returned by `poll_fqdn()`.
+///
+/// Resolves into the fully qualified domain name or `None` if the name
+/// doesn’t exist.
+pub struct Fqdn(Result<dns::FqdnLookup, Option<Result<String, io::Error>>>);
+
+impl Fqdn {
+    fn new(name: &str, reactor: &reactor::Handle) -> Self {
+        if is_ip_literal(name).is_some() {
+            return Fqdn(Err(Some(Ok(name.into()))))
+        }
+        let options = ResolverOptions::default();
+        Fqdn(match dns::FqdnLookup::new(name, &options, reactor) {
+            Ok(lookup) => Ok(lookup),
+            Err(err) => Err(Some(Err(err))),
+        })
+    }
+}
+
+impl Future for Fqdn {
+    type Item = Option<String>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.0 {
+            Ok(ref mut lookup) => lookup.poll(),
+            Err(ref mut res) => {
+                match res.take() {
+                    Some(res) => res.map(|name| Async::Ready(Some(name))),
+                    None => panic!("polling a resolved Fqdn"),
+                }
+            }
+        }
+    }
+}
+
+
+//------------ LookupError ---------------------------------------------------
+
+/// An error happened during a lookup.
+///
+/// The lookup futures resolve into an `io::Error` if they fail. If all
+/// sources consulted for the lookup failed, this error is of kind
+/// `io::ErrorKind::Other` and wraps a value of this type. You can get to
+/// it through the error’s `get_ref()` method and downcasting.
+#[derive(Debug)]
+pub enum LookupError {
+    /// All sources failed.
+    ///
+    /// Contains the error of each source in the order they were consulted.
+    AllSourcesFailed(Vec<(Service, io::Error)>),
+}
+
+impl error::Error for LookupError {
+    fn description(&self) -> &str {
+        match *self {
+            LookupError::AllSourcesFailed(_) => "all sources failed",
         }
-        match mem::replace(&mut self.0, ByAddrInner::Done) {
-            ByAddrInner::Files(res) => Ok(Async::Ready(Some(res))),
-            ByAddrInner::Error(err) => Err(err),
-            ByAddrInner::Done => panic!("polling a resolved HostByAddr"),
-            _ => panic!()
+    }
+}
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LookupError::AllSourcesFailed(ref errors) => {
+                f.write_str("all sources failed")?;
+                let mut sep = ": ";
+                for &(ref service, ref err) in errors {
+                    write!(f, "{}{}: {}", sep, service, err)?;
+                    sep = "; ";
+                }
+                Ok(())
+            }
         }
     }
 }
 
+impl From<LookupError> for io::Error {
+    fn from(err: LookupError) -> Self {
+        io::Error::new(io::ErrorKind::Other, err)
+    }
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ip_literals() {
+        assert_eq!(is_ip_literal("192.0.2.1"),
+                   Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))));
+        assert_eq!(is_ip_literal("2001:db8::1"),
+                   Some(IpAddr::from_str("2001:db8::1").unwrap()));
+        assert_eq!(is_ip_literal("[::1]"),
+                   Some(IpAddr::from_str("::1").unwrap()));
+        assert_eq!(is_ip_literal("[2001:db8::1]"),
+                   Some(IpAddr::from_str("2001:db8::1").unwrap()));
+        assert_eq!(is_ip_literal("[192.0.2.1]"), None);
+        assert_eq!(is_ip_literal("[]"), None);
+        assert_eq!(is_ip_literal("example.com"), None);
+    }
+
+    #[test]
+    fn bracketed_literals() {
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        for name in &["[::1]", "[2001:db8::1]"] {
+            let ent = core.run(poll_host_by_name(name, &handle))
+                          .unwrap().unwrap();
+            assert_eq!(ent.addrs(), &[is_ip_literal(name).unwrap()]);
+        }
+    }
+
+    #[test]
+    fn overlap() {
+        assert!(addresses_overlap("::1", "[::1]").unwrap());
+        assert!(!addresses_overlap("192.0.2.1", "192.0.2.2").unwrap());
+    }
+
+    #[test]
+    fn resolves_to_addr() {
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        let addr = IpAddr::from_str("192.0.2.1").unwrap();
+        assert!(core.run(resolves_to("192.0.2.1", addr, &handle)).unwrap());
+        assert!(!core.run(resolves_to("192.0.2.2", addr, &handle)).unwrap());
+        assert!(!core.run(resolves_to("[::1]", addr, &handle)).unwrap());
+    }
+
+    #[test]
+    fn streamed_addrs() {
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        let addrs = core.run(
+            stream_host_by_name("192.0.2.1", &handle).collect()
+        ).unwrap();
+        assert_eq!(addrs, [IpAddr::from_str("192.0.2.1").unwrap()]);
+        let addrs = core.run(
+            HostAddrs::new("::1", &ResolverOptions::new().no_ipv6(true),
+                           &handle).collect()
+        ).unwrap();
+        assert!(addrs.is_empty());
+    }
+
+    #[test]
+    fn literal_entries() {
+        let addr = IpAddr::from_str("2001:db8::1").unwrap();
+        let ent = from_literal(addr);
+        assert_eq!(ent.name(), "2001:db8::1");
+        assert!(ent.aliases().is_empty());
+        assert_eq!(ent.addrs(), &[addr]);
+
+        let ent = from_literal_str("[2001:db8::1]").unwrap();
+        assert_eq!(ent.name(), "[2001:db8::1]");
+        assert_eq!(ent.addrs(), &[addr]);
+        assert_eq!(ent, get_host_by_name("[2001:db8::1]").unwrap().unwrap());
+        assert!(from_literal_str("example.com").is_none());
+    }
+
+    #[test]
+    fn absolute_names() {
+        assert_eq!(host_name("example.com."), "example.com");
+        assert_eq!(host_name("example.com"), "example.com");
+        assert_eq!(host_name("."), ".");
+        match HostByName::prepare("example.com.") {
+            Ok(name) => {
+                assert_eq!(name, DNameBuf::from_str("example.com").unwrap())
+            }
+            Err(_) => panic!("absolute name rejected")
+        }
+    }
+
+    #[test]
+    fn raw_literal() {
+        let (ent, answers) = get_host_by_name_raw("192.0.2.1").unwrap()
+                                                              .unwrap();
+        assert_eq!(ent.addrs(), &["192.0.2.1".parse::<IpAddr>().unwrap()]);
+        assert!(answers.is_empty());
+    }
+
+    #[test]
+    fn name_lists() {
+        assert_eq!(split_names("localhost, example.com"),
+                   ["localhost", "example.com"]);
+        assert_eq!(split_names(" a,b\tc ,, d\n"), ["a", "b", "c", "d"]);
+
+        let long = "a".repeat(MAX_NAME_LEN + 1);
+        let res = get_hosts(&format!("192.0.2.1, 2001:db8::1 {}", long))
+                      .unwrap();
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[0].0, "192.0.2.1");
+        assert_eq!(res[0].1.as_ref().unwrap().as_ref().unwrap().addrs(),
+                   host(&["192.0.2.1"]).addrs());
+        assert_eq!(res[1].0, "2001:db8::1");
+        assert_eq!(res[1].1.as_ref().unwrap().as_ref().unwrap().addrs(),
+                   host(&["2001:db8::1"]).addrs());
+        assert_eq!(res[2].0, long);
+        assert_eq!(res[2].1.as_ref().unwrap_err().kind(),
+                   io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn alias_strs() {
+        let mut ent = host(&[]);
+        assert_eq!(ent.alias_strs().next(), None);
+        ent.aliases = vec!["www.example.com".into(), "web".into()];
+        assert_eq!(ent.alias_strs().collect::<Vec<_>>(),
+                   ["www.example.com", "web"]);
+    }
+
+    #[test]
+    fn addrs_by_family() {
+        let ent = host(&["192.0.2.1", "2001:db8::1", "192.0.2.2",
+                         "2001:db8::2"]);
+        assert_eq!(ent.v4_addrs().collect::<Vec<_>>(),
+                   vec![Ipv4Addr::new(192, 0, 2, 1),
+                        Ipv4Addr::new(192, 0, 2, 2)]);
+        assert_eq!(ent.v6_addrs().collect::<Vec<_>>(),
+                   vec![Ipv6Addr::from_str("2001:db8::1").unwrap(),
+                        Ipv6Addr::from_str("2001:db8::2").unwrap()]);
+    }
+
+    #[test]
+    fn first_of_each_family() {
+        assert_eq!(host(&["2001:db8::1", "192.0.2.1", "192.0.2.2"])
+                       .first_of_each_family(),
+                   (Some(Ipv4Addr::new(192, 0, 2, 1)),
+                    Some(Ipv6Addr::from_str("2001:db8::1").unwrap())));
+        assert_eq!(host(&["192.0.2.1"]).first_of_each_family(),
+                   (Some(Ipv4Addr::new(192, 0, 2, 1)), None));
+        assert_eq!(host(&["2001:db8::1"]).first_of_each_family(),
+                   (None, Some(Ipv6Addr::from_str("2001:db8::1").unwrap())));
+        assert_eq!(host(&[]).first_of_each_family(), (None, None));
+    }
+
+    #[test]
+    fn fqdn() {
+        let mut ent = host(&["192.0.2.1"]);
+        ent.name = "myhost".into();
+        assert_eq!(fqdn_of(&ent), "myhost");
+        ent.aliases = vec!["other".into(), "myhost.example.com.".into()];
+        assert_eq!(fqdn_of(&ent), "myhost.example.com");
+        ent.name = "myhost.example.org".into();
+        assert_eq!(fqdn_of(&ent), "myhost.example.org");
+
+        assert_eq!(local_hostname_with(Some("forced.example.com".into()))
+                       .unwrap(),
+                   "forced.example.com");
+        assert_eq!(get_fqdn_for("192.0.2.1").unwrap(),
+                   Some("192.0.2.1".into()));
+    }
+
+    #[test]
+    fn ordering() {
+        let mut a = host(&["192.0.2.2", "192.0.2.1"]);
+        a.name = "a.example.com".into();
+        let b = host(&["192.0.2.1", "192.0.2.2"]);
+        let c = host(&["192.0.2.1", "192.0.2.3"]);
+        let d = host(&["192.0.2.2", "192.0.2.1"]);
+        assert!(a < b);
+        assert!(b < c);
+        assert_eq!(b, d);
+
+        let mut list = vec![c, a, b];
+        list.sort();
+        assert_eq!(list.iter().map(HostEnt::name).collect::<Vec<_>>(),
+                   ["a.example.com", "example.com", "example.com"]);
+        assert_eq!(list[2].addrs()[1], "192.0.2.3".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn untimed_ttls() {
+        let ent = host(&["::1", "192.0.2.1"]);
+        assert_eq!(ent.addr_ttls(),
+                   &[("::1".parse().unwrap(), None),
+                     ("192.0.2.1".parse().unwrap(), None)]);
+        let ent = ent.finalize(&ResolverOptions::new().exclude_loopback(true))
+                     .unwrap();
+        assert_eq!(ent.addr_ttls(), &[("192.0.2.1".parse().unwrap(), None)]);
+    }
+
+    fn host(addrs: &[&str]) -> HostEnt {
+        HostEnt::untimed(
+            "example.com".into(), Vec::new(),
+            addrs.iter().map(|addr| addr.parse().unwrap()).collect()
+        )
+    }
+
+    #[test]
+    fn family() {
+        assert_eq!(Family::from_rtype(Rtype::A), Some(Family::V4));
+        assert_eq!(Family::from_rtype(Rtype::Aaaa), Some(Family::V6));
+        assert_eq!(Family::from_rtype(Rtype::Mx), None);
+        for family in &[Family::V4, Family::V6, Family::Both] {
+            for rtype in family.rtypes() {
+                assert!(family.matches(&match Family::from_rtype(*rtype) {
+                    Some(Family::V4) => "192.0.2.1".parse().unwrap(),
+                    _ => "2001:db8::1".parse().unwrap(),
+                }));
+            }
+        }
+        assert_eq!(Family::Both.rtypes(), &[Rtype::A, Rtype::Aaaa]);
+
+        let all = host(&["192.0.2.1", "2001:db8::1"]);
+        let ent = host(&["192.0.2.1", "2001:db8::1"]);
+        let ent = ent.finalize(&ResolverOptions::new().family(Family::Both))
+                     .unwrap();
+        assert_eq!(ent.addrs(), all.addrs());
+        let ent = ent.finalize(&ResolverOptions::new().family(Family::V6))
+                     .unwrap();
+        assert_eq!(ent.addrs(), host(&["2001:db8::1"]).addrs());
+        assert!(ent.finalize(&ResolverOptions::new().family(Family::V4))
+                   .is_none());
+    }
+
+    #[test]
+    fn family_leaves_no_addrs() {
+        use std::io::Write;
+
+        let mut path = env::temp_dir();
+        path.push(format!("netdb-{}-hosts-v6-only", ::std::process::id()));
+        {
+            let mut file = fs::File::create(&path).unwrap();
+            writeln!(file, "2001:db8::1 v6-only.example").unwrap();
+        }
+        let nsswitch = Conf::parse(&mut io::Cursor::new("hosts: files\n"))
+                           .unwrap();
+        let options = ResolverOptions::new().hosts_files(vec![path.clone()])
+                                            .nsswitch_conf(nsswitch);
+        assert!(get_host_by_name_with("v6-only.example",
+                                      &options.clone().family(Family::V6))
+                    .unwrap().is_some());
+        assert_eq!(get_host_by_name_with("v6-only.example",
+                                         &options.family(Family::V4))
+                       .unwrap(),
+                   None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn addrs_sorted() {
+        let ent = host(&["2001:db8::2", "192.0.2.10", "2001:db8::1",
+                         "192.0.2.9"]);
+        assert_eq!(ent.addrs_sorted(),
+                   host(&["192.0.2.9", "192.0.2.10", "2001:db8::1",
+                          "2001:db8::2"]).addrs());
+    }
+
+    #[test]
+    fn matches_name() {
+        let ent = HostEnt::new("Example.com".into(),
+                               vec!["www.example.COM".into()], Vec::new());
+        assert!(ent.matches_name("example.com"));
+        assert!(ent.matches_name("EXAMPLE.COM."));
+        assert!(ent.matches_name("www.example.com"));
+        assert!(!ent.matches_name("mail.example.com"));
+        assert!(!ent.matches_name("example"));
+    }
+
+    #[test]
+    fn from_parts() {
+        let addr = IpAddr::from_str("192.0.2.1").unwrap();
+        let ent = HostEnt::new("example.com".into(),
+                               vec!["www.example.com".into()], vec![addr]);
+        assert_eq!(ent.name(), "example.com");
+        assert_eq!(ent.aliases(), ["www.example.com"]);
+        assert_eq!(ent.addrs(), &[addr]);
+        assert_eq!(ent.addr_ttls(), &[(addr, None)]);
+        assert!(!ent.is_authenticated());
+        assert_eq!(ent.resolved_via(), None);
+    }
+
+    #[test]
+    fn into_parts() {
+        let ent = HostEnt::untimed(
+            "example.com".into(), vec!["www.example.com".into()],
+            vec!["192.0.2.1".parse().unwrap()]
+        );
+        assert_eq!(ent.to_owned_addrs(), ent.addrs());
+        let (name, aliases, addrs) = ent.into_parts();
+        assert_eq!(name, "example.com");
+        assert_eq!(aliases, ["www.example.com"]);
+        assert_eq!(addrs, [IpAddr::from_str("192.0.2.1").unwrap()]);
+    }
+
+    #[test]
+    fn shuffled_addrs() {
+        let addrs = ["192.0.2.1", "192.0.2.2", "192.0.2.3", "192.0.2.4",
+                     "192.0.2.5"];
+        let options = ResolverOptions::new().shuffle(true).shuffle_seed(42);
+        let ent = host(&addrs).finalize(&options).unwrap();
+        assert_eq!(ent.addrs(),
+                   host(&["192.0.2.2", "192.0.2.3", "192.0.2.1",
+                          "192.0.2.4", "192.0.2.5"]).addrs());
+        assert_eq!(ent.addrs_sorted(), host(&addrs).addrs());
+        assert_eq!(ent.addr_ttls()[0].0, ent.addrs()[0]);
+
+        let ent = host(&addrs).finalize(&options.shuffle(false)).unwrap();
+        assert_eq!(ent.addrs(), host(&addrs).addrs());
+    }
+
+    #[test]
+    fn gai_conf() {
+        let options = ResolverOptions::new().gai_conf(GaiConf::new());
+        let ent = host(&["192.0.2.1", "2001:db8::1", "::1"])
+                      .finalize(&options).unwrap();
+        assert_eq!(ent.addrs(),
+                   host(&["::1", "2001:db8::1", "192.0.2.1"]).addrs());
+
+        // Shuffling takes precedence over sorting.
+        let addrs = ["192.0.2.1", "2001:db8::1", "::1"];
+        let options = ResolverOptions::new().shuffle(true).shuffle_seed(42);
+        let shuffled = host(&addrs).finalize(&options).unwrap();
+        let ent = host(&addrs).finalize(&options.gai_conf(GaiConf::new()))
+                              .unwrap();
+        assert_eq!(ent.addrs(), shuffled.addrs());
+    }
+
+    #[test]
+    fn exclude_loopback() {
+        let options = ResolverOptions::new().exclude_loopback(true);
+
+        let ent = host(&["127.0.1.1", "fe80::1", "192.0.2.1", "::1",
+                         "169.254.0.1", "2001:db8::1"]);
+        let ent = ent.finalize(&options).unwrap();
+        assert_eq!(ent.addrs(), host(&["192.0.2.1", "2001:db8::1"]).addrs());
+
+        let ent = host(&["127.0.0.1", "::1"]).finalize(&options).unwrap();
+        assert_eq!(ent.addrs(), host(&["127.0.0.1", "::1"]).addrs());
+    }
+
+    struct DummySource;
+
+    impl NameSource for DummySource {
+        fn lookup_name(&self, name: &str, _reactor: &reactor::Handle)
+                       -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+            let mut ent = host(&["192.0.2.7"]);
+            ent.name = name.into();
+            Box::new(::futures::future::ok(Some(ent)))
+        }
+
+        fn lookup_addr(&self, _addr: IpAddr, _reactor: &reactor::Handle)
+                       -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+            Box::new(::futures::future::ok(None))
+        }
+    }
+
+    #[test]
+    fn resolution_plan() {
+        let conf = Conf::parse(&mut io::Cursor::new(
+            "hosts: dns [NOTFOUND=return] compat files\n"
+        )).unwrap();
+        let resolv = ResolvConf::parse(&mut io::Cursor::new(
+            "search a.example b.example\n"
+        )).unwrap();
+        let options = ResolverOptions::new().nsswitch_conf(conf)
+                                            .resolv_conf(resolv);
+        let plan = explain_with("WWW", &options);
+        assert_eq!(plan.sources(), [Service::Dns, Service::Files]);
+        assert_eq!(plan.names(),
+                   ["www.a.example.", "www.b.example.", "www."]);
+        assert_eq!(explain_with("www.example.com.", &options).names(),
+                   ["www.example.com."]);
+        assert_eq!(explain_with("192.0.2.1", &options),
+                   ResolutionPlan::default());
+
+        let conf = Conf::parse(&mut io::Cursor::new(
+            "hosts: files unknown\n"
+        )).unwrap();
+        let options = options.nsswitch_conf(conf);
+        let plan = explain_with("www", &options);
+        assert_eq!(plan.sources(), [Service::Files]);
+        assert!(plan.names().is_empty());
+    }
+
+    #[test]
+    fn registered_source() {
+        let conf = Conf::parse(&mut io::Cursor::new(
+            "hosts: mysrc\n"
+        )).unwrap();
+        let mut sources = SourceRegistry::new();
+        sources.register("MySrc", DummySource);
+        assert!(sources.get("MYSRC").is_some());
+        let options = ResolverOptions::new().nsswitch_conf(conf)
+                                            .sources(sources);
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        let ent = core.run(poll_host_by_name_with("dummy.netdb.invalid",
+                                                  &options, &handle))
+                      .unwrap().unwrap();
+        assert_eq!(ent.name(), "dummy.netdb.invalid");
+        assert_eq!(ent.addrs(), host(&["192.0.2.7"]).addrs());
+    }
+
+    /// A source returning a different entry than `DummySource`.
+    struct OtherSource;
+
+    impl NameSource for OtherSource {
+        fn lookup_name(&self, _name: &str, _reactor: &reactor::Handle)
+                       -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+            let mut ent = host(&["192.0.2.8", "192.0.2.7"]);
+            ent.authenticated = true;
+            Box::new(::futures::future::ok(Some(ent)))
+        }
+
+        fn lookup_addr(&self, _addr: IpAddr, _reactor: &reactor::Handle)
+                       -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+            Box::new(::futures::future::ok(None))
+        }
+    }
+
+    #[test]
+    fn merge_action() {
+        // The two sources stand in for files and dns.
+        let lookup = |conf: &str| {
+            let conf = Conf::parse(&mut io::Cursor::new(conf)).unwrap();
+            let mut sources = SourceRegistry::new();
+            sources.register("mysrc", DummySource);
+            sources.register("othersrc", OtherSource);
+            let options = ResolverOptions::new().nsswitch_conf(conf)
+                                                .sources(sources);
+            let mut core = reactor::Core::new().unwrap();
+            let handle = core.handle();
+            core.run(poll_host_by_name_with("dummy.netdb.invalid",
+                                            &options, &handle))
+                .unwrap().unwrap()
+        };
+
+        // Without merge, the first source wins and the second is skipped.
+        let ent = lookup("hosts: mysrc othersrc\n");
+        assert_eq!(ent.name(), "dummy.netdb.invalid");
+        assert!(ent.aliases().is_empty());
+        assert_eq!(ent.addrs(), host(&["192.0.2.7"]).addrs());
+
+        // With merge, the second source augments the first.
+        let ent = lookup("hosts: mysrc [SUCCESS=merge] othersrc\n");
+        assert_eq!(ent.name(), "dummy.netdb.invalid");
+        assert_eq!(ent.aliases(), ["example.com"]);
+        assert_eq!(ent.addrs(), host(&["192.0.2.7", "192.0.2.8"]).addrs());
+        assert!(!ent.is_authenticated());
+    }
+
+    /// A DNS backend knowing one address and a few aliases for every name.
+    struct AliasBackend;
+
+    impl DnsBackend for AliasBackend {
+        fn lookup_name(&self, name: &str, _options: &ResolverOptions,
+                       _reactor: &reactor::Handle)
+                       -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+            let mut ent = host(&["198.51.100.1"]);
+            ent.name = name.into();
+            ent.aliases = vec!["CDN.example".into(), "WWW.example".into()];
+            Box::new(::futures::future::ok(Some(ent)))
+        }
+
+        fn lookup_addr(&self, _addr: IpAddr, _options: &ResolverOptions,
+                       _reactor: &reactor::Handle)
+                       -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+            Box::new(::futures::future::ok(None))
+        }
+    }
+
+    #[test]
+    fn merge_files_and_dns() {
+        use std::io::Write;
+
+        let mut path = env::temp_dir();
+        path.push(format!("netdb-{}-hosts-merge", ::std::process::id()));
+        fs::File::create(&path).unwrap()
+            .write_all(b"192.0.2.1 host.example www.example alias.example\n")
+            .unwrap();
+        let conf = Conf::parse(&mut io::Cursor::new(
+            "hosts: files [SUCCESS=merge] dns\n"
+        )).unwrap();
+        let options = ResolverOptions::new().nsswitch_conf(conf)
+                                            .hosts_files(vec![path.clone()])
+                                            .dns_backend(AliasBackend);
+        let ent = get_host_by_name_with("host.example", &options).unwrap()
+                                                                 .unwrap();
+        assert_eq!(ent.aliases(),
+                   ["www.example", "alias.example", "CDN.example"]);
+        assert_eq!(ent.addrs(), host(&["192.0.2.1", "198.51.100.1"]).addrs());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn merge_aliases() {
+        let mut ent = HostEnt::new(
+            "host.example".into(),
+            vec!["Www.Example".into(), "alias.example".into()],
+            vec!["192.0.2.1".parse().unwrap()]
+        );
+        ent.merge(HostEnt::new(
+            "HOST.example".into(),
+            vec!["www.example".into(), "Other.example".into(),
+                 "ALIAS.EXAMPLE".into(), "other.example".into()],
+            vec!["192.0.2.1".parse().unwrap()]
+        ));
+        assert_eq!(ent.name(), "host.example");
+        assert_eq!(ent.aliases(),
+                   ["Www.Example", "alias.example", "Other.example"]);
+    }
+
+    /// A DNS backend knowing a single address for every name.
+    struct FixedBackend;
+
+    impl DnsBackend for FixedBackend {
+        fn lookup_name(&self, name: &str, _options: &ResolverOptions,
+                       _reactor: &reactor::Handle)
+                       -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+            let mut ent = host(&["198.51.100.1"]);
+            ent.name = name.into();
+            Box::new(::futures::future::ok(Some(ent)))
+        }
+
+        fn lookup_addr(&self, _addr: IpAddr, _options: &ResolverOptions,
+                       _reactor: &reactor::Handle)
+                       -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+            Box::new(::futures::future::ok(None))
+        }
+    }
+
+    #[test]
+    fn explicit_conf() {
+        let conf = Conf::parse(&mut io::Cursor::new(
+            "hosts: nosuchsrc\n"
+        )).unwrap();
+        assert!(get_host_by_name_with_conf("localhost", &conf).unwrap()
+                                                               .is_none());
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        assert!(core.run(poll_host_by_name_with_conf("localhost", &conf,
+                                                     &handle))
+                    .unwrap().is_none());
+        let ent = core.run(poll_host_by_name_with_conf("192.0.2.1", &conf,
+                                                       &handle))
+                      .unwrap().unwrap();
+        assert_eq!(ent.addrs(), host(&["192.0.2.1"]).addrs());
+    }
+
+    #[test]
+    fn dns_backend() {
+        let conf = Conf::parse(&mut io::Cursor::new(
+            "hosts: dns\n"
+        )).unwrap();
+        let options = ResolverOptions::new().nsswitch_conf(conf)
+                                            .dns_backend(FixedBackend);
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        let ent = core.run(poll_host_by_name_with("fixed.netdb.invalid",
+                                                  &options, &handle))
+                      .unwrap().unwrap();
+        assert_eq!(ent.name(), "fixed.netdb.invalid");
+        assert_eq!(ent.addrs(), host(&["198.51.100.1"]).addrs());
+    }
+
+    #[test]
+    fn notfound_return() {
+        let conf = Conf::parse(&mut io::Cursor::new(
+            "hosts: files [NOTFOUND=return] mysrc\n"
+        )).unwrap();
+        let mut sources = SourceRegistry::new();
+        sources.register("mysrc", DummySource);
+        let options = ResolverOptions::new().nsswitch_conf(conf)
+                                            .sources(sources);
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        let res = core.run(poll_host_by_name_with("dummy.netdb.invalid",
+                                                  &options, &handle))
+                      .unwrap();
+        assert!(res.is_none());
+    }
+
+    #[test]
+    fn name_too_long() {
+        let name = "a.".repeat(150);
+        assert_eq!(name.len(), 300);
+        let err = get_host_by_name(&name).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(format!("{}", err),
+                   "host name too long: 299 bytes, at most 253 \
+                    allowed");
+
+        let name = format!("{}a.", "a.".repeat(126));
+        assert!(HostByName::prepare(&name).is_ok());
+    }
+
+    #[test]
+    fn source_order() {
+        assert_eq!(SourceOrder::DnsFirst.rules(),
+                   [Rule::Service(Service::Dns),
+                    Rule::Service(Service::Files)]);
+        assert_eq!(SourceOrder::FilesOnly.rules(),
+                   [Rule::Service(Service::Files)]);
+        assert!(get_host_by_name_ordered("nowhere.netdb.invalid",
+                                         SourceOrder::FilesOnly)
+                    .unwrap().is_none());
+    }
+
+    /// A source knowing the name of a single address.
+    struct PtrSource;
+
+    impl NameSource for PtrSource {
+        fn lookup_name(&self, _name: &str, _reactor: &reactor::Handle)
+                       -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+            Box::new(::futures::future::ok(None))
+        }
+
+        fn lookup_addr(&self, addr: IpAddr, _reactor: &reactor::Handle)
+                       -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+            let ent = if addr == IpAddr::from([192, 0, 2, 7]) {
+                let mut ent = host(&["192.0.2.7"]);
+                ent.name = "ptr.example.com".into();
+                Some(ent)
+            }
+            else {
+                None
+            };
+            Box::new(::futures::future::ok(ent))
+        }
+    }
+
+    #[test]
+    fn canonical_name_for_addr() {
+        let conf = Conf::parse(&mut io::Cursor::new(
+            "hosts: ptrsrc\n"
+        )).unwrap();
+        let mut sources = SourceRegistry::new();
+        sources.register("ptrsrc", PtrSource);
+        let options = ResolverOptions::new().nsswitch_conf(conf)
+                                            .sources(sources);
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        let lookup = HostByAddr::with_options(
+            IpAddr::from([192, 0, 2, 7]), &options, &handle
+        );
+        assert_eq!(core.run(CanonicalName::from(lookup)).unwrap(),
+                   Some("ptr.example.com".into()));
+        let lookup = HostByAddr::with_options(
+            IpAddr::from([192, 0, 2, 8]), &options, &handle
+        );
+        assert_eq!(core.run(CanonicalName::from(lookup)).unwrap(), None);
+    }
+
+    #[test]
+    fn dns_over_tls_unsupported() {
+        let server = TlsServer::new("192.0.2.1:853".parse().unwrap(),
+                                    "dns.example");
+        let err = get_host_by_name_dot("dot.netdb.invalid", server.clone())
+                      .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        let ent = get_host_by_name_dot("192.0.2.2", server).unwrap()
+                                                            .unwrap();
+        assert_eq!(ent.addrs(), &["192.0.2.2".parse::<IpAddr>().unwrap()]);
+    }
+}
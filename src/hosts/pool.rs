@@ -0,0 +1,216 @@
+//! Pools of DNS resolvers.
+
+use std::io;
+use std::cell::Cell;
+use std::rc::Rc;
+use domain::resolv::Resolver;
+use tokio_core::reactor;
+use ::resolv::ResolvConf;
+use super::{dns, HostByName, ResolverOptions};
+
+
+//------------ ResolverPool --------------------------------------------------
+
+/// A pool of DNS resolvers for many concurrent lookups.
+///
+/// Each resolver of the pool has its own sockets. Lookups made through the
+/// pool are handed the resolvers in turn, spreading the in-flight queries
+/// over all of them. This avoids contention on a single resolver when a
+/// large number of names is resolved at the same time.
+///
+/// All lookups through a pool use the options and the reactor the pool was
+/// created with. Cloning a pool is cheap and results in a pool sharing the
+/// resolvers with the original.
+#[derive(Clone)]
+pub struct ResolverPool(Rc<PoolInner>);
+
+struct PoolInner {
+    resolvers: Vec<Resolver>,
+    conf: ResolvConf,
+
+    /// The index of the resolver to use for the next lookup.
+    next: Cell<usize>,
+
+    /// The number of lookups handed to each resolver.
+    lookups: Vec<Cell<u64>>,
+
+    options: ResolverOptions,
+    reactor: reactor::Handle,
+}
+
+impl ResolverPool {
+    /// Creates a pool of `size` resolvers.
+    ///
+    /// Returns an error if `size` is zero or the resolvers can’t be
+    /// created with the given options.
+    pub fn new(size: usize, options: &ResolverOptions,
+               reactor: &reactor::Handle) -> io::Result<Self> {
+        if size == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "empty resolver pool"))
+        }
+        let conf = dns::resolv_conf(options);
+        let mut resolvers = Vec::with_capacity(size);
+        for _ in 0..size {
+            resolvers.push(dns::resolver(options, &conf, reactor)?);
+        }
+        Ok(ResolverPool(Rc::new(PoolInner {
+            resolvers: resolvers,
+            conf: conf,
+            next: Cell::new(0),
+            lookups: (0..size).map(|_| Cell::new(0)).collect(),
+            options: options.clone(),
+            reactor: reactor.clone(),
+        })))
+    }
+
+    /// Returns the number of resolvers in the pool.
+    pub fn size(&self) -> usize {
+        self.0.resolvers.len()
+    }
+
+    /// Returns the number of lookups handed to each resolver so far.
+    pub fn lookups(&self) -> Vec<u64> {
+        self.0.lookups.iter().map(Cell::get).collect()
+    }
+
+    /// Returns the options used for lookups through the pool.
+    pub fn options(&self) -> &ResolverOptions {
+        &self.0.options
+    }
+
+    /// Returns the reactor the resolvers of the pool run on.
+    pub fn reactor(&self) -> &reactor::Handle {
+        &self.0.reactor
+    }
+
+    /// Returns the resolver configuration of the pool.
+    pub fn resolv_conf(&self) -> &ResolvConf {
+        &self.0.conf
+    }
+
+    /// Returns the resolver to use for the next lookup.
+    pub fn resolver(&self) -> Resolver {
+        let pos = self.0.next.get();
+        self.0.next.set((pos + 1) % self.0.resolvers.len());
+        let count = &self.0.lookups[pos];
+        count.set(count.get() + 1);
+        self.0.resolvers[pos].clone()
+    }
+
+    /// Starts a lookup for a host name using the pool.
+    ///
+    /// This is the pooled variant of `poll_host_by_name_with()` using the
+    /// options of the pool.
+    pub fn poll_host_by_name(&self, name: &str) -> HostByName {
+        HostByName::pooled(name, self)
+    }
+}
+
+
+//------------ SharedResolver ------------------------------------------------
+
+/// A single DNS resolver shared by many lookups.
+///
+/// Normally, each lookup creates a resolver of its own which opens its own
+/// sockets. Lookups made through a shared resolver all use the one
+/// resolver and thus its sockets and its state, such as which name server
+/// answered last.
+///
+/// This is a pool of exactly one resolver and, like a pool, uses the
+/// options and the reactor it was created with. Cloning it is cheap and
+/// results in a value sharing the resolver.
+#[derive(Clone)]
+pub struct SharedResolver(ResolverPool);
+
+impl SharedResolver {
+    /// Creates a new shared resolver.
+    pub fn new(options: &ResolverOptions, reactor: &reactor::Handle)
+               -> io::Result<Self> {
+        ResolverPool::new(1, options, reactor).map(SharedResolver)
+    }
+
+    /// Returns the number of lookups that used the resolver so far.
+    pub fn lookups(&self) -> u64 {
+        self.0.lookups()[0]
+    }
+
+    /// Returns the shared resolver as a pool.
+    pub fn as_pool(&self) -> &ResolverPool {
+        &self.0
+    }
+
+    /// Starts a lookup for a host name using the shared resolver.
+    pub fn poll_host_by_name(&self, name: &str) -> HostByName {
+        self.0.poll_host_by_name(name)
+    }
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::SocketAddr;
+    use futures::future;
+    use ::nsswitch::Conf;
+    use super::super::stub;
+
+    /// Returns options for DNS lookups through the given name server only.
+    fn stub_options(server: SocketAddr) -> ResolverOptions {
+        let conf = ResolvConf::parse(&mut io::Cursor::new(format!(
+            "nameserver {}\noptions timeout:1 attempts:1\n", server
+        ))).unwrap();
+        let nsswitch = Conf::parse(&mut io::Cursor::new(
+            "hosts: dns\n"
+        )).unwrap();
+        ResolverOptions::new().resolv_conf(conf).nsswitch_conf(nsswitch)
+    }
+
+    #[test]
+    fn round_robin() {
+        let core = reactor::Core::new().unwrap();
+        let pool = ResolverPool::new(3, &ResolverOptions::new(),
+                                     &core.handle()).unwrap();
+        for _ in 0..7 {
+            pool.resolver();
+        }
+        assert_eq!(pool.lookups(), [3, 2, 2]);
+        assert!(ResolverPool::new(0, &ResolverOptions::new(),
+                                  &core.handle()).is_err());
+    }
+
+    #[test]
+    fn concurrent_lookups() {
+        let (server, _) = stub::server(None);
+        let mut core = reactor::Core::new().unwrap();
+        let pool = ResolverPool::new(4, &stub_options(server),
+                                     &core.handle()).unwrap();
+        let lookups: Vec<_> = (0..20).map(|i| {
+            pool.poll_host_by_name(&format!("host{}.netdb.invalid", i))
+        }).collect();
+        let res = core.run(future::join_all(lookups)).unwrap();
+        assert_eq!(res.len(), 20);
+        assert!(res.iter().all(Option::is_none));
+        assert_eq!(pool.lookups(), [5, 5, 5, 5]);
+    }
+
+    #[test]
+    fn shared_resolver() {
+        let (server, peers) = stub::server(None);
+        let mut core = reactor::Core::new().unwrap();
+        let shared = SharedResolver::new(&stub_options(server),
+                                         &core.handle()).unwrap();
+        let lookups: Vec<_> = (0..3).map(|i| {
+            shared.poll_host_by_name(&format!("host{}.netdb.invalid", i))
+        }).collect();
+        let res = core.run(future::join_all(lookups)).unwrap();
+        assert!(res.iter().all(Option::is_none));
+        assert_eq!(shared.as_pool().size(), 1);
+        assert_eq!(shared.lookups(), 3);
+
+        // All queries were sent from the one socket of the resolver.
+        assert_eq!(peers.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,279 @@
+//! Retrying lookups that failed transiently.
+
+use std::{cmp, io};
+use std::time::Duration;
+use futures::{Async, Future, Poll};
+use tokio_core::reactor;
+use ::lookup::error_status;
+use ::nsswitch::Status;
+use super::{HostByName, HostEnt, LookupError, ResolverOptions};
+
+
+//------------ RetryPolicy ---------------------------------------------------
+
+/// How often and when to retry a lookup that failed transiently.
+///
+/// A lookup is attempted at most `max_attempts` times, counting the first
+/// attempt. Before the second attempt, the lookup waits for `base_delay`.
+/// The delay doubles for every further attempt but never exceeds
+/// `max_delay`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts including the first one.
+    pub max_attempts: usize,
+
+    /// The delay before the first retry.
+    pub base_delay: Duration,
+
+    /// The maximum delay between two attempts.
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Creates a new policy.
+    pub fn new(max_attempts: usize, base_delay: Duration,
+               max_delay: Duration) -> Self {
+        RetryPolicy {
+            max_attempts: max_attempts,
+            base_delay: base_delay,
+            max_delay: max_delay,
+        }
+    }
+
+    /// Returns the delay before the attempt following attempt `attempt`.
+    ///
+    /// Attempts are counted from one.
+    pub fn delay(&self, attempt: usize) -> Duration {
+        let shift = cmp::min(attempt.saturating_sub(1), 31) as u32;
+        let delay = self.base_delay.checked_mul(1 << shift)
+                                   .unwrap_or(self.max_delay);
+        cmp::min(delay, self.max_delay)
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Returns a policy of three attempts starting with a 100 ms delay.
+    ///
+    /// The delay is capped at two seconds.
+    fn default() -> Self {
+        Self::new(3, Duration::from_millis(100), Duration::from_secs(2))
+    }
+}
+
+
+//------------ HostByNameRetry -----------------------------------------------
+
+/// The future returned by `poll_host_by_name_retry()`.
+///
+/// Resolves into the result of the first attempt that didn’t fail
+/// transiently or into the error of the last attempt.
+pub struct HostByNameRetry {
+    name: String,
+    options: ResolverOptions,
+    policy: RetryPolicy,
+
+    /// The number of attempts started so far.
+    attempts: usize,
+
+    state: RetryState,
+    reactor: reactor::Handle,
+}
+
+enum RetryState {
+    /// An attempt is in progress.
+    Lookup(HostByName),
+
+    /// Waiting for the next attempt.
+    Delay(reactor::Timeout),
+}
+
+impl HostByNameRetry {
+    pub fn new(name: &str, reactor: &reactor::Handle, policy: RetryPolicy)
+               -> Self {
+        Self::with_options(name, &ResolverOptions::default(), reactor, policy)
+    }
+
+    /// Starts a lookup using the given options for every attempt.
+    pub fn with_options(name: &str, options: &ResolverOptions,
+                        reactor: &reactor::Handle, policy: RetryPolicy)
+                        -> Self {
+        HostByNameRetry {
+            name: name.into(),
+            options: options.clone(),
+            policy: policy,
+            attempts: 1,
+            state: RetryState::Lookup(HostByName::with_options(name, options,
+                                                               reactor)),
+            reactor: reactor.clone(),
+        }
+    }
+}
+
+impl Future for HostByNameRetry {
+    type Item = Option<HostEnt>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next = match self.state {
+                RetryState::Lookup(ref mut lookup) => {
+                    match lookup.poll() {
+                        Err(err) => {
+                            if self.attempts >= self.policy.max_attempts
+                                    || !is_transient(&err) {
+                                return Err(err)
+                            }
+                            RetryState::Delay(reactor::Timeout::new(
+                                self.policy.delay(self.attempts),
+                                &self.reactor
+                            )?)
+                        }
+                        res => return res
+                    }
+                }
+                RetryState::Delay(ref mut delay) => {
+                    try_ready!(delay.poll());
+                    self.attempts += 1;
+                    RetryState::Lookup(HostByName::with_options(
+                        &self.name, &self.options, &self.reactor
+                    ))
+                }
+            };
+            self.state = next;
+        }
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Returns whether a lookup failing with `err` may succeed later.
+///
+/// If all sources failed, the lookup may succeed later if any of them
+/// failed transiently.
+pub fn is_transient(err: &io::Error) -> bool {
+    if let Some(err) = err.get_ref() {
+        if let Some(&LookupError::AllSourcesFailed(ref errors))
+                = err.downcast_ref::<LookupError>() {
+            return errors.iter().any(|item| is_transient(&item.1))
+        }
+    }
+    error_status(err) == Status::TryAgain
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::IpAddr;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use futures::future;
+    use ::nsswitch::{Conf, Service};
+    use super::super::{NameSource, SourceRegistry};
+
+    #[test]
+    fn backoff() {
+        let policy = RetryPolicy::new(5, Duration::from_millis(100),
+                                      Duration::from_millis(500));
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        assert_eq!(policy.delay(4), Duration::from_millis(500));
+        assert_eq!(policy.delay(100), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn transient_errors() {
+        assert!(is_transient(&io::ErrorKind::TimedOut.into()));
+        assert!(!is_transient(&io::ErrorKind::InvalidInput.into()));
+        let err: io::Error = LookupError::AllSourcesFailed(vec![
+            (Service::Files, io::ErrorKind::PermissionDenied.into()),
+            (Service::Dns, io::ErrorKind::TimedOut.into()),
+        ]).into();
+        assert!(is_transient(&err));
+        let err: io::Error = LookupError::AllSourcesFailed(vec![
+            (Service::Files, io::ErrorKind::PermissionDenied.into()),
+        ]).into();
+        assert!(!is_transient(&err));
+    }
+
+    /// A source that times out a number of times before it answers.
+    struct FlakySource {
+        failures: usize,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl NameSource for FlakySource {
+        fn lookup_name(&self, name: &str, _reactor: &reactor::Handle)
+                       -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Box::new(future::err(
+                    io::Error::new(io::ErrorKind::TimedOut, "timed out")
+                ))
+            }
+            Box::new(future::ok(Some(HostEnt::new(
+                name.into(), Vec::new(), vec![IpAddr::from([192, 0, 2, 1])]
+            ))))
+        }
+
+        fn lookup_addr(&self, _addr: IpAddr, _reactor: &reactor::Handle)
+                       -> Box<Future<Item=Option<HostEnt>, Error=io::Error>> {
+            Box::new(future::ok(None))
+        }
+    }
+
+    /// Looks up a name via a `FlakySource` failing `failures` times.
+    ///
+    /// Returns the result and the number of attempts made.
+    fn flaky_lookup(failures: usize)
+                    -> (Result<Option<HostEnt>, io::Error>, usize) {
+        let conf = Conf::parse(&mut io::Cursor::new(
+            "hosts: flaky\n"
+        )).unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut sources = SourceRegistry::new();
+        sources.register("flaky", FlakySource {
+            failures: failures, calls: calls.clone()
+        });
+        let options = ResolverOptions::new().nsswitch_conf(conf)
+                                            .sources(sources);
+        let policy = RetryPolicy::new(3, Duration::from_millis(1),
+                                      Duration::from_millis(1));
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        let res = core.run(HostByNameRetry::with_options(
+            "flaky.netdb.invalid", &options, &handle, policy
+        ));
+        (res, calls.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn transient_failure_retried() {
+        let (res, attempts) = flaky_lookup(1);
+        assert_eq!(res.unwrap().unwrap().addrs(),
+                   &[IpAddr::from([192, 0, 2, 1])]);
+        assert_eq!(attempts, 2);
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let (res, attempts) = flaky_lookup(10);
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn invalid_name_not_retried() {
+        let mut core = reactor::Core::new().unwrap();
+        let handle = core.handle();
+        let policy = RetryPolicy::new(3, Duration::from_secs(10),
+                                      Duration::from_secs(10));
+        let err = core.run(HostByNameRetry::new(&"a.".repeat(150), &handle,
+                                                policy))
+                      .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
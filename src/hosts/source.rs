@@ -0,0 +1,74 @@
+//! Sources provided by the application.
+
+use std::{fmt, io};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use futures::Future;
+use tokio_core::reactor;
+use super::HostEnt;
+
+
+//------------ NameSource ----------------------------------------------------
+
+/// A source for the hosts database provided by the application.
+///
+/// Sources are registered with a `SourceRegistry` under the service name
+/// they are to be used for. If the name service configuration lists a
+/// service that isn’t known to this crate, i.e., a `Service::Other(_)`, a
+/// lookup consults the source registered under that name. Services without
+/// a registered source are skipped.
+///
+/// Both methods return a future resolving into the host entry or `None` if
+/// the source doesn’t know about the name or address. Sources create their
+/// entries via `HostEnt::new()`.
+pub trait NameSource {
+    /// Looks up the host entry for a host name.
+    fn lookup_name(&self, name: &str, reactor: &reactor::Handle)
+                   -> Box<Future<Item=Option<HostEnt>, Error=io::Error>>;
+
+    /// Looks up the host entry for an address.
+    fn lookup_addr(&self, addr: IpAddr, reactor: &reactor::Handle)
+                   -> Box<Future<Item=Option<HostEnt>, Error=io::Error>>;
+}
+
+
+//------------ SourceRegistry ------------------------------------------------
+
+/// The sources provided by the application, keyed by service name.
+///
+/// A registry is handed to lookups via `ResolverOptions::sources()`.
+/// Cloning a registry is cheap since the sources themselves are shared.
+///
+/// Like service names in the name service configuration, the names of
+/// sources are case-insensitive. They are converted to lowercase ASCII.
+#[derive(Clone, Default)]
+pub struct SourceRegistry {
+    sources: HashMap<String, Arc<NameSource + Send + Sync>>,
+}
+
+impl SourceRegistry {
+    /// Creates a new, empty registry.
+    pub fn new() -> Self {
+        SourceRegistry { sources: HashMap::new() }
+    }
+
+    /// Registers a source for the service `name`.
+    ///
+    /// Any source previously registered under that name is replaced.
+    pub fn register<S>(&mut self, name: &str, source: S)
+                    where S: NameSource + Send + Sync + 'static {
+        self.sources.insert(name.to_ascii_lowercase(), Arc::new(source));
+    }
+
+    /// Returns the source for the service `name` if there is one.
+    pub fn get(&self, name: &str) -> Option<&(NameSource + Send + Sync)> {
+        self.sources.get(&name.to_ascii_lowercase()).map(AsRef::as_ref)
+    }
+}
+
+impl fmt::Debug for SourceRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.sources.keys()).finish()
+    }
+}
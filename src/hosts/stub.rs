@@ -0,0 +1,71 @@
+//! A stub name server for testing.
+
+use std::{thread, time};
+use std::collections::HashSet;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+
+
+//------------ server --------------------------------------------------------
+
+/// Starts a name server on the loopback address.
+///
+/// The server answers queries for A records with `addr` if given. All
+/// other queries are answered with NXDOMAIN.
+///
+/// Returns the server’s address and the set of addresses it received
+/// queries from.
+pub fn server(addr: Option<Ipv4Addr>)
+              -> (SocketAddr, Arc<Mutex<HashSet<SocketAddr>>>) {
+    let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    sock.set_read_timeout(Some(time::Duration::from_secs(5))).unwrap();
+    let local = sock.local_addr().unwrap();
+    let peers = Arc::new(Mutex::new(HashSet::new()));
+    let res = peers.clone();
+    thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        while let Ok((len, peer)) = sock.recv_from(&mut buf) {
+            peers.lock().unwrap().insert(peer);
+            if let Some(response) = respond(&buf[..len], addr) {
+                let _ = sock.send_to(&response, peer);
+            }
+        }
+    });
+    (local, res)
+}
+
+/// Creates the response to `query`.
+///
+/// Returns `None` if the query is malformed.
+fn respond(query: &[u8], addr: Option<Ipv4Addr>) -> Option<Vec<u8>> {
+    if query.len() < 12 {
+        return None
+    }
+    let mut pos = 12;
+    while pos < query.len() && query[pos] != 0 {
+        pos += usize::from(query[pos]) + 1;
+    }
+    let end = pos + 5;
+    if end > query.len() {
+        return None
+    }
+    let qtype = u16::from(query[end - 4]) << 8 | u16::from(query[end - 3]);
+
+    // Keep header and question, drop everything else.
+    let mut res = query[..end].to_vec();
+    res[2] = 0x80 | (query[2] & 0x01);
+    res[3] = 0x80;
+    for octet in &mut res[6..12] {
+        *octet = 0
+    }
+    match addr {
+        Some(addr) if qtype == 1 => {
+            res[7] = 1;
+            res.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0x0e, 0x10,
+                                    0, 4]);
+            res.extend_from_slice(&addr.octets());
+        }
+        _ => res[3] |= 3,
+    }
+    Some(res)
+}
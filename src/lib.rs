@@ -15,9 +15,41 @@
 //!
 //! For each database, there is a submodule. Have a look at these modules
 //! for more information.
+//!
+//! If the `tracing` feature is enabled, lookups are instrumented with spans
+//! and events for the [tracing] ecosystem.
+//!
+//! If the `hickory` feature is enabled, the resolver of the [hickory-dns]
+//! project can be used for DNS lookups of the hosts database instead of
+//! the resolver of the `domain` crate.
+//!
+//! Loading the system configuration reads files and thus blocks. The
+//! configuration types therefore have a `poll_system()` function next to
+//! their `system()` function that reads the files on a thread of its own.
+//!
+//! [tracing]: https://crates.io/crates/tracing
+//! [hickory-dns]: https://github.com/hickory-dns/hickory-dns
 //! 
 extern crate domain;
-extern crate futures;
+#[macro_use] extern crate futures;
 extern crate tokio_core;
+#[cfg(feature = "ffi")] extern crate libc;
+#[cfg(feature = "mmap")] extern crate memmap;
+#[cfg(feature = "tracing")] #[macro_use] extern crate tracing;
+#[cfg(feature = "hickory")] extern crate trust_dns_resolver;
+
+pub use blocking::Blocking;
 
+pub mod addr;
+pub mod dns;
+pub mod gaiconf;
 pub mod hosts;
+pub mod networks;
+pub mod nsswitch;
+pub mod resolv;
+pub mod rpc;
+pub mod services;
+
+mod blocking;
+mod lookup;
+mod trace;
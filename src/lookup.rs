@@ -0,0 +1,469 @@
+//! The engine walking the sources configured for a database.
+//!
+//! Each database of the name service switch is looked up the same way: the
+//! rules configured for it are walked in order, consulting each source
+//! until one of them finds an entry. This module implements this walk once
+//! for all databases. A database only needs to implement `Lookupable`
+//! which tells the engine how to start a lookup for a given source.
+
+use std::{io, mem};
+use futures::{Async, Future, Poll};
+use ::hosts::LookupError;
+use ::nsswitch::{Action, Conf, Database, Rule, Service, Status};
+use ::trace::Span;
+
+
+//------------ Lookupable ----------------------------------------------------
+
+/// The lookup of a single source.
+pub type SourceLookup<T> = Box<Future<Item=Option<T>, Error=io::Error>>;
+
+/// A database that can be looked up via the name service switch.
+///
+/// A value of a type implementing this trait carries everything its
+/// sources need to start a lookup, such as options and the reactor.
+pub trait Lookupable {
+    /// The type of the keys looked up.
+    type Key;
+
+    /// The type of the entries resolved into.
+    type Item;
+
+    /// Returns the database whose rules are to be used.
+    fn database() -> Database;
+
+    /// Returns the rules if the configuration has none for the database.
+    fn default_rules() -> Vec<Rule>;
+
+    /// Starts the lookup of `key` with `service`.
+    ///
+    /// Returns `None` if the service can’t be used for this database.
+    fn start(&self, service: &Service, key: &Self::Key)
+             -> Option<SourceLookup<Self::Item>>;
+
+    /// Merges the entry `other` found by a later source into `found`.
+    ///
+    /// This is used for the merge action. By default, the entry found
+    /// first is kept and `other` dropped.
+    fn merge(&self, found: &mut Self::Item, other: Self::Item) {
+        let _ = (found, other);
+    }
+}
+
+
+//------------ run_lookup ----------------------------------------------------
+
+/// Returns the rules for database `D` given by `conf`.
+pub fn rules<D: Lookupable>(conf: &Conf) -> Vec<Rule> {
+    match conf.database(&D::database()) {
+        Some(rules) => rules.into(),
+        None => D::default_rules(),
+    }
+}
+
+/// Looks up `key` in `db` using the rules given by `conf`.
+pub fn run_lookup<D: Lookupable>(conf: &Conf, db: D, key: D::Key,
+                                 span: Span) -> Lookup<D> {
+    Lookup::new(rules::<D>(conf), db, key, span)
+}
+
+
+//------------ Lookup --------------------------------------------------------
+
+/// A lookup walking the sources listed in the configuration.
+///
+/// The sources are consulted in order. After each source, the action rules
+/// following it decide whether to return or to continue with the next
+/// source based on the status of the source. Without a matching action
+/// rule, the lookup returns if the source found an entry and continues
+/// otherwise, just like glibc does.
+///
+/// Sources that fail are skipped, their errors are collected. If no source
+/// finds an entry, the outcome of the last source consulted decides, just
+/// like glibc’s last status does: if it reported that there is no entry,
+/// the lookup resolves into `None`, if it failed, into its error. Only if
+/// every source consulted failed, the lookup resolves into a `LookupError`
+/// with all their errors instead. An entry found by a source whose
+/// `[SUCCESS=continue]` rule made the lookup go on is returned if no later
+/// source finds one.
+///
+/// With a `[SUCCESS=merge]` action, the lookup goes on after a source found
+/// an entry and merges the entry of the next source into it. This goes on
+/// for as long as sources are followed by a merge action. As with glibc,
+/// the entries merged so far are returned as soon as a source following a
+/// merge action doesn’t find an entry. A merge action for any status other
+/// than success is treated like the continue action.
+pub struct Lookup<D: Lookupable> {
+    db: D,
+    key: D::Key,
+    rules: Vec<Rule>,
+
+    /// The index of the next rule to look at.
+    pos: usize,
+
+    /// The source currently consulted.
+    current: Option<(Service, SourceLookup<D::Item>)>,
+
+    /// Whether the last source consulted failed.
+    last_failed: bool,
+
+    /// Whether any source consulted reported that there is no entry.
+    not_found: bool,
+
+    /// The entry found by the latest successful source.
+    ///
+    /// If the source before it was followed by a merge action, this is
+    /// the merged entry.
+    found: Option<D::Item>,
+
+    /// Whether the entry of the next source is to be merged into `found`.
+    merging: bool,
+
+    errors: Vec<(Service, io::Error)>,
+    span: Span,
+}
+
+impl<D: Lookupable> Lookup<D> {
+    /// Creates a new lookup using `rules` and starts its first source.
+    pub fn new(rules: Vec<Rule>, db: D, key: D::Key, span: Span) -> Self {
+        let mut res = Lookup {
+            db: db,
+            key: key,
+            rules: rules,
+            pos: 0,
+            current: None,
+            last_failed: false,
+            not_found: false,
+            found: None,
+            merging: false,
+            errors: Vec::new(),
+            span: span,
+        };
+        res.advance();
+        res
+    }
+
+    /// Starts the next source.
+    ///
+    /// Leaves `self.current` at `None` if there are no more sources.
+    fn advance(&mut self) {
+        self.current = None;
+        while self.pos < self.rules.len() {
+            self.pos += 1;
+            let service = match self.rules[self.pos - 1] {
+                Rule::Service(ref service) => service.clone(),
+                Rule::Action(..) => continue,
+            };
+            if let Some(lookup) = self.db.start(&service, &self.key) {
+                self.span.source(&service);
+                self.current = Some((service, lookup));
+                return
+            }
+        }
+    }
+
+    /// Returns the action for `status` of the source just consulted.
+    ///
+    /// These are the action rules following the source up to the next
+    /// source.
+    fn action(&self, status: Status) -> Action {
+        for rule in &self.rules[self.pos..] {
+            match *rule {
+                Rule::Action(rule_status, action) if rule_status == status => {
+                    return action
+                }
+                Rule::Action(..) => { }
+                Rule::Service(_) => break,
+            }
+        }
+        match status {
+            Status::Success => Action::Return,
+            _ => Action::Continue,
+        }
+    }
+
+    /// Produces the result once the lookup is done.
+    fn finish(&mut self) -> Result<Option<D::Item>, io::Error> {
+        if let Some(found) = self.found.take() {
+            return Ok(Some(found))
+        }
+        if !self.last_failed {
+            return Ok(None)
+        }
+        match self.errors.pop() {
+            Some((_, err)) if self.not_found => Err(err),
+            Some((service, err)) => {
+                Err(all_failed(&mut self.errors, service, err))
+            }
+            None => Ok(None)
+        }
+    }
+}
+
+impl<D: Lookupable> Future for Lookup<D> {
+    type Item = Option<D::Item>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let status = match self.current {
+                Some((ref service, ref mut lookup)) => {
+                    match lookup.poll() {
+                        Ok(Async::Ready(Some(ent))) => {
+                            match self.found {
+                                Some(ref mut found) if self.merging => {
+                                    self.db.merge(found, ent)
+                                }
+                                _ => self.found = Some(ent),
+                            }
+                            self.last_failed = false;
+                            Status::Success
+                        }
+                        Ok(Async::Ready(None)) => {
+                            self.last_failed = false;
+                            self.not_found = true;
+                            Status::NotFound
+                        }
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Err(err) => {
+                            let status = error_status(&err);
+                            self.errors.push((service.clone(), err));
+                            self.last_failed = true;
+                            status
+                        }
+                    }
+                }
+                None => return self.finish().map(Async::Ready)
+            };
+            let merging = mem::replace(&mut self.merging, false);
+            match self.action(status) {
+                Action::Return => {
+                    self.current = None;
+                    return self.finish().map(Async::Ready)
+                }
+                Action::Merge if status == Status::Success => {
+                    self.merging = true
+                }
+                _ if merging && status != Status::Success => {
+                    self.current = None;
+                    return self.finish().map(Async::Ready)
+                }
+                _ => { }
+            }
+            self.advance();
+        }
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Returns the status of a source that failed with `err`.
+///
+/// Errors that may go away when trying again later result in
+/// `Status::TryAgain`, all others in `Status::Unavail`.
+pub fn error_status(err: &io::Error) -> Status {
+    match err.kind() {
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut => Status::TryAgain,
+        _ => Status::Unavail,
+    }
+}
+
+/// Returns the error if all sources consulted failed.
+///
+/// The errors of all earlier sources are in `errors`, `service` is the last
+/// source and `err` its error. If there were earlier sources, the combined
+/// error is returned. Otherwise `err` is returned as is.
+fn all_failed(errors: &mut Vec<(Service, io::Error)>, service: Service,
+                  err: io::Error) -> io::Error {
+    if errors.is_empty() {
+        return err
+    }
+    let mut errors = mem::replace(errors, Vec::new());
+    errors.push((service, err));
+    LookupError::AllSourcesFailed(errors).into()
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::future;
+
+    /// A database with three sources.
+    ///
+    /// The files source knows numbers below ten, the DNS source knows all
+    /// numbers, and the source `broken` always fails. Merging adds up the
+    /// numbers found.
+    struct Numbers;
+
+    impl Lookupable for Numbers {
+        type Key = u32;
+        type Item = u32;
+
+        fn database() -> Database {
+            Database::Other("numbers".into())
+        }
+
+        fn default_rules() -> Vec<Rule> {
+            vec![Rule::Service(Service::Files)]
+        }
+
+        fn start(&self, service: &Service, key: &u32)
+                 -> Option<SourceLookup<u32>> {
+            match *service {
+                Service::Files => {
+                    Some(Box::new(future::ok(if *key < 10 { Some(*key) }
+                                             else { None })))
+                }
+                Service::Dns => Some(Box::new(future::ok(Some(*key)))),
+                Service::Other(ref name) if name == "broken" => {
+                    Some(Box::new(future::err(
+                        io::Error::new(io::ErrorKind::Other, "broken")
+                    )))
+                }
+                _ => None
+            }
+        }
+
+        fn merge(&self, found: &mut u32, other: u32) {
+            *found += other
+        }
+    }
+
+    fn lookup(conf: &str, key: u32) -> Result<Option<u32>, io::Error> {
+        let conf = Conf::parse(&mut conf.as_bytes()).unwrap();
+        run_lookup(&conf, Numbers, key, Span::resolve(key, "test")).wait()
+    }
+
+    #[test]
+    fn generic_lookup() {
+        assert_eq!(lookup("", 3).unwrap(), Some(3));
+        assert_eq!(lookup("", 12).unwrap(), None);
+        assert_eq!(lookup("numbers: files dns", 12).unwrap(), Some(12));
+        assert_eq!(lookup("numbers: broken files", 3).unwrap(), Some(3));
+        assert_eq!(lookup("numbers: broken files", 12).unwrap(), None);
+        assert_eq!(lookup("numbers: files broken", 12).unwrap_err().kind(),
+                   io::ErrorKind::Other);
+    }
+
+    #[test]
+    fn actions() {
+        assert_eq!(lookup("numbers: files [NOTFOUND=return] dns", 12)
+                       .unwrap(),
+                   None);
+        assert_eq!(lookup("numbers: files [NOTFOUND=return] dns", 3)
+                       .unwrap(),
+                   Some(3));
+        assert!(lookup("numbers: broken [UNAVAIL=return] files", 3)
+                    .is_err());
+        assert_eq!(lookup("numbers: files [SUCCESS=continue] broken", 3)
+                       .unwrap(),
+                   Some(3));
+    }
+
+    #[test]
+    fn merge() {
+        assert_eq!(lookup("numbers: files dns", 3).unwrap(), Some(3));
+        assert_eq!(lookup("numbers: files [SUCCESS=merge] dns", 3).unwrap(),
+                   Some(6));
+        assert_eq!(lookup("numbers: files [SUCCESS=merge] dns", 12)
+                       .unwrap(),
+                   Some(12));
+        assert_eq!(lookup("numbers: files [SUCCESS=merge] dns \
+                           [SUCCESS=merge] dns", 3).unwrap(),
+                   Some(9));
+        assert_eq!(lookup("numbers: files [SUCCESS=merge] broken dns", 3)
+                       .unwrap(),
+                   Some(3));
+        assert_eq!(lookup("numbers: files [NOTFOUND=merge] dns", 12)
+                       .unwrap(),
+                   Some(12));
+    }
+
+    /// A database whose files and DNS sources fail.
+    ///
+    /// The files source fails with a permission error, the DNS source
+    /// times out, and the source `nis` never finds an entry.
+    struct Failing;
+
+    impl Lookupable for Failing {
+        type Key = u32;
+        type Item = u32;
+
+        fn database() -> Database {
+            Database::Other("failing".into())
+        }
+
+        fn default_rules() -> Vec<Rule> {
+            vec![Rule::Service(Service::Files), Rule::Service(Service::Dns)]
+        }
+
+        fn start(&self, service: &Service, _key: &u32)
+                 -> Option<SourceLookup<u32>> {
+            match *service {
+                Service::Files => {
+                    Some(Box::new(future::err(io::Error::new(
+                        io::ErrorKind::PermissionDenied, "permission denied"
+                    ))))
+                }
+                Service::Dns => {
+                    Some(Box::new(future::err(io::Error::new(
+                        io::ErrorKind::TimedOut, "timed out"
+                    ))))
+                }
+                Service::Other(ref name) if name == "nis" => {
+                    Some(Box::new(future::ok(None)))
+                }
+                _ => None
+            }
+        }
+    }
+
+    fn failing(conf: &str) -> Result<Option<u32>, io::Error> {
+        let conf = Conf::parse(&mut conf.as_bytes()).unwrap();
+        run_lookup(&conf, Failing, 1, Span::resolve(1, "test")).wait()
+    }
+
+    #[test]
+    fn aggregate_errors() {
+        let err = failing("failing: dns").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let err = failing("failing: files dns").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert_eq!(format!("{}", err),
+                   "all sources failed: files: permission denied; \
+                    dns: timed out");
+        match err.get_ref().unwrap().downcast_ref::<LookupError>() {
+            Some(&LookupError::AllSourcesFailed(ref errors)) => {
+                assert_eq!(errors.len(), 2);
+                assert_eq!(errors[0].0, Service::Files);
+                assert_eq!(errors[0].1.kind(),
+                           io::ErrorKind::PermissionDenied);
+                assert_eq!(errors[1].0, Service::Dns);
+                assert_eq!(errors[1].1.kind(), io::ErrorKind::TimedOut);
+            }
+            None => panic!("not a lookup error")
+        }
+
+        // A source in between that finds nothing means not all sources
+        // failed, so the last error is returned as is.
+        let err = failing("failing: files nis dns").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        // If the last source finds nothing, that is the outcome.
+        assert_eq!(failing("failing: files nis").unwrap(), None);
+    }
+
+    #[test]
+    fn status_of_errors() {
+        assert_eq!(error_status(&io::ErrorKind::Interrupted.into()),
+                   Status::TryAgain);
+        assert_eq!(error_status(&io::ErrorKind::PermissionDenied.into()),
+                   Status::Unavail);
+    }
+}
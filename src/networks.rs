@@ -0,0 +1,348 @@
+//! The networks database.
+//!
+//! This database maps the names of IPv4 networks to network numbers.
+//! Currently, the database is only read from the `/etc/networks` file.
+
+use std::{error, fmt, fs, io, result};
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::str::FromStr;
+
+
+//============ High-level API ================================================
+
+/// Returns network information for a given network name.
+///
+/// The name can be the network’s official name or one of its aliases.
+/// Returns `Ok(None)` if there is no such network.
+pub fn get_network_by_name(name: &str) -> Result<Option<NetEnt>, io::Error> {
+    Ok(NetworksDb::system()?.lookup_name(name).cloned())
+}
+
+/// Returns the network a given address belongs to.
+///
+/// Of all networks of the system’s table containing `addr`, the one with
+/// the longest prefix is returned. See `NetworksDb` for how the prefix of
+/// a network is determined.
+///
+/// Returns `Ok(None)` if no network contains the address.
+pub fn get_network_by_addr_in(addr: Ipv4Addr)
+                              -> Result<Option<NetEnt>, io::Error> {
+    Ok(NetworksDb::system()?.lookup_addr_in(addr).cloned())
+}
+
+
+//------------ NetEnt --------------------------------------------------------
+
+/// The result of a network lookup.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NetEnt {
+    name: String,
+    aliases: Vec<String>,
+    net: Ipv4Addr,
+
+    /// The number of octets given for the network number.
+    octets: usize,
+}
+
+impl NetEnt {
+    /// The official name of the network.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The aliases of the network.
+    pub fn aliases(&self) -> &[String] {
+        self.aliases.as_ref()
+    }
+
+    /// The network number.
+    ///
+    /// Octets left out in the abbreviated form are zero, i.e., the number
+    /// of the network given as `10.1` is `10.1.0.0`.
+    pub fn net(&self) -> Ipv4Addr {
+        self.net
+    }
+
+    /// Returns the prefix length of the network.
+    ///
+    /// If the network number was given in abbreviated form, the prefix
+    /// covers the octets given. Otherwise, it is `default` if given or the
+    /// length of the network’s class.
+    pub fn prefix_len(&self, default: Option<u8>) -> u8 {
+        if self.octets < 4 {
+            return (self.octets * 8) as u8
+        }
+        match default {
+            Some(len) => len,
+            None => natural_prefix_len(self.net)
+        }
+    }
+
+    /// Returns whether `addr` is in the network given its prefix length.
+    fn contains(&self, addr: Ipv4Addr, prefix_len: u8) -> bool {
+        if prefix_len == 0 {
+            return true
+        }
+        let mask = !0u32 << (32 - u32::from(prefix_len.min(32)));
+        u32::from(addr) & mask == u32::from(self.net) & mask
+    }
+}
+
+
+//------------ NetworksDb ----------------------------------------------------
+
+/// A networks table.
+///
+/// You can create an empty table using `NetworksDb::new()`, create one by
+/// parsing a networks file with `NetworksDb::parse()` or
+/// `NetworksDb::parse_file()`, or load the system’s table with
+/// `NetworksDb::system()`.
+///
+/// Network numbers are usually given in abbreviated form leaving out
+/// trailing zero octets, such as `10` for the network `10.0.0.0/8` or
+/// `172.16` for `172.16.0.0/16`. For such numbers, the prefix is inferred
+/// from the number of octets. For numbers given with all four octets, the
+/// prefix length set via `set_default_prefix_len()` is used or, if there
+/// is none, the length of the network’s class.
+#[derive(Clone, Debug, Default)]
+pub struct NetworksDb {
+    entries: Vec<NetEnt>,
+
+    /// The prefix length of networks given with all four octets.
+    default_prefix_len: Option<u8>,
+}
+
+
+/// # Creation and Manipulation
+///
+impl NetworksDb {
+    /// Creates a new, empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the networks table of this system.
+    ///
+    /// A missing networks file results in an empty table.
+    ///
+    /// XXX This currently only works for Unix-y systems.
+    pub fn system() -> io::Result<Self> {
+        let mut res = NetworksDb::new();
+        match res.parse_file("/etc/networks") {
+            Ok(()) => Ok(res),
+            Err(Error::IoError(ref err))
+                if err.kind() == io::ErrorKind::NotFound => Ok(res),
+            Err(Error::IoError(err)) => Err(err),
+            Err(Error::ParseError) => Ok(res),
+        }
+    }
+
+    /// Adds an entry.
+    pub fn add(&mut self, ent: NetEnt) {
+        self.entries.push(ent)
+    }
+
+    /// Sets the prefix length of networks given with all four octets.
+    ///
+    /// If `None`, the default, the length of the network’s class is used.
+    pub fn set_default_prefix_len(&mut self, len: Option<u8>) {
+        self.default_prefix_len = len
+    }
+}
+
+
+/// # Lookups
+///
+impl NetworksDb {
+    /// Looks up a network by name or alias.
+    pub fn lookup_name(&self, name: &str) -> Option<&NetEnt> {
+        self.entries.iter().find(|ent| {
+            ent.name == name || ent.aliases.iter().any(|alias| alias == name)
+        })
+    }
+
+    /// Looks up the most specific network containing `addr`.
+    ///
+    /// If several networks with the same prefix length contain the
+    /// address, the first one is returned.
+    pub fn lookup_addr_in(&self, addr: Ipv4Addr) -> Option<&NetEnt> {
+        let mut res: Option<(&NetEnt, u8)> = None;
+        for ent in &self.entries {
+            let len = ent.prefix_len(self.default_prefix_len);
+            if !ent.contains(addr, len) {
+                continue
+            }
+            match res {
+                Some((_, found)) if found >= len => { }
+                _ => res = Some((ent, len))
+            }
+        }
+        res.map(|(ent, _)| ent)
+    }
+}
+
+
+/// # Parsing Networks File
+///
+impl NetworksDb {
+    /// Adds the networks listed in a file.
+    pub fn parse_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.parse(&mut fs::File::open(path)?)
+    }
+
+    /// Reads networks from a reader and adds them.
+    ///
+    /// The format is that of the /etc/networks file. Lines that fail to
+    /// parse are skipped.
+    pub fn parse<R: io::Read>(&mut self, reader: &mut R) -> Result<()> {
+        use std::io::BufRead;
+
+        for line in io::BufReader::new(reader).lines() {
+            if let Ok(Some(ent)) = parse_line(&line?) {
+                self.add(ent)
+            }
+        }
+        Ok(())
+    }
+}
+
+
+//------------ parse_line ---------------------------------------------------
+
+/// Parses a single line of a networks file.
+///
+/// Returns `Ok(None)` if the line is empty or contains only a comment.
+/// Otherwise, the line consists of the network name, the network number
+/// in dotted notation with up to four octets, and optional aliases. A
+/// trailing carriage return and a UTF-8 byte order mark are ignored.
+pub fn parse_line(line: &str) -> Result<Option<NetEnt>> {
+    let line: &str = match line.find('#') {
+        Some(pos) => line.split_at(pos).0,
+        None => line
+    };
+    let line = line.trim_matches(|ch: char| {
+        ch.is_whitespace() || ch == '\u{feff}'
+    });
+    if line.is_empty() { return Ok(None) }
+    let mut words = line.split_whitespace();
+
+    let name = words.next().ok_or(Error::ParseError)?;
+    let number = words.next().ok_or(Error::ParseError)?;
+    let mut octets = [0u8; 4];
+    let mut count = 0;
+    for part in number.split('.') {
+        if count == 4 {
+            return Err(Error::ParseError)
+        }
+        octets[count] = u8::from_str(part).map_err(|_| Error::ParseError)?;
+        count += 1;
+    }
+
+    Ok(Some(NetEnt {
+        name: name.into(),
+        aliases: words.map(Into::into).collect(),
+        net: Ipv4Addr::from(octets),
+        octets: count,
+    }))
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Returns the prefix length of the class of `addr`.
+///
+/// This is 8 for class A, 16 for class B, and 24 for all other addresses.
+pub(crate) fn natural_prefix_len(addr: Ipv4Addr) -> u8 {
+    match addr.octets()[0] {
+        0..=127 => 8,
+        128..=191 => 16,
+        _ => 24,
+    }
+}
+
+
+//------------ Error and Result ----------------------------------------------
+
+/// An error happend during parsing a networks file.
+#[derive(Debug)]
+pub enum Error {
+    /// The networks file is kaputt.
+    ParseError,
+
+    /// Reading failed.
+    IoError(io::Error),
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::ParseError => "error parsing networks",
+            Error::IoError(ref e) => e.description(),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::IoError(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use std::error::Error;
+
+        self.description().fmt(f)
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn parse_line_forms() {
+        let ent = parse_line("loopback 127 # local\n").unwrap().unwrap();
+        assert_eq!(ent.name(), "loopback");
+        assert_eq!(ent.net(), Ipv4Addr::new(127, 0, 0, 0));
+        assert_eq!(ent.prefix_len(None), 8);
+        let ent = parse_line("link-local 169.254.0.0 ll").unwrap().unwrap();
+        assert_eq!(ent.aliases(), ["ll"]);
+        assert_eq!(ent.prefix_len(None), 16);
+        assert_eq!(ent.prefix_len(Some(20)), 20);
+        assert!(parse_line("# comment").unwrap().is_none());
+        assert!(parse_line("broken 10.1.2.3.4").is_err());
+        assert!(parse_line("broken 10.256").is_err());
+        assert!(parse_line("broken").is_err());
+    }
+
+    #[test]
+    fn most_specific() {
+        let mut db = NetworksDb::new();
+        db.parse(&mut Cursor::new(
+            "ten\t\t10\n\
+             ten-one\t\t10.1\n\
+             other\t\t192.168.1.0\n"
+        )).unwrap();
+        let lookup = |db: &NetworksDb, addr: &str| {
+            db.lookup_addr_in(Ipv4Addr::from_str(addr).unwrap())
+              .map(|ent| ent.name().to_owned())
+        };
+        assert_eq!(lookup(&db, "10.1.2.3"), Some("ten-one".into()));
+        assert_eq!(lookup(&db, "10.2.0.1"), Some("ten".into()));
+        assert_eq!(lookup(&db, "192.168.1.7"), Some("other".into()));
+        assert_eq!(lookup(&db, "192.168.2.7"), None);
+
+        db.set_default_prefix_len(Some(16));
+        assert_eq!(lookup(&db, "192.168.2.7"), Some("other".into()));
+        assert_eq!(db.lookup_name("ten-one").unwrap().net(),
+                   Ipv4Addr::new(10, 1, 0, 0));
+    }
+}
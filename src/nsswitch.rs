@@ -1,12 +1,36 @@
 //! Parsing of nsswitch.conf.
 //!
 //! Parsing herein follows the `nsswitch.conf` file used by glibc 2.
+//!
+//! As with glibc, only `#` starts a comment. Unlike in `resolv.conf` and
+//! the hosts file, a `;` is not special.
+//!
+//! Long-running programs can pick up changes to the configuration via the
+//! stream returned by `watch_config()`.
 
-use std::{error, fmt, fs, io};
-use std::ascii::AsciiExt;
+use std::{env, error, fmt, fs, io};
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::path::Path;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use futures::{Async, Poll, Stream};
+use tokio_core::reactor;
+use ::blocking::Blocking;
+
+
+//============ High-level API ================================================
+
+/// Returns a stream of the system’s configuration whenever it changes.
+///
+/// The system’s `nsswitch.conf`, see `Conf::system()` for its path, and
+/// `/etc/resolv.conf` are checked for changes every five seconds. See
+/// `ConfWatch` for details.
+pub fn watch_config(reactor: &reactor::Handle) -> io::Result<ConfWatch> {
+    ConfWatch::new(vec![Conf::system_path(), "/etc/resolv.conf".into()],
+                   Duration::from_secs(5), reactor)
+}
 
 
 //------------ Conf ----------------------------------------------------------
@@ -31,6 +55,140 @@ impl Conf {
 }
 
 
+/// # System Configuration
+///
+impl Conf {
+    /// Returns the name service configuration of this system.
+    ///
+    /// If the configuration file cannot be read, returns an empty
+    /// configuration. The file is read from the path given in the
+    /// environment variable `NETDB_NSSWITCH_CONF` if it is set and not
+    /// empty or from `/etc/nsswitch.conf` otherwise.
+    ///
+    /// XXX This currently only works for Unix-y systems.
+    pub fn system() -> Self {
+        Self::parse_file(Self::system_path()).unwrap_or_default()
+    }
+
+    /// Returns a future resolving into the configuration of this system.
+    ///
+    /// This is identical to `system()` except that the file is read on a
+    /// thread of its own so that a reactor isn’t blocked during startup.
+    pub fn poll_system() -> Blocking<Self> {
+        Self::poll_file(Self::system_path())
+    }
+
+    /// Returns a future resolving into the configuration in a file.
+    ///
+    /// The file at `path` is read on a thread of its own. Like with
+    /// `system()`, the future resolves into an empty configuration if the
+    /// file cannot be read.
+    pub fn poll_file<P: AsRef<Path>>(path: P) -> Blocking<Self> {
+        let path = path.as_ref().to_path_buf();
+        Blocking::spawn(move || Ok(Self::parse_file(path).unwrap_or_default()))
+    }
+
+    /// Returns the system’s configuration, reading the file only if needed.
+    ///
+    /// This is like `system()` but keeps the configuration last read on
+    /// the current thread. The file is only parsed again if its
+    /// modification time or size has changed, so callers creating default
+    /// options for every lookup don’t read the file each time.
+    pub(crate) fn system_cached() -> Self {
+        Self::cached(&Self::system_path())
+    }
+
+    /// Returns the configuration at `path`, reading the file only if needed.
+    fn cached(path: &Path) -> Self {
+        thread_local!(
+            static CACHE: RefCell<Option<CachedConf>> = RefCell::new(None)
+        );
+        let new_stamp = stamp(path);
+        CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            if let Some((ref cached_path, ref old_stamp, ref conf)) = *cache {
+                if cached_path == path && *old_stamp == new_stamp {
+                    return conf.clone()
+                }
+            }
+            let conf = Self::parse_file(path).unwrap_or_default();
+            *cache = Some((path.into(), new_stamp, conf.clone()));
+            conf
+        })
+    }
+
+    /// Returns the path of the system’s configuration file.
+    fn system_path() -> PathBuf {
+        Self::system_path_with(env::var_os("NETDB_NSSWITCH_CONF"))
+    }
+
+    /// Returns the path given the value of `NETDB_NSSWITCH_CONF`.
+    fn system_path_with(path: Option<OsString>) -> PathBuf {
+        if let Some(path) = path {
+            if !path.is_empty() {
+                return path.into()
+            }
+        }
+        "/etc/nsswitch.conf".into()
+    }
+}
+
+/// # Validation
+///
+impl Conf {
+    /// Checks the rules of all databases for structural problems.
+    ///
+    /// Returns a warning for each action that cannot have any effect: an
+    /// action before the first service of a database has no status to act
+    /// upon and an action after the last service has no source left to
+    /// continue with. These are almost certainly misconfigurations but
+    /// don’t prevent the configuration from being used.
+    ///
+    /// The warnings are ordered by database name and position.
+    pub fn validate(&self) -> Vec<Warning> {
+        let mut res = Vec::new();
+        for (db, rules) in &self.databases {
+            let first = rules.iter().position(|rule| {
+                if let Rule::Service(_) = *rule { true } else { false }
+            });
+            let last = rules.iter().rposition(|rule| {
+                if let Rule::Service(_) = *rule { true } else { false }
+            });
+            for (pos, rule) in rules.iter().enumerate() {
+                if let Rule::Action(..) = *rule {
+                    let kind = match (first, last) {
+                        (Some(first), _) if pos < first => {
+                            WarningKind::ActionFirst
+                        }
+                        (Some(_), Some(last)) if pos > last => {
+                            WarningKind::DanglingAction
+                        }
+                        (None, _) => WarningKind::ActionFirst,
+                        _ => continue,
+                    };
+                    res.push(Warning {
+                        database: db.clone(),
+                        position: pos,
+                        kind: kind
+                    })
+                }
+            }
+        }
+        res.sort_by(|left, right| {
+            (left.database.as_ref(), left.position)
+                .cmp(&(right.database.as_ref(), right.position))
+        });
+        res
+    }
+}
+
+impl Default for Conf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
 /// # Parsing Conf File
 ///
 impl Conf {
@@ -40,52 +198,121 @@ impl Conf {
     }
 
     /// Parse a conf from a reader.
+    ///
+    /// A line ending in a backslash is joined with the following line,
+    /// allowing the rules of a database to span several lines. Comments
+    /// are removed first, so a comment ending in a backslash doesn’t
+    /// continue.
+    ///
+    /// All names are converted to lowercase. Use `parse_with()` to keep
+    /// the case of unknown services.
     pub fn parse<R: io::Read>(reader: &mut R) -> Result<Self, Error> {
+        Self::parse_with(reader, ParseOptions::default())
+    }
+
+    /// Parse a conf from a reader using the given options.
+    pub fn parse_with<R: io::Read>(reader: &mut R, options: ParseOptions)
+                                   -> Result<Self, Error> {
         use std::io::BufRead;
 
         let mut res = Conf::new();
+        let mut logical = String::new();
         for line in io::BufReader::new(reader).lines() {
-            let _ = res.parse_line(&mut line?);
+            let line = line?;
+
+            // Remove comments before looking for a continuation.
+            let line: &str = match line.find('#') {
+                Some(pos) => &line[..pos],
+                None => &line
+            };
+            let line = line.trim_right();
+            if line.ends_with('\\') {
+                logical.push_str(line.trim_right_matches('\\'));
+                logical.push(' ');
+                continue;
+            }
+            logical.push_str(line);
+            let _ = res.parse_line(&logical, options);
+            logical.clear();
+        }
+        if !logical.is_empty() {
+            let _ = res.parse_line(&logical, options);
         }
         Ok(res)
     }
 
-    fn parse_line(&mut self, line: &mut str) -> Result<(), Error> {
-        /// Quick workaround: Make everything lowercase.
-        line.make_ascii_lowercase();
-
-        /// Remove comments, strip white space, and return early on empty.
-        let line: &str = match line.find('#') {
-            Some(pos) => line.split_at(pos).0,
-            None => &line
-        };
-        let line = line.trim();
+    fn parse_line(&mut self, line: &str, options: ParseOptions)
+                  -> Result<(), Error> {
+        // Comments are already gone. Strip white space and a byte order
+        // mark, and return early on empty.
+        let line = line.trim_matches(|ch: char| {
+            ch.is_whitespace() || ch == '\u{feff}'
+        });
         if line.is_empty() { return Ok(()) }
         let mut words = line.split_whitespace();
 
-        /// First word is the database followed by a colon.
+        // First word is the database followed by a colon.
         let db = words.next().ok_or(Error::ParseError)?;
         if !db.ends_with(':') {
             return Err(Error::ParseError);
         }
         let db = db.trim_right_matches(':');
-        let db = Database::from_str(db)?;
+        let mut db = Database::from_str(db)?;
 
-        /// All following words are rules.
-        ///
-        /// We can’t use collect() here because of the error handling. Or
-        /// can we?
+        // All following words are rules.
+        //
+        // We can’t use collect() here because of the error handling. Or
+        // can we?
         let mut rules = Vec::new();
         for word in words {
             rules.push(Rule::from_str(word)?)
         }
 
+        // Known names are matched ignoring case already, so only the
+        // names kept verbatim need converting to lowercase.
+        if !options.preserve_case {
+            db.fold_case();
+            for rule in &mut rules {
+                if let Rule::Service(ref mut service) = *rule {
+                    service.fold_case()
+                }
+            }
+        }
+
         self.databases.insert(db, rules);
         Ok(())
     }
 }
 
 
+//------------ ParseOptions --------------------------------------------------
+
+/// Options for parsing a name service configuration.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ParseOptions {
+    preserve_case: bool,
+}
+
+impl ParseOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps the case of unknown database and service names.
+    ///
+    /// By default, unknown names are converted to lowercase. Since
+    /// the names of NSS modules are the names of shared objects, they may
+    /// be case-sensitive on some platforms. If enabled, `Service::Other`
+    /// and `Database::Other` keep the names exactly as given. Known names
+    /// and keywords are matched ignoring case either way.
+    pub fn preserve_case(mut self, value: bool) -> Self {
+        self.preserve_case = value;
+        self
+    }
+}
+
+
 //------------ Rule ----------------------------------------------------------
 
 /// A lookup rule for a single database.
@@ -105,7 +332,7 @@ impl FromStr for Rule {
                 return Err(Error::ParseError)
             }
             let mut iter = s.trim_left_matches('[')
-                            .trim_right_matches(']');
+                            .trim_right_matches(']')
                             .splitn(2, '=');
             let status = iter.next().ok_or(Error::ParseError)?;
             let action = iter.next().ok_or(Error::ParseError)?;
@@ -118,10 +345,24 @@ impl FromStr for Rule {
     }
 }
 
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Rule::Service(ref service) => service.fmt(f),
+            Rule::Action(status, action) => {
+                write!(f, "[{}={}]", status, action)
+            }
+        }
+    }
+}
+
 
 //------------ Database ------------------------------------------------------
 
 /// A database referenced in the name service configuration.
+///
+/// Database names are matched ignoring ASCII case. Unknown names are kept
+/// verbatim in `Other`.
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum Database {
     /// The hosts database.
@@ -140,12 +381,20 @@ pub enum Database {
     Other(String),
 }
 
+impl Database {
+    /// Converts the name of an unknown database to lowercase.
+    fn fold_case(&mut self) {
+        if let Database::Other(ref mut db) = *self {
+            db.make_ascii_lowercase()
+        }
+    }
+}
 
 impl FromStr for Database {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s {
+        Ok(match s.to_ascii_lowercase().as_str() {
             "hosts" => Database::Hosts,
             "networks" => Database::Networks,
             "protocols" => Database::Protocols,
@@ -217,7 +466,7 @@ impl FromStr for Status {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
+        match s.to_ascii_lowercase().as_str() {
             "success" => Ok(Status::Success),
             "notfound" => Ok(Status::NotFound),
             "unavail" => Ok(Status::Unavail),
@@ -278,7 +527,7 @@ impl FromStr for Action {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
+        match s.to_ascii_lowercase().as_str() {
             "return" => Ok(Action::Return),
             "continue" => Ok(Action::Continue),
             "merge" => Ok(Action::Merge),
@@ -319,6 +568,9 @@ impl fmt::Display for Action {
 /// unknown service values.
 ///
 /// Note that not all service values are necessary valid for all databases.
+///
+/// Known service values are matched ignoring ASCII case. Unknown values are
+/// kept verbatim in `Other`.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Service {
     /// `"compat"`
@@ -341,11 +593,20 @@ pub enum Service {
     Other(String),
 }
 
+impl Service {
+    /// Converts the name of an unknown service to lowercase.
+    fn fold_case(&mut self) {
+        if let Service::Other(ref mut service) = *self {
+            service.make_ascii_lowercase()
+        }
+    }
+}
+
 impl FromStr for Service {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s {
+        Ok(match s.to_ascii_lowercase().as_str() {
             "compat" => Service::Compat,
             "dns" => Service::Dns,
             "files" => Service::Files,
@@ -383,6 +644,141 @@ impl fmt::Display for Service {
 }
 
 
+//------------ Warning -------------------------------------------------------
+
+/// A structural problem found by `Conf::validate()`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Warning {
+    database: Database,
+    position: usize,
+    kind: WarningKind,
+}
+
+impl Warning {
+    /// Returns the database whose rules have the problem.
+    pub fn database(&self) -> &Database {
+        &self.database
+    }
+
+    /// Returns the index of the offending rule in the database’s rules.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Returns the kind of problem.
+    pub fn kind(&self) -> WarningKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: rule {}: ", self.database, self.position + 1)?;
+        match self.kind {
+            WarningKind::ActionFirst => {
+                f.write_str("action before any service")
+            }
+            WarningKind::DanglingAction => {
+                f.write_str("action after the last service")
+            }
+        }
+    }
+}
+
+
+//------------ WarningKind ---------------------------------------------------
+
+/// The kind of problem reported by a `Warning`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WarningKind {
+    /// An action appears before any service.
+    ActionFirst,
+
+    /// An action appears after the last service.
+    DanglingAction,
+}
+
+
+//------------ ConfWatch -----------------------------------------------------
+
+/// A stream of the configuration whenever its files change.
+///
+/// The stream is returned by `watch_config()`. It checks the modification
+/// time and size of a list of files periodically. If any of them has
+/// changed, including a file appearing or disappearing, it parses the
+/// first file of the list as the name service configuration and yields
+/// the result. The other files are only watched, so that a stream
+/// watching `resolv.conf`, too, yields the unchanged name service
+/// configuration if only the latter changes, signalling that it is time
+/// to reload the resolver configuration.
+///
+/// If parsing fails, the stream yields the error. It can be polled again
+/// afterwards and continues to watch the files. The stream never ends.
+pub struct ConfWatch {
+    paths: Vec<PathBuf>,
+
+    /// The modification time and size of each file if it exists.
+    stamps: Vec<Option<(SystemTime, u64)>>,
+
+    interval: reactor::Interval,
+}
+
+impl ConfWatch {
+    /// Starts watching the files in `paths` every `period`.
+    ///
+    /// The configuration is parsed from the first file. The stream only
+    /// yields for changes happening after it was created.
+    pub fn new(paths: Vec<PathBuf>, period: Duration,
+               reactor: &reactor::Handle) -> io::Result<Self> {
+        Ok(ConfWatch {
+            stamps: paths.iter().map(|path| stamp(path)).collect(),
+            paths: paths,
+            interval: reactor::Interval::new(period, reactor)?,
+        })
+    }
+
+    /// Updates the stamps of the files and returns whether any changed.
+    fn changed(&mut self) -> bool {
+        let mut res = false;
+        for (path, old) in self.paths.iter().zip(self.stamps.iter_mut()) {
+            let new = stamp(path);
+            if new != *old {
+                *old = new;
+                res = true;
+            }
+        }
+        res
+    }
+}
+
+impl Stream for ConfWatch {
+    type Item = Conf;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            try_ready!(self.interval.poll());
+            if self.changed() {
+                let conf = match self.paths.first() {
+                    Some(path) => Conf::parse_file(path)?,
+                    None => Conf::new(),
+                };
+                return Ok(Async::Ready(Some(conf)))
+            }
+        }
+    }
+}
+
+/// A configuration read from a file with the file’s path and stamp.
+type CachedConf = (PathBuf, Option<(SystemTime, u64)>, Conf);
+
+/// Returns the modification time and size of a file if it exists.
+fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+
 //------------ Error and Result ----------------------------------------------
 
 /// An error happend during parsing a hosts file.
@@ -410,26 +806,6 @@ impl From<io::Error> for Error {
     }
 }
 
-/*
-impl From<name::FromStrError> for Error {
-    fn from(_: name::FromStrError) -> Error {
-        Error::ParseError
-    }
-}
-
-impl From<::std::num::ParseIntError> for Error {
-    fn from(_: ::std::num::ParseIntError) -> Error {
-        Error::ParseError
-    }
-}
-
-impl From<net::AddrParseError> for Error {
-    fn from(_: net::AddrParseError) -> Error {
-        Error::ParseError
-    }
-}
-*/
-
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use std::error::Error;
@@ -479,5 +855,225 @@ mod test {
                         Rule::Service(Service::Other("myho".into()))
                    ][..]));
     }
-}
 
+    #[test]
+    fn parse_continuation() {
+        use std::io::Cursor;
+
+        let mut conf = Cursor::new(
+            "hosts:  files \\\n\
+             \x20       [NOTFOUND=return] \\\n\
+             \x20       dns # the rest\n\
+             # a comment ending in a backslash \\\n\
+             networks: files\n\
+             ");
+        let conf = Conf::parse(&mut conf).unwrap();
+        assert_eq!(conf.database(&Database::Hosts),
+                   Some(&[
+                        Rule::Service(Service::Files),
+                        Rule::Action(Status::NotFound, Action::Return),
+                        Rule::Service(Service::Dns),
+                   ][..]));
+        assert_eq!(conf.database(&Database::Networks),
+                   Some(&[Rule::Service(Service::Files)][..]));
+    }
+
+    /// Checks that `from_str()` reverses `as_ref()` for every value.
+    ///
+    /// Keep the tables here in sync with the variants of the enums.
+    #[test]
+    fn round_trip() {
+        for db in &[Database::Hosts, Database::Networks, Database::Protocols,
+                    Database::Services, Database::Other("passwd".into()),
+                    Database::Other("Netgroup".into())] {
+            assert_eq!(&Database::from_str(db.as_ref()).unwrap(), db);
+            assert_eq!(&Database::from_str(&db.to_string()).unwrap(), db);
+        }
+        for stat in &[Status::Success, Status::NotFound, Status::Unavail,
+                      Status::TryAgain] {
+            assert_eq!(&Status::from_str(stat.as_ref()).unwrap(), stat);
+            assert_eq!(&Status::from_str(&stat.to_string()).unwrap(), stat);
+        }
+        for action in &[Action::Return, Action::Continue, Action::Merge] {
+            assert_eq!(&Action::from_str(action.as_ref()).unwrap(), action);
+            assert_eq!(&Action::from_str(&action.to_string()).unwrap(),
+                       action);
+        }
+        for service in &[Service::Compat, Service::Dns, Service::Files,
+                         Service::Other("mdns4_minimal".into()),
+                         Service::Other("MyMod".into())] {
+            assert_eq!(&Service::from_str(service.as_ref()).unwrap(),
+                       service);
+            assert_eq!(&Service::from_str(&service.to_string()).unwrap(),
+                       service);
+        }
+        for rule in &[Rule::Service(Service::Dns),
+                      Rule::Service(Service::Other("myho".into())),
+                      Rule::Action(Status::NotFound, Action::Return),
+                      Rule::Action(Status::TryAgain, Action::Merge)] {
+            assert_eq!(&Rule::from_str(&rule.to_string()).unwrap(), rule);
+        }
+    }
+
+    #[test]
+    fn keywords_ignore_case() {
+        assert_eq!(Database::from_str("Hosts").unwrap(), Database::Hosts);
+        assert_eq!(Status::from_str("NOTFOUND").unwrap(), Status::NotFound);
+        assert_eq!(Action::from_str("Return").unwrap(), Action::Return);
+        assert_eq!(Service::from_str("DNS").unwrap(), Service::Dns);
+    }
+
+    #[test]
+    fn validate() {
+        use std::io::Cursor;
+
+        let mut conf = Cursor::new(
+            "hosts:    files dns [SUCCESS=continue]\n\
+             networks: [NOTFOUND=return] files\n\
+             services: files [NOTFOUND=return] db\n\
+             ");
+        let conf = Conf::parse(&mut conf).unwrap();
+        let warnings = conf.validate();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[0].database(), &Database::Hosts);
+        assert_eq!(warnings[0].position(), 2);
+        assert_eq!(warnings[0].kind(), WarningKind::DanglingAction);
+        assert_eq!(warnings[1].database(), &Database::Networks);
+        assert_eq!(warnings[1].position(), 0);
+        assert_eq!(warnings[1].kind(), WarningKind::ActionFirst);
+        assert_eq!(warnings[1].to_string(),
+                   "networks: rule 1: action before any service");
+    }
+
+    #[test]
+    fn preserve_case() {
+        let line = "Hosts: Files MyMod [NotFound=Return] DNS\n";
+        let conf = Conf::parse(&mut line.as_bytes()).unwrap();
+        assert_eq!(conf.database(&Database::Hosts).unwrap()[1],
+                   Rule::Service(Service::Other("mymod".into())));
+
+        let conf = Conf::parse_with(&mut line.as_bytes(),
+                                    ParseOptions::new().preserve_case(true))
+                       .unwrap();
+        let rules = conf.database(&Database::Hosts).unwrap();
+        assert_eq!(rules,
+                   &[Rule::Service(Service::Files),
+                     Rule::Service(Service::Other("MyMod".into())),
+                     Rule::Action(Status::NotFound, Action::Return),
+                     Rule::Service(Service::Dns)][..]);
+        assert_eq!(rules[1].to_string(), "MyMod");
+    }
+
+    #[test]
+    fn case_of_keys_only() {
+        let data = "Hosts: FILES MyNss [NotFound=Return] Dns\n\
+                    AutoMount: Files # Some Comment\n";
+        let options = ParseOptions::new().preserve_case(true);
+        let conf = Conf::parse_with(&mut data.as_bytes(), options).unwrap();
+        assert_eq!(conf.database(&Database::Hosts),
+                   Some(&[Rule::Service(Service::Files),
+                          Rule::Service(Service::Other("MyNss".into())),
+                          Rule::Action(Status::NotFound, Action::Return),
+                          Rule::Service(Service::Dns)][..]));
+        assert_eq!(conf.database(&Database::Other("AutoMount".into())),
+                   Some(&[Rule::Service(Service::Files)][..]));
+        assert_eq!(conf.database(&Database::Other("automount".into())),
+                   None);
+
+        let conf = Conf::parse(&mut data.as_bytes()).unwrap();
+        assert_eq!(conf.database(&Database::Hosts).unwrap()[1],
+                   Rule::Service(Service::Other("mynss".into())));
+        assert_eq!(conf.database(&Database::Other("automount".into())),
+                   Some(&[Rule::Service(Service::Files)][..]));
+    }
+
+    #[test]
+    fn system_path() {
+        assert_eq!(Conf::system_path_with(Some("/tmp/nsswitch.conf".into())),
+                   PathBuf::from("/tmp/nsswitch.conf"));
+        assert_eq!(Conf::system_path_with(Some("".into())),
+                   PathBuf::from("/etc/nsswitch.conf"));
+        assert_eq!(Conf::system_path_with(None),
+                   PathBuf::from("/etc/nsswitch.conf"));
+    }
+
+    #[test]
+    fn cached() {
+        use std::io::Write;
+
+        let mut path = env::temp_dir();
+        path.push(format!("netdb-{}-cached.conf", ::std::process::id()));
+        fs::File::create(&path).unwrap().write_all(b"hosts: files\n")
+                                        .unwrap();
+        assert_eq!(Conf::cached(&path).database(&Database::Hosts),
+                   Some(&[Rule::Service(Service::Files)][..]));
+        fs::File::create(&path).unwrap().write_all(b"hosts: dns files\n")
+                                        .unwrap();
+        assert_eq!(Conf::cached(&path).database(&Database::Hosts),
+                   Some(&[Rule::Service(Service::Dns),
+                          Rule::Service(Service::Files)][..]));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(Conf::cached(&path), Conf::new());
+    }
+
+    #[test]
+    fn poll_system() {
+        use futures::Future;
+
+        assert_eq!(Conf::poll_system().wait().unwrap(), Conf::system());
+    }
+
+    #[test]
+    fn poll_file() {
+        use std::io::Write;
+        use futures::Future;
+
+        let mut path = env::temp_dir();
+        path.push(format!("netdb-{}-poll.conf", ::std::process::id()));
+        fs::File::create(&path).unwrap()
+            .write_all(b"hosts: dns [NOTFOUND=return] files\n").unwrap();
+        let conf = Conf::poll_file(&path).wait().unwrap();
+        assert_eq!(conf, Conf::parse_file(&path).unwrap());
+        assert_eq!(conf.database(&Database::Hosts).unwrap()[0],
+                   Rule::Service(Service::Dns));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(Conf::poll_file(&path).wait().unwrap(), Conf::new());
+    }
+
+    #[test]
+    fn watch() {
+        use std::io::Write;
+        use futures::Future;
+
+        let mut path = env::temp_dir();
+        path.push(format!("netdb-{}-watch.conf", ::std::process::id()));
+        fs::File::create(&path).unwrap().write_all(b"hosts: files\n")
+                                        .unwrap();
+        let mut core = reactor::Core::new().unwrap();
+        let watch = ConfWatch::new(vec![path.clone()],
+                                   Duration::from_millis(10),
+                                   &core.handle()).unwrap();
+        fs::File::create(&path).unwrap().write_all(b"hosts: dns files\n")
+                                        .unwrap();
+        let (conf, watch) = core.run(watch.into_future()).ok().unwrap();
+        assert_eq!(conf.unwrap().database(&Database::Hosts),
+                   Some(&[Rule::Service(Service::Dns),
+                          Rule::Service(Service::Files)][..]));
+
+        // Errors don’t end the stream.
+        fs::remove_file(&path).unwrap();
+        let (err, watch) = core.run(watch.into_future()).err().unwrap();
+        match err {
+            Error::IoError(ref err) => {
+                assert_eq!(err.kind(), io::ErrorKind::NotFound)
+            }
+            _ => panic!("missing file not reported")
+        }
+        fs::File::create(&path).unwrap().write_all(b"hosts: files\n")
+                                        .unwrap();
+        let (conf, _) = core.run(watch.into_future()).ok().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(conf.unwrap().database(&Database::Hosts),
+                   Some(&[Rule::Service(Service::Files)][..]));
+    }
+}
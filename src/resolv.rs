@@ -0,0 +1,734 @@
+//! Parsing of resolv.conf.
+//!
+//! Parsing herein follows the `resolv.conf` file used by glibc 2.
+//!
+//! Comments start with either `#` or `;`. Unlike `#`, a `;` only starts a
+//! comment at the start of a line or after white space.
+
+use std::{cmp, env, error, fmt, fs, io};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use domain::bits::DNameBuf;
+use domain::resolv;
+use domain::resolv::conf::{ServerConf, Transport};
+use ::blocking::Blocking;
+use ::networks::natural_prefix_len;
+
+
+//------------ ResolvConf ----------------------------------------------------
+
+/// The resolver configuration.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ResolvConf {
+    servers: Vec<SocketAddr>,
+    search: Vec<DNameBuf>,
+    ndots: usize,
+    timeout: Duration,
+    attempts: usize,
+    single_request: bool,
+    rotate: bool,
+    sortlist: Vec<SortlistEntry>,
+}
+
+
+/// # Limits
+///
+/// These are the limits glibc imposes on the configuration. Values beyond
+/// them are silently truncated.
+impl ResolvConf {
+    /// The maximum number of name servers.
+    pub const MAX_SERVERS: usize = 3;
+
+    /// The maximum number of search domains.
+    pub const MAX_SEARCH: usize = 6;
+
+    /// The maximum value for `ndots`.
+    pub const MAX_NDOTS: usize = 15;
+
+    /// The maximum timeout in seconds.
+    pub const MAX_TIMEOUT: u64 = 30;
+
+    /// The maximum number of attempts.
+    pub const MAX_ATTEMPTS: usize = 5;
+
+    /// The maximum number of sortlist entries.
+    pub const MAX_SORTLIST: usize = 10;
+}
+
+
+impl ResolvConf {
+    /// Creates a new configuration with default values.
+    pub fn new() -> Self {
+        ResolvConf {
+            servers: Vec::new(),
+            search: Vec::new(),
+            ndots: 1,
+            timeout: Duration::from_secs(5),
+            attempts: 2,
+            single_request: false,
+            rotate: false,
+            sortlist: Vec::new(),
+        }
+    }
+
+    /// The addresses of the name servers in the order given.
+    ///
+    /// This is empty if the configuration doesn’t list any name servers.
+    /// See `effective_servers()` for the name servers actually used.
+    pub fn servers(&self) -> &[SocketAddr] {
+        self.servers.as_ref()
+    }
+
+    /// The addresses of the name servers to use.
+    ///
+    /// These are the name servers given by the configuration or, if there
+    /// are none, the name server on the local host at `127.0.0.1` just
+    /// like glibc does.
+    pub fn effective_servers(&self) -> Vec<SocketAddr> {
+        if self.servers.is_empty() {
+            vec![SocketAddr::new(IpAddr::from([127, 0, 0, 1]), 53)]
+        }
+        else {
+            self.servers.clone()
+        }
+    }
+
+    /// The search list in the order given.
+    pub fn search(&self) -> &[DNameBuf] {
+        self.search.as_ref()
+    }
+
+    /// The number of dots that make a name be tried as absolute first.
+    pub fn ndots(&self) -> usize {
+        self.ndots
+    }
+
+    /// The time to wait for a response from a server.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// The number of times to try each server.
+    pub fn attempts(&self) -> usize {
+        self.attempts
+    }
+
+    /// Whether queries for several record types are to be asked in turn.
+    ///
+    /// This is set by the `single-request` option.
+    pub fn single_request(&self) -> bool {
+        self.single_request
+    }
+
+    /// Whether the name servers are to be used round-robin.
+    ///
+    /// This is set by the `rotate` option. Otherwise, the name servers are
+    /// always tried in the order given.
+    pub fn rotate(&self) -> bool {
+        self.rotate
+    }
+
+    /// The sortlist in the order given.
+    ///
+    /// Addresses matching an earlier entry are preferred over those
+    /// matching a later one or none at all.
+    pub fn sortlist(&self) -> &[SortlistEntry] {
+        self.sortlist.as_ref()
+    }
+
+    /// Returns the rank of `addr` according to the sortlist.
+    ///
+    /// This is the index of the first entry matching the address or the
+    /// length of the sortlist if none does. Sorting addresses by their
+    /// rank puts them in the order preferred by the sortlist.
+    pub fn sortlist_rank(&self, addr: &IpAddr) -> usize {
+        self.sortlist.iter().position(|entry| entry.contains(addr))
+                     .unwrap_or(self.sortlist.len())
+    }
+
+    /// Returns whether systemd-resolved’s stub resolver is used.
+    ///
+    /// This is the case if any of the name servers is `127.0.0.53` on port
+    /// 53, the address the stub listens on.
+    pub fn is_systemd_stub(&self) -> bool {
+        let stub = SocketAddr::new(IpAddr::from([127, 0, 0, 53]), 53);
+        self.servers.contains(&stub)
+    }
+}
+
+/// # System Configuration
+///
+impl ResolvConf {
+    /// Returns the resolver configuration of this system.
+    ///
+    /// If the configuration file cannot be read, returns the defaults. Use
+    /// `parse_file_or_default()` if you need to know whether this happened.
+    ///
+    /// The overrides given through environment variables are applied as
+    /// described with `apply_env()`.
+    ///
+    /// XXX This currently only works for Unix-y systems.
+    pub fn system() -> Self {
+        let mut res = Self::parse_file_or_default("/etc/resolv.conf").0;
+        res.apply_env();
+        res
+    }
+
+    /// Returns a future resolving into the configuration of this system.
+    ///
+    /// This is identical to `system()` except that the file is read on a
+    /// thread of its own so that a reactor isn’t blocked during startup.
+    pub fn poll_system() -> Blocking<Self> {
+        Blocking::spawn(|| Ok(Self::system()))
+    }
+
+    /// Applies the overrides from the environment.
+    ///
+    /// As with glibc, the environment variable `LOCALDOMAIN` replaces the
+    /// search list with its space-separated list of domains and
+    /// `RES_OPTIONS` is processed as if it was an additional `options` line
+    /// at the end of the file. Values that fail to parse are ignored.
+    pub fn apply_env(&mut self) {
+        let domains = env::var("LOCALDOMAIN").ok();
+        let options = env::var("RES_OPTIONS").ok();
+        self.apply_overrides(domains.as_ref().map(AsRef::as_ref),
+                             options.as_ref().map(AsRef::as_ref));
+    }
+
+    /// Applies the values of `LOCALDOMAIN` and `RES_OPTIONS`.
+    fn apply_overrides(&mut self, domains: Option<&str>,
+                       options: Option<&str>) {
+        if let Some(domains) = domains {
+            let _ = self.parse_search(domains.split_whitespace());
+        }
+        if let Some(options) = options {
+            let _ = self.parse_options(options.split_whitespace());
+        }
+    }
+
+    /// Converts the configuration into one for the `domain` resolver.
+    ///
+    /// The name servers used are those returned by `effective_servers()`.
+    pub fn to_domain_conf(&self) -> resolv::ResolvConf {
+        let mut res = resolv::ResolvConf::new();
+        for addr in self.effective_servers() {
+            res.servers.push(ServerConf::new(addr, Transport::Udp));
+            res.servers.push(ServerConf::new(addr, Transport::Tcp));
+        }
+        res.search = self.search.clone();
+        res.ndots = self.ndots;
+        res.timeout = self.timeout;
+        res.attempts = self.attempts;
+        res.options.rotate = self.rotate;
+        res.finalize();
+        res
+    }
+}
+
+impl Default for ResolvConf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// # Parsing Conf File
+///
+impl ResolvConf {
+    /// Parse a conf file.
+    pub fn parse_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::parse(&mut fs::File::open(path)?)
+    }
+
+    /// Parse a conf file, falling back to the defaults.
+    ///
+    /// If the file cannot be read, returns the default configuration
+    /// together with the error that happened. This allows callers to
+    /// notice, and maybe report, that they are running on defaults rather
+    /// than on the configured name servers while still being able to
+    /// resolve names.
+    pub fn parse_file_or_default<P: AsRef<Path>>(path: P)
+                                                 -> (Self, Option<Error>) {
+        match Self::parse_file(path) {
+            Ok(conf) => (conf, None),
+            Err(err) => (Self::default(), Some(err)),
+        }
+    }
+
+    /// Parse a conf from a reader.
+    ///
+    /// Lines that fail to parse are ignored.
+    pub fn parse<R: io::Read>(reader: &mut R) -> Result<Self, Error> {
+        use std::io::BufRead;
+
+        let mut res = ResolvConf::new();
+        for line in io::BufReader::new(reader).lines() {
+            let _ = res.parse_line(&line?);
+        }
+        Ok(res)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), Error> {
+        // Remove comments, strip white space and a byte order mark, and
+        // return early on empty.
+        let line = strip_comment(line).trim_matches(|ch: char| {
+            ch.is_whitespace() || ch == '\u{feff}'
+        });
+        if line.is_empty() { return Ok(()) }
+        let mut words = line.split_whitespace();
+
+        match words.next() {
+            Some("nameserver") => self.parse_nameserver(words),
+            Some("domain") => self.parse_domain(words),
+            Some("search") => self.parse_search(words),
+            Some("options") => self.parse_options(words),
+            Some("sortlist") => self.parse_sortlist(words),
+            _ => Err(Error::ParseError),
+        }
+    }
+
+    /// Parses a nameserver line.
+    ///
+    /// In addition to a plain address, which uses port 53, this accepts an
+    /// address and port in the socket address notation, i.e.,
+    /// `127.0.0.1:5353` or `[::1]:5353`. This is an extension to glibc,
+    /// which doesn’t allow a port, but is supported by other tools.
+    fn parse_nameserver<'a, I>(&mut self, mut words: I) -> Result<(), Error>
+                        where I: Iterator<Item=&'a str> {
+        let addr = words.next().ok_or(Error::ParseError)?;
+        let addr = match IpAddr::from_str(addr) {
+            Ok(addr) => SocketAddr::new(addr, 53),
+            Err(_) => {
+                SocketAddr::from_str(addr).map_err(|_| Error::ParseError)?
+            }
+        };
+        if self.servers.len() < Self::MAX_SERVERS {
+            self.servers.push(addr);
+        }
+        Ok(())
+    }
+
+    /// Parses a domain line.
+    ///
+    /// As with glibc, the last of all domain and search lines wins. A
+    /// domain line therefore replaces the search list with the one domain.
+    fn parse_domain<'a, I>(&mut self, mut words: I) -> Result<(), Error>
+                    where I: Iterator<Item=&'a str> {
+        let domain = words.next().ok_or(Error::ParseError)?;
+        let domain = DNameBuf::from_str(domain)
+                              .map_err(|_| Error::ParseError)?;
+        self.search = vec![domain];
+        Ok(())
+    }
+
+    /// Parses a search line.
+    ///
+    /// The line replaces any earlier search list. Domains beyond
+    /// `MAX_SEARCH` are ignored.
+    fn parse_search<'a, I>(&mut self, words: I) -> Result<(), Error>
+                    where I: Iterator<Item=&'a str> {
+        let mut search = Vec::new();
+        for word in words.take(Self::MAX_SEARCH) {
+            search.push(DNameBuf::from_str(word)
+                                 .map_err(|_| Error::ParseError)?);
+        }
+        self.search = search;
+        Ok(())
+    }
+
+    /// Parses a sortlist line.
+    ///
+    /// Each entry is an IPv4 address optionally followed by a slash and a
+    /// netmask. Without a netmask, the natural netmask of the address’
+    /// class is used. As with glibc, the line replaces any earlier sortlist
+    /// and entries beyond `MAX_SORTLIST` are ignored.
+    fn parse_sortlist<'a, I>(&mut self, words: I) -> Result<(), Error>
+                      where I: Iterator<Item=&'a str> {
+        let mut sortlist = Vec::new();
+        for word in words.take(Self::MAX_SORTLIST) {
+            sortlist.push(SortlistEntry::from_str(word)?);
+        }
+        self.sortlist = sortlist;
+        Ok(())
+    }
+
+    /// Parses an options line.
+    ///
+    /// Each word of the line is a separate option and is applied in turn.
+    /// Unknown options are ignored. An option with an invalid value is
+    /// skipped, too, but still makes the line result in an error once all
+    /// other options have been applied.
+    fn parse_options<'a, I>(&mut self, words: I) -> Result<(), Error>
+                     where I: Iterator<Item=&'a str> {
+        let mut res = Ok(());
+        for word in words {
+            if let Err(err) = self.parse_option(word) {
+                res = Err(err)
+            }
+        }
+        res
+    }
+
+    /// Parses and applies a single option.
+    fn parse_option(&mut self, word: &str) -> Result<(), Error> {
+        let mut parts = word.splitn(2, ':');
+        let name = parts.next().unwrap();
+        let value = parts.next();
+        match name {
+            "ndots" => {
+                self.ndots = cmp::min(int_value(value)?, Self::MAX_NDOTS)
+            }
+            "timeout" => {
+                self.timeout = Duration::from_secs(
+                    cmp::min(int_value(value)? as u64, Self::MAX_TIMEOUT)
+                )
+            }
+            "attempts" => {
+                self.attempts = cmp::min(int_value(value)?,
+                                         Self::MAX_ATTEMPTS)
+            }
+            "single-request" => self.single_request = true,
+            "rotate" => self.rotate = true,
+            _ => { }
+        }
+        Ok(())
+    }
+}
+
+//------------ SortlistEntry -------------------------------------------------
+
+/// An entry of the sortlist.
+///
+/// The entry matches all IPv4 addresses in the network given by an address
+/// and a netmask.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct SortlistEntry {
+    addr: Ipv4Addr,
+    mask: Ipv4Addr,
+}
+
+impl SortlistEntry {
+    /// Creates a new entry from an address and a netmask.
+    pub fn new(addr: Ipv4Addr, mask: Ipv4Addr) -> Self {
+        SortlistEntry { addr: addr, mask: mask }
+    }
+
+    /// Returns the address of the entry.
+    pub fn addr(&self) -> Ipv4Addr {
+        self.addr
+    }
+
+    /// Returns the netmask of the entry.
+    pub fn mask(&self) -> Ipv4Addr {
+        self.mask
+    }
+
+    /// Returns whether `addr` is in the network of the entry.
+    ///
+    /// IPv6 addresses never match.
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        match *addr {
+            IpAddr::V4(addr) => {
+                let mask = u32::from(self.mask);
+                u32::from(addr) & mask == u32::from(self.addr) & mask
+            }
+            IpAddr::V6(_) => false
+        }
+    }
+
+    /// Returns the natural netmask for the class of `addr`.
+    fn natural_mask(addr: Ipv4Addr) -> Ipv4Addr {
+        let len = u32::from(natural_prefix_len(addr));
+        Ipv4Addr::from(!0u32 << (32 - len))
+    }
+}
+
+impl FromStr for SortlistEntry {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut parts = s.splitn(2, '/');
+        let addr = Ipv4Addr::from_str(parts.next().unwrap())
+                            .map_err(|_| Error::ParseError)?;
+        let mask = match parts.next() {
+            Some(mask) => {
+                Ipv4Addr::from_str(mask).map_err(|_| Error::ParseError)?
+            }
+            None => Self::natural_mask(addr)
+        };
+        Ok(SortlistEntry::new(addr, mask))
+    }
+}
+
+
+//------------ TlsServer -----------------------------------------------------
+
+/// A name server to be queried via DNS-over-TLS.
+///
+/// The server is given by its socket address, normally using port 853,
+/// and the host name its certificate is expected to be issued for.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct TlsServer {
+    addr: SocketAddr,
+    name: String,
+}
+
+impl TlsServer {
+    /// Creates a new server from its address and its certificate’s name.
+    pub fn new(addr: SocketAddr, name: &str) -> Self {
+        TlsServer { addr: addr, name: name.into() }
+    }
+
+    /// Returns the socket address of the server.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the name the server’s certificate is validated against.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Parses the value of an integer option.
+fn int_value(value: Option<&str>) -> Result<usize, Error> {
+    let value = value.ok_or(Error::ParseError)?;
+    usize::from_str(value).map_err(|_| Error::ParseError)
+}
+
+/// Returns a line with its comment removed.
+///
+/// A comment starts with a `#` anywhere or with a `;` at the start of the
+/// line or after white space and extends to the end of the line. Hosts
+/// files use the same rules.
+pub(crate) fn strip_comment(line: &str) -> &str {
+    let mut prev_space = true;
+    for (pos, ch) in line.char_indices() {
+        if ch == '#' || (ch == ';' && prev_space) {
+            return &line[..pos]
+        }
+        prev_space = ch.is_whitespace() || ch == '\u{feff}';
+    }
+    line
+}
+
+
+//------------ Error and Result ----------------------------------------------
+
+/// An error happend during parsing a resolv.conf file.
+#[derive(Debug)]
+pub enum Error {
+    /// The file is kaputt.
+    ParseError,
+
+    /// Reading failed.
+    IoError(io::Error),
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::ParseError => "error parsing configuration",
+            Error::IoError(ref e) => e.description(),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::IoError(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use std::error::Error;
+
+        self.description().fmt(f)
+    }
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<DNameBuf> {
+        names.iter().map(|name| DNameBuf::from_str(name).unwrap()).collect()
+    }
+
+    #[test]
+    fn search() {
+        let conf = ResolvConf::parse(&mut Cursor::new(
+            "search a.example b.example c.example d.example e.example \
+             f.example g.example\n"
+        )).unwrap();
+        assert_eq!(conf.search(),
+                   &names(&["a.example", "b.example", "c.example",
+                            "d.example", "e.example", "f.example"])[..]);
+
+        let conf = ResolvConf::parse(&mut Cursor::new(
+            "search a.example b.example\n\
+             search c.example\n"
+        )).unwrap();
+        assert_eq!(conf.search(), &names(&["c.example"])[..]);
+
+        let conf = ResolvConf::parse(&mut Cursor::new(
+            "search a.example b.example\n\
+             domain c.example\n"
+        )).unwrap();
+        assert_eq!(conf.search(), &names(&["c.example"])[..]);
+
+        let conf = ResolvConf::parse(&mut Cursor::new(
+            "domain c.example\n\
+             search a.example b.example\n"
+        )).unwrap();
+        assert_eq!(conf.search(), &names(&["a.example", "b.example"])[..]);
+    }
+
+    #[test]
+    fn comments() {
+        let conf = ResolvConf::parse(&mut Cursor::new(
+            "; nameserver 192.0.2.1\n\
+             \t;nameserver 192.0.2.2\n\
+             nameserver 192.0.2.3 ; comment\n\
+             # nameserver 192.0.2.4\n\
+             search a.example ;b.example\n"
+        )).unwrap();
+        assert_eq!(conf.servers(),
+                   [SocketAddr::from_str("192.0.2.3:53").unwrap()]);
+        assert_eq!(conf.search().len(), 1);
+    }
+
+    #[test]
+    fn no_nameservers() {
+        let conf = ResolvConf::parse(&mut Cursor::new(
+            "search a.example b.example
+"
+        )).unwrap();
+        assert!(conf.servers().is_empty());
+        assert_eq!(conf.effective_servers(),
+                   [SocketAddr::from_str("127.0.0.1:53").unwrap()]);
+
+        let conf = ResolvConf::parse(&mut Cursor::new(
+            "nameserver 192.0.2.1
+"
+        )).unwrap();
+        assert_eq!(conf.effective_servers(),
+                   [SocketAddr::from_str("192.0.2.1:53").unwrap()]);
+    }
+
+    #[test]
+    fn missing_file() {
+        let (conf, err) = ResolvConf::parse_file_or_default(
+            "/nonexistent/netdb/resolv.conf"
+        );
+        assert_eq!(conf, ResolvConf::default());
+        match err {
+            Some(Error::IoError(err)) => {
+                assert_eq!(err.kind(), io::ErrorKind::NotFound)
+            }
+            _ => panic!("missing file not reported")
+        }
+    }
+
+    #[test]
+    fn nameserver_ports() {
+        let conf = ResolvConf::parse(&mut Cursor::new(
+            "nameserver 192.0.2.1\n\
+             nameserver 127.0.0.1:5353\n\
+             nameserver [::1]:5353\n\
+             nameserver 2001:db8::1\n"
+        )).unwrap();
+        assert_eq!(conf.servers(),
+                   &[SocketAddr::from_str("192.0.2.1:53").unwrap(),
+                     SocketAddr::from_str("127.0.0.1:5353").unwrap(),
+                     SocketAddr::from_str("[::1]:5353").unwrap()]);
+
+        let conf = ResolvConf::parse(&mut Cursor::new(
+            "nameserver 2001:db8::1\n"
+        )).unwrap();
+        assert_eq!(conf.servers(),
+                   &[SocketAddr::from_str("[2001:db8::1]:53").unwrap()]);
+    }
+
+    #[test]
+    fn systemd_stub() {
+        let conf = ResolvConf::parse(&mut io::Cursor::new(
+            "nameserver 127.0.0.53\noptions edns0 trust-ad\n"
+        )).unwrap();
+        assert!(conf.is_systemd_stub());
+        assert!(!conf.single_request());
+
+        let conf = ResolvConf::parse(&mut io::Cursor::new(
+            "nameserver 127.0.0.1\noptions single-request\n"
+        )).unwrap();
+        assert!(!conf.is_systemd_stub());
+        assert!(conf.single_request());
+    }
+
+    #[test]
+    fn sortlist() {
+        let conf = ResolvConf::parse(&mut Cursor::new(
+            "sortlist 130.155.160.0/255.255.240.0 10.0.0.0\n"
+        )).unwrap();
+        assert_eq!(conf.sortlist(),
+                   &[SortlistEntry::new(Ipv4Addr::new(130, 155, 160, 0),
+                                        Ipv4Addr::new(255, 255, 240, 0)),
+                     SortlistEntry::new(Ipv4Addr::new(10, 0, 0, 0),
+                                        Ipv4Addr::new(255, 0, 0, 0))]);
+        let rank = |addr: &str| {
+            conf.sortlist_rank(&IpAddr::from_str(addr).unwrap())
+        };
+        assert_eq!(rank("130.155.175.1"), 0);
+        assert_eq!(rank("130.155.176.1"), 2);
+        assert_eq!(rank("10.1.2.3"), 1);
+        assert_eq!(rank("192.0.2.1"), 2);
+        assert_eq!(rank("2001:db8::1"), 2);
+
+        let conf = ResolvConf::parse(&mut Cursor::new(
+            "sortlist 10.0.0.0/bogus\n"
+        )).unwrap();
+        assert!(conf.sortlist().is_empty());
+    }
+
+    #[test]
+    fn env_overrides() {
+        let mut conf = ResolvConf::parse(&mut io::Cursor::new(
+            "search example.com\noptions ndots:2\n"
+        )).unwrap();
+        conf.apply_overrides(Some("corp.example lab.example"),
+                             Some("ndots:3 timeout:1"));
+        assert_eq!(conf.search(),
+                   &names(&["corp.example", "lab.example"])[..]);
+        assert_eq!(conf.ndots(), 3);
+        assert_eq!(conf.timeout(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn options_line() {
+        let conf = ResolvConf::parse(&mut Cursor::new(
+            "options ndots:2 timeout:3 attempts:2 rotate\n"
+        )).unwrap();
+        assert_eq!(conf.ndots(), 2);
+        assert_eq!(conf.timeout(), Duration::from_secs(3));
+        assert_eq!(conf.attempts(), 2);
+        assert!(conf.rotate());
+        assert!(!ResolvConf::new().rotate());
+
+        let conf = ResolvConf::parse(&mut Cursor::new(
+            "options edns0 ndots:bogus timeout:4 no-such-option rotate\n"
+        )).unwrap();
+        assert_eq!(conf.ndots(), 1);
+        assert_eq!(conf.timeout(), Duration::from_secs(4));
+        assert!(conf.rotate());
+    }
+}
@@ -0,0 +1,241 @@
+//! The RPC program number database.
+//!
+//! This database maps the names of ONC RPC programs to program numbers.
+//! Currently, the database is only read from the `/etc/rpc` file.
+
+use std::{error, fmt, fs, io, result};
+use std::path::Path;
+use std::str::FromStr;
+
+
+//============ High-level API ================================================
+
+/// Returns RPC program information for a given program name.
+///
+/// The name can be the program’s official name or one of its aliases.
+/// Returns `Ok(None)` if there is no such program.
+pub fn get_rpc_by_name(name: &str) -> Result<Option<RpcEnt>, io::Error> {
+    Ok(RpcDb::system()?.lookup_name(name).cloned())
+}
+
+/// Returns RPC program information for a given program number.
+///
+/// Returns `Ok(None)` if there is no such program.
+pub fn get_rpc_by_number(number: u32) -> Result<Option<RpcEnt>, io::Error> {
+    Ok(RpcDb::system()?.lookup_number(number).cloned())
+}
+
+
+//------------ RpcEnt --------------------------------------------------------
+
+/// The result of an RPC program lookup.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RpcEnt {
+    name: String,
+    aliases: Vec<String>,
+    number: u32,
+}
+
+impl RpcEnt {
+    /// The official name of the program.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The aliases of the program.
+    pub fn aliases(&self) -> &[String] {
+        self.aliases.as_ref()
+    }
+
+    /// The program number.
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+}
+
+
+//------------ RpcDb ---------------------------------------------------------
+
+/// An RPC program table.
+///
+/// You can create an empty table using `RpcDb::new()`, create one by
+/// parsing an rpc file with `RpcDb::parse()` or `RpcDb::parse_file()`, or
+/// load the system’s table with `RpcDb::system()`. If several entries
+/// match a lookup, the first one added is returned.
+#[derive(Clone, Debug, Default)]
+pub struct RpcDb {
+    entries: Vec<RpcEnt>,
+}
+
+
+/// # Creation and Manipulation
+///
+impl RpcDb {
+    /// Creates a new, empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the RPC program table of this system.
+    ///
+    /// A missing rpc file results in an empty table.
+    ///
+    /// XXX This currently only works for Unix-y systems.
+    pub fn system() -> io::Result<Self> {
+        let mut res = RpcDb::new();
+        match res.parse_file("/etc/rpc") {
+            Ok(()) => Ok(res),
+            Err(Error::IoError(ref err))
+                if err.kind() == io::ErrorKind::NotFound => Ok(res),
+            Err(Error::IoError(err)) => Err(err),
+            Err(Error::ParseError) => Ok(res),
+        }
+    }
+
+    /// Adds an entry.
+    pub fn add(&mut self, ent: RpcEnt) {
+        self.entries.push(ent)
+    }
+}
+
+
+/// # Lookups
+///
+impl RpcDb {
+    /// Looks up a program by name or alias.
+    pub fn lookup_name(&self, name: &str) -> Option<&RpcEnt> {
+        self.entries.iter().find(|ent| {
+            ent.name == name || ent.aliases.iter().any(|alias| alias == name)
+        })
+    }
+
+    /// Looks up a program by number.
+    pub fn lookup_number(&self, number: u32) -> Option<&RpcEnt> {
+        self.entries.iter().find(|ent| ent.number == number)
+    }
+}
+
+
+/// # Parsing RPC File
+///
+impl RpcDb {
+    /// Adds the programs listed in a file.
+    pub fn parse_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.parse(&mut fs::File::open(path)?)
+    }
+
+    /// Reads programs from a reader and adds them.
+    ///
+    /// The format is that of the /etc/rpc file. Lines that fail to parse
+    /// are skipped.
+    pub fn parse<R: io::Read>(&mut self, reader: &mut R) -> Result<()> {
+        use std::io::BufRead;
+
+        for line in io::BufReader::new(reader).lines() {
+            if let Ok(Some(ent)) = parse_line(&line?) {
+                self.add(ent)
+            }
+        }
+        Ok(())
+    }
+}
+
+
+//------------ parse_line ---------------------------------------------------
+
+/// Parses a single line of an rpc file.
+///
+/// Returns `Ok(None)` if the line is empty or contains only a comment.
+/// Otherwise, the line consists of the program name, the program number
+/// in decimal, and optional aliases. A trailing carriage return and a
+/// UTF-8 byte order mark are ignored.
+pub fn parse_line(line: &str) -> Result<Option<RpcEnt>> {
+    let line: &str = match line.find('#') {
+        Some(pos) => line.split_at(pos).0,
+        None => line
+    };
+    let line = line.trim_matches(|ch: char| {
+        ch.is_whitespace() || ch == '\u{feff}'
+    });
+    if line.is_empty() { return Ok(None) }
+    let mut words = line.split_whitespace();
+
+    let name = words.next().ok_or(Error::ParseError)?;
+    let number = words.next().ok_or(Error::ParseError)?;
+    let number = u32::from_str(number).map_err(|_| Error::ParseError)?;
+
+    Ok(Some(RpcEnt {
+        name: name.into(),
+        aliases: words.map(Into::into).collect(),
+        number: number,
+    }))
+}
+
+
+//------------ Error and Result ----------------------------------------------
+
+/// An error happend during parsing an rpc file.
+#[derive(Debug)]
+pub enum Error {
+    /// The rpc file is kaputt.
+    ParseError,
+
+    /// Reading failed.
+    IoError(io::Error),
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::ParseError => "error parsing rpc programs",
+            Error::IoError(ref e) => e.description(),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::IoError(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use std::error::Error;
+
+        self.description().fmt(f)
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn parse_and_lookup() {
+        let mut db = RpcDb::new();
+        db.parse(&mut Cursor::new(
+            "# rpc file\n\
+             portmapper\t100000\tportmap sunrpc rpcbind\n\
+             nfs\t\t100003\tnfsprog # comment\n\
+             broken\t\tnfs\n\
+             \n\
+             mountd\t\t100005\tmount showmount\r\n"
+        )).unwrap();
+        let ent = db.lookup_name("rpcbind").unwrap();
+        assert_eq!(ent.name(), "portmapper");
+        assert_eq!(ent.number(), 100000);
+        assert_eq!(ent.aliases(), ["portmap", "sunrpc", "rpcbind"]);
+        assert_eq!(db.lookup_number(100003).unwrap().aliases(), ["nfsprog"]);
+        assert_eq!(db.lookup_number(100005).unwrap().aliases(),
+                   ["mount", "showmount"]);
+        assert!(db.lookup_name("broken").is_none());
+        assert!(db.lookup_number(100004).is_none());
+    }
+}
@@ -0,0 +1,426 @@
+//! The services database.
+//!
+//! This database maps the names of network services to port numbers and
+//! transport protocols. Currently, the database is only read from the
+//! `/etc/services` file.
+
+use std::{error, fmt, fs, io, result, slice, vec};
+use std::path::Path;
+use std::str::FromStr;
+
+
+//============ High-level API ================================================
+
+/// Returns service information for a given service name.
+///
+/// The name can be the service’s official name or one of its aliases. If
+/// `proto` is given, only services for this transport protocol, such as
+/// `"tcp"` or `"udp"`, are considered. Otherwise, the entry for the
+/// default protocol of the system’s table, `"tcp"`, is returned or, if
+/// there is none, the first entry for the service.
+///
+/// Returns `Ok(None)` if there is no such service.
+pub fn get_serv_by_name(name: &str, proto: Option<&str>)
+                        -> Result<Option<ServEnt>, io::Error> {
+    Ok(ServicesDb::system()?.lookup_name(name, proto).cloned())
+}
+
+/// Returns service information for a given port.
+///
+/// If `proto` is given, only services for this transport protocol are
+/// considered. Otherwise, the service for the default protocol of the
+/// system’s table, `"tcp"`, is returned or, if there is none, the first
+/// entry for the port.
+///
+/// Returns `Ok(None)` if there is no such service.
+pub fn get_serv_by_port(port: u16, proto: Option<&str>)
+                        -> Result<Option<ServEnt>, io::Error> {
+    Ok(ServicesDb::system()?.lookup_port(port, proto).cloned())
+}
+
+/// Returns all services for which `op` returns `true`.
+///
+/// The entries of the system’s table are returned in the order they
+/// appear in the services file. Services available for several protocols
+/// have an entry for each of them.
+pub fn filter<F>(op: F) -> Result<vec::IntoIter<ServEnt>, io::Error>
+              where F: FnMut(&ServEnt) -> bool {
+    Ok(filter_entries(ServicesDb::system()?, op))
+}
+
+/// Returns all services with a port between `lo` and `hi` inclusive.
+pub fn in_port_range(lo: u16, hi: u16)
+                     -> Result<vec::IntoIter<ServEnt>, io::Error> {
+    filter(|ent| lo <= ent.port && ent.port <= hi)
+}
+
+/// Returns all services for the transport protocol `proto`.
+pub fn for_proto(proto: &str) -> Result<vec::IntoIter<ServEnt>, io::Error> {
+    filter(|ent| ent.proto == proto)
+}
+
+/// Returns the entries of `db` for which `op` returns `true`.
+fn filter_entries<F>(db: ServicesDb, mut op: F) -> vec::IntoIter<ServEnt>
+                  where F: FnMut(&ServEnt) -> bool {
+    db.entries.into_iter().filter(|ent| op(ent)).collect::<Vec<_>>()
+      .into_iter()
+}
+
+
+//------------ ServEnt -------------------------------------------------------
+
+/// The result of a service lookup.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ServEnt {
+    name: String,
+    aliases: Vec<String>,
+    port: u16,
+    proto: String,
+}
+
+impl ServEnt {
+    /// The official name of the service.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The aliases of the service.
+    pub fn aliases(&self) -> &[String] {
+        self.aliases.as_ref()
+    }
+
+    /// The port number of the service.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// The transport protocol of the service.
+    pub fn proto(&self) -> &str {
+        &self.proto
+    }
+
+    /// Returns whether the service has the given name or alias.
+    fn has_name(&self, name: &str) -> bool {
+        self.name == name || self.aliases.iter().any(|alias| alias == name)
+    }
+
+    /// Returns whether the service is for `proto` if that is given.
+    fn has_proto(&self, proto: Option<&str>) -> bool {
+        match proto {
+            Some(proto) => self.proto == proto,
+            None => true
+        }
+    }
+}
+
+
+//------------ ServicesDb ----------------------------------------------------
+
+/// A services table.
+///
+/// You can create an empty table using `ServicesDb::new()`, create one by
+/// parsing a services file with `ServicesDb::parse()` or
+/// `ServicesDb::parse_file()`, or load the system’s table with
+/// `ServicesDb::system()`. Entries are kept in the order they were added.
+///
+/// Lookups that don’t ask for a specific transport protocol prefer entries
+/// for the table’s default protocol. This is `"tcp"` unless changed via
+/// `set_default_proto()`.
+#[derive(Clone, Debug)]
+pub struct ServicesDb {
+    entries: Vec<ServEnt>,
+
+    /// The protocol preferred by lookups without a protocol.
+    default_proto: String,
+}
+
+
+/// # Creation and Manipulation
+///
+impl ServicesDb {
+    /// Creates a new, empty table.
+    pub fn new() -> Self {
+        ServicesDb {
+            entries: Vec::new(),
+            default_proto: "tcp".into(),
+        }
+    }
+
+    /// Loads the services table of this system.
+    ///
+    /// A missing services file results in an empty table.
+    ///
+    /// XXX This currently only works for Unix-y systems.
+    pub fn system() -> io::Result<Self> {
+        let mut res = ServicesDb::new();
+        match res.parse_file("/etc/services") {
+            Ok(()) => Ok(res),
+            Err(Error::IoError(ref err))
+                if err.kind() == io::ErrorKind::NotFound => Ok(res),
+            Err(Error::IoError(err)) => Err(err),
+            Err(Error::ParseError) => Ok(res),
+        }
+    }
+
+    /// Adds an entry.
+    pub fn add(&mut self, ent: ServEnt) {
+        self.entries.push(ent)
+    }
+
+    /// Returns the protocol preferred by lookups without a protocol.
+    pub fn default_proto(&self) -> &str {
+        &self.default_proto
+    }
+
+    /// Sets the protocol preferred by lookups without a protocol.
+    ///
+    /// Applications that mostly use UDP can set this to `"udp"`. Lookups
+    /// that explicitly ask for a protocol are not affected.
+    pub fn set_default_proto(&mut self, proto: &str) {
+        self.default_proto = proto.into()
+    }
+}
+
+impl Default for ServicesDb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// # Lookups
+///
+impl ServicesDb {
+    /// Looks up a service by name or alias.
+    ///
+    /// If `proto` is given, only entries for this protocol are considered.
+    /// Otherwise, the entry for the default protocol is returned or, if
+    /// there is none, the first entry for the service.
+    pub fn lookup_name(&self, name: &str, proto: Option<&str>)
+                       -> Option<&ServEnt> {
+        self.lookup(proto, |ent| ent.has_name(name))
+    }
+
+    /// Looks up a service by port.
+    ///
+    /// The protocol is treated as with `lookup_name()`.
+    pub fn lookup_port(&self, port: u16, proto: Option<&str>)
+                       -> Option<&ServEnt> {
+        self.lookup(proto, |ent| ent.port == port)
+    }
+
+    /// Returns an iterator over all entries in the order they were added.
+    pub fn iter(&self) -> slice::Iter<ServEnt> {
+        self.entries.iter()
+    }
+
+    /// Returns the best entry for `proto` among those matching `op`.
+    fn lookup<F>(&self, proto: Option<&str>, op: F) -> Option<&ServEnt>
+              where F: Fn(&ServEnt) -> bool {
+        if proto.is_some() {
+            return self.entries.iter().find(|ent| {
+                op(ent) && ent.has_proto(proto)
+            })
+        }
+        let default = Some(self.default_proto.as_ref());
+        self.entries.iter().find(|ent| op(ent) && ent.has_proto(default))
+            .or_else(|| self.entries.iter().find(|ent| op(ent)))
+    }
+}
+
+
+/// # Parsing Services File
+///
+impl ServicesDb {
+    /// Adds the services listed in a file.
+    pub fn parse_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.parse(&mut fs::File::open(path)?)
+    }
+
+    /// Reads services from a reader and adds them.
+    ///
+    /// The format is that of the /etc/services file. Lines that fail to
+    /// parse are skipped.
+    pub fn parse<R: io::Read>(&mut self, reader: &mut R) -> Result<()> {
+        use std::io::BufRead;
+
+        for line in io::BufReader::new(reader).lines() {
+            if let Ok(Some(ent)) = parse_line(&line?) {
+                self.add(ent)
+            }
+        }
+        Ok(())
+    }
+}
+
+
+//------------ parse_line ---------------------------------------------------
+
+/// Parses a single line of a services file.
+///
+/// Returns `Ok(None)` if the line is empty or contains only a comment.
+/// Otherwise, the line consists of the service name, the port and protocol
+/// separated by a slash, and optional aliases. A trailing carriage return
+/// and a UTF-8 byte order mark are ignored.
+pub fn parse_line(line: &str) -> Result<Option<ServEnt>> {
+    let line: &str = match line.find('#') {
+        Some(pos) => line.split_at(pos).0,
+        None => line
+    };
+    let line = line.trim_matches(|ch: char| {
+        ch.is_whitespace() || ch == '\u{feff}'
+    });
+    if line.is_empty() { return Ok(None) }
+    let mut words = line.split_whitespace();
+
+    let name = words.next().ok_or(Error::ParseError)?;
+    let mut port = words.next().ok_or(Error::ParseError)?.splitn(2, '/');
+    let number = port.next().ok_or(Error::ParseError)?;
+    let number = u16::from_str(number).map_err(|_| Error::ParseError)?;
+    let proto = port.next().ok_or(Error::ParseError)?;
+
+    Ok(Some(ServEnt {
+        name: name.into(),
+        aliases: words.map(Into::into).collect(),
+        port: number,
+        proto: proto.into(),
+    }))
+}
+
+
+//------------ Error and Result ----------------------------------------------
+
+/// An error happend during parsing a services file.
+#[derive(Debug)]
+pub enum Error {
+    /// The services file is kaputt.
+    ParseError,
+
+    /// Reading failed.
+    IoError(io::Error),
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::ParseError => "error parsing services",
+            Error::IoError(ref e) => e.description(),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::IoError(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use std::error::Error;
+
+        self.description().fmt(f)
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn parse_and_lookup() {
+        let mut db = ServicesDb::new();
+        db.parse(&mut Cursor::new(
+            "# /etc/services\n\
+             ssh\t\t22/tcp\t\t\t# SSH Remote Login Protocol\n\
+             domain\t\t53/tcp\n\
+             domain\t\t53/udp\n\
+             http\t\t80/tcp\t\twww\n\
+             broken\t\tnope/tcp\n"
+        )).unwrap();
+        assert_eq!(db.lookup_name("ssh", None).unwrap().port(), 22);
+        assert_eq!(db.lookup_name("www", Some("tcp")).unwrap().name(),
+                   "http");
+        assert_eq!(db.lookup_name("domain", Some("udp")).unwrap().proto(),
+                   "udp");
+        assert_eq!(db.lookup_name("domain", None).unwrap().proto(), "tcp");
+        assert!(db.lookup_name("http", Some("udp")).is_none());
+        assert!(db.lookup_name("broken", None).is_none());
+        assert_eq!(db.lookup_port(53, Some("udp")).unwrap().name(),
+                   "domain");
+    }
+
+    #[test]
+    fn file_quirks() {
+        let mut db = ServicesDb::new();
+        db.parse(&mut Cursor::new(
+            "\u{feff}ssh\t\t22/tcp\r\n\
+             domain\t\t53/udp\r\n\
+             http\t\t80/tcp\t\twww"
+        )).unwrap();
+        assert_eq!(db.lookup_name("ssh", None).unwrap().port(), 22);
+        assert_eq!(db.lookup_name("domain", None).unwrap().proto(), "udp");
+        assert_eq!(db.lookup_name("www", None).unwrap().aliases(),
+                   ["www"]);
+
+        let mut db = ServicesDb::new();
+        db.parse(&mut Cursor::new("ntp\t\t123/udp\r")).unwrap();
+        assert_eq!(db.lookup_port(123, Some("udp")).unwrap().name(), "ntp");
+    }
+
+    #[test]
+    fn default_proto() {
+        let mut db = ServicesDb::new();
+        db.parse(&mut Cursor::new(
+            "syslog		514/udp
+             domain		53/udp
+             domain		53/tcp
+             shell		514/tcp
+"
+        )).unwrap();
+        assert_eq!(db.default_proto(), "tcp");
+        assert_eq!(db.lookup_name("domain", None).unwrap().proto(), "tcp");
+        assert_eq!(db.lookup_name("syslog", None).unwrap().proto(), "udp");
+        assert_eq!(db.lookup_port(514, None).unwrap().name(), "shell");
+        assert_eq!(db.lookup_name("domain", Some("udp")).unwrap().proto(),
+                   "udp");
+
+        db.set_default_proto("udp");
+        assert_eq!(db.lookup_name("domain", None).unwrap().proto(), "udp");
+        assert_eq!(db.lookup_port(514, None).unwrap().name(), "syslog");
+        assert_eq!(db.lookup_name("domain", Some("tcp")).unwrap().proto(),
+                   "tcp");
+        assert!(db.lookup_name("shell", Some("udp")).is_none());
+        assert_eq!(db.lookup_name("shell", None).unwrap().proto(), "tcp");
+    }
+
+    #[test]
+    fn filter() {
+        let mut db = ServicesDb::new();
+        db.parse(&mut Cursor::new(
+            "ssh\t\t22/tcp\n\
+             domain\t\t53/tcp\n\
+             domain\t\t53/udp\n\
+             http\t\t80/tcp\t\twww\n\
+             postgresql\t5432/tcp\n"
+        )).unwrap();
+        assert_eq!(db.iter().count(), 5);
+        let names = |iter: vec::IntoIter<ServEnt>| {
+            iter.map(|ent| format!("{}/{}", ent.name(), ent.proto()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(filter_entries(db.clone(), |ent| ent.port() < 1024)),
+                   ["ssh/tcp", "domain/tcp", "domain/udp", "http/tcp"]);
+        assert_eq!(names(filter_entries(db.clone(),
+                                        |ent| ent.proto() == "udp")),
+                   ["domain/udp"]);
+        assert_eq!(names(filter_entries(db, |ent| ent.port() > 6000)),
+                   Vec::<String>::new());
+    }
+}
@@ -0,0 +1,82 @@
+//! Optional instrumentation of lookups.
+//!
+//! If the `tracing` feature is enabled, lookups are wrapped in a span named
+//! `netdb.resolve` carrying the queried name, the address family, the
+//! source tried last, and the outcome. Each source tried is also reported
+//! as an event within the span. Lines of configuration files skipped for
+//! reasons other than failing to parse are reported as warnings.
+//!
+//! Without the feature, `Span` is an empty type and all its methods do
+//! nothing, so the instrumentation compiles away entirely.
+
+use std::fmt;
+#[cfg(feature = "tracing")] use tracing;
+
+
+//------------ Span ----------------------------------------------------------
+
+/// The span of a single lookup.
+#[derive(Clone, Debug)]
+pub struct Span {
+    #[cfg(feature = "tracing")]
+    inner: tracing::Span,
+}
+
+#[cfg(feature = "tracing")]
+impl Span {
+    /// Creates the span for resolving `name` for the given `family`.
+    pub fn resolve<N: fmt::Display>(name: N, family: &str) -> Self {
+        Span {
+            inner: info_span!(
+                "netdb.resolve",
+                name = %name,
+                family = family,
+                source = tracing::field::Empty,
+                outcome = tracing::field::Empty,
+            )
+        }
+    }
+
+    /// Records that the lookup is trying `source`.
+    pub fn source<S: fmt::Display>(&self, source: S) {
+        self.inner.record("source", &tracing::field::display(&source));
+        debug!(parent: &self.inner, source = %source, "trying source");
+    }
+
+    /// Records the outcome of the lookup.
+    pub fn outcome(&self, outcome: &str) {
+        self.inner.record("outcome", &outcome);
+        debug!(parent: &self.inner, outcome = outcome, "lookup finished");
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    /// Creates the span for resolving `name` for the given `family`.
+    #[inline]
+    pub fn resolve<N: fmt::Display>(_name: N, _family: &str) -> Self {
+        Span { }
+    }
+
+    /// Records that the lookup is trying `source`.
+    #[inline]
+    pub fn source<S: fmt::Display>(&self, _source: S) { }
+
+    /// Records the outcome of the lookup.
+    #[inline]
+    pub fn outcome(&self, _outcome: &str) { }
+}
+
+
+//------------ skipped_line --------------------------------------------------
+
+/// Reports that line number `line` of a file was skipped for `reason`.
+#[cfg(feature = "tracing")]
+pub fn skipped_line(line: usize, reason: &str) {
+    warn!(line = line, reason = reason, "skipping line");
+}
+
+/// Reports that line number `line` of a file was skipped for `reason`.
+#[cfg(not(feature = "tracing"))]
+#[inline]
+pub fn skipped_line(_line: usize, _reason: &str) { }